@@ -6,10 +6,16 @@ defmodule Mix.Tasks.Projection.Codegen do
   @moduledoc """
   Generates Rust binding glue under `slint/ui_host/src/generated/` from
   `__projection_schema__/0` metadata exported by screen modules.
+
+  Window modules (`use ProjectionUI, :window`) each get a `<Name>WindowHost`
+  Slint window wrapping the app-owned `<Name>Window` component from
+  `windows/<kind>.slint` under the UI root, a state global, and an `apply`
+  function that `new_window/1` in `mod.rs` wires into the runtime.
   """
 
   @supported_schema_types [:string, :bool, :integer, :float, :map, :list, :id_table, :component]
   @supported_codegen_types [:string, :bool, :integer, :float, :list, :id_table, :component]
+  @supported_window_codegen_types [:string, :bool, :integer, :float, :list]
   @required_ui_shell_files ~w(app_shell.slint error.slint screen.slint ui.slint)
 
   @impl Mix.Task
@@ -28,6 +34,11 @@ defmodule Mix.Tasks.Projection.Codegen do
       |> Enum.map(&build_screen_spec/1)
       |> Enum.sort_by(& &1.module_name)
 
+    window_specs =
+      discover_window_modules()
+      |> Enum.map(&build_window_spec/1)
+      |> Enum.sort_by(& &1.kind)
+
     ensure_codegen_targets!(specs, routes)
     ensure_required_ui_shell_files!(specs, routes, ui_root)
 
@@ -60,10 +71,28 @@ defmodule Mix.Tasks.Projection.Codegen do
       )
       |> Enum.map(&unwrap_task_result!/1)
 
+    window_results =
+      Enum.flat_map(window_specs, fn spec ->
+        [
+          write_file_if_changed(
+            Path.join(generated_dir, "#{spec.file_name}.rs"),
+            render_window_module(spec)
+          ),
+          write_file_if_changed(
+            Path.join(generated_dir, spec.state_file),
+            render_screen_state_slint(spec)
+          ),
+          write_file_if_changed(
+            Path.join(generated_dir, spec.host_file),
+            render_window_host_slint(spec, ui_root_from_generated)
+          )
+        ]
+      end)
+
     mod_result =
       write_file_if_changed(
         Path.join(generated_dir, "mod.rs"),
-        render_generated_mod(specs, routes)
+        render_generated_mod(specs, routes, window_specs)
       )
 
     routes_result =
@@ -78,7 +107,7 @@ defmodule Mix.Tasks.Projection.Codegen do
     app_result =
       write_file_if_changed(
         Path.join(generated_dir, "app.slint"),
-        render_generated_app_slint(specs, routes, ui_root_from_generated)
+        render_generated_app_slint(specs, routes, window_specs, ui_root_from_generated)
       )
 
     error_state_result =
@@ -90,15 +119,16 @@ defmodule Mix.Tasks.Projection.Codegen do
     build_rs_result =
       write_file_if_changed(
         Path.join(File.cwd!(), "slint/ui_host/build.rs"),
-        render_build_rs(specs, ui_root_from_ui_host)
+        render_build_rs(specs, window_specs, ui_root_from_ui_host)
       )
 
-    removed_count = prune_stale_generated_files(generated_dir, specs)
+    removed_count = prune_stale_generated_files(generated_dir, specs, window_specs)
 
     written_count =
       Enum.count(
         screen_results ++
           screen_state_results ++
+          window_results ++
           [
             mod_result,
             routes_result,
@@ -114,7 +144,7 @@ defmodule Mix.Tasks.Projection.Codegen do
       )
 
     Mix.shell().info(
-      "projection.codegen generated #{length(specs)} screen module(s), #{length(routes)} route constant(s), wrote #{written_count} file(s), removed #{removed_count} stale file(s)"
+      "projection.codegen generated #{length(specs)} screen module(s), #{length(window_specs)} window module(s), #{length(routes)} route constant(s), wrote #{written_count} file(s), removed #{removed_count} stale file(s)"
     )
   end
 
@@ -232,6 +262,24 @@ defmodule Mix.Tasks.Projection.Codegen do
     |> Enum.sort_by(&Atom.to_string/1)
   end
 
+  defp discover_window_modules do
+    marker_modules =
+      configured_otp_apps()
+      |> Enum.flat_map(fn app ->
+        app
+        |> Application.spec(:modules)
+        |> List.wrap()
+      end)
+      |> Enum.filter(&Code.ensure_loaded?/1)
+      |> Enum.filter(&function_exported?(&1, :__projection_window__, 0))
+
+    (configured_window_modules() ++ marker_modules)
+    |> Enum.uniq()
+    |> Enum.filter(&Code.ensure_loaded?/1)
+    |> Enum.filter(&function_exported?(&1, :__projection_schema__, 0))
+    |> Enum.sort_by(&Atom.to_string/1)
+  end
+
   defp ensure_codegen_targets!([], routes) when is_list(routes) do
     if allow_empty_codegen?() do
       :ok
@@ -343,6 +391,18 @@ defmodule Mix.Tasks.Projection.Codegen do
     end
   end
 
+  defp configured_window_modules do
+    case Application.get_env(:projection, :window_modules, []) do
+      modules when is_list(modules) ->
+        modules
+        |> Enum.filter(&(is_atom(&1) and not is_nil(&1)))
+        |> Enum.filter(&Code.ensure_loaded?/1)
+
+      _other ->
+        []
+    end
+  end
+
   defp configured_otp_apps do
     case Application.get_env(:projection, :otp_apps) do
       apps when is_list(apps) and apps != [] ->
@@ -410,6 +470,46 @@ defmodule Mix.Tasks.Projection.Codegen do
     }
   end
 
+  defp build_window_spec(module) do
+    kind = module |> Module.split() |> List.last() |> Macro.underscore()
+
+    fields =
+      module.__projection_schema__()
+      |> Enum.map(&normalize_field!/1)
+      |> Enum.sort_by(&Atom.to_string(&1.name))
+
+    unsupported_fields =
+      fields
+      |> Enum.reject(&(&1.type in @supported_window_codegen_types))
+
+    if unsupported_fields != [] do
+      field_descriptions =
+        unsupported_fields
+        |> Enum.map(fn field -> "#{field.name}: #{inspect(field.type)}" end)
+        |> Enum.join(", ")
+
+      raise ArgumentError,
+            "projection.codegen does not support these schema field types in window " <>
+              "#{inspect(module)}: #{field_descriptions}. Use typed scalars or :list."
+    end
+
+    %{
+      module: module,
+      module_name: Atom.to_string(module),
+      kind: kind,
+      component_name: module |> Module.split() |> List.last() |> Kernel.<>("Window"),
+      host_name: camelize(kind) <> "WindowHost",
+      file_name: "#{kind}_window",
+      fields:
+        Enum.map(fields, fn field ->
+          Map.put(field, :source, %{kind: :direct, root: field.name, vm_path: "vm"})
+        end),
+      global_name: camelize(kind) <> "WindowState",
+      state_file: "#{kind}_window_state.slint",
+      host_file: "#{kind}_window.slint"
+    }
+  end
+
   defp raise_codegen_unsupported_fields!(module, fields) do
     field_descriptions =
       fields
@@ -594,7 +694,7 @@ defmodule Mix.Tasks.Projection.Codegen do
     System.schedulers_online()
   end
 
-  defp prune_stale_generated_files(generated_dir, specs) do
+  defp prune_stale_generated_files(generated_dir, specs, window_specs) do
     keep =
       MapSet.new(
         [
@@ -605,7 +705,8 @@ defmodule Mix.Tasks.Projection.Codegen do
           "error_state.slint"
         ] ++
           Enum.map(specs, &"#{&1.file_name}.rs") ++
-          Enum.map(specs, & &1.state_file)
+          Enum.map(specs, & &1.state_file) ++
+          Enum.flat_map(window_specs, &["#{&1.file_name}.rs", &1.state_file, &1.host_file])
       )
 
     generated_dir
@@ -628,12 +729,12 @@ defmodule Mix.Tasks.Projection.Codegen do
     end)
   end
 
-  defp render_generated_mod([], _routes) do
+  defp render_generated_mod([], _routes, window_specs) do
     """
     use crate::AppWindow;
-    use projection_ui_host_runtime::PatchOp;
+    #{runtime_mod_use_line(window_specs)}
     use serde_json::Value;
-
+    #{empty_mod_window_module_lines(window_specs)}
     #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
     pub enum ScreenId {
         #[default]
@@ -652,16 +753,19 @@ defmodule Mix.Tasks.Projection.Codegen do
     ) -> Result<(), String> {
         Ok(())
     }
+
+    #{render_new_window(window_specs)}
     """
   end
 
-  defp render_generated_mod(specs, routes) do
+  defp render_generated_mod(specs, routes, window_specs) do
     [first | rest] = specs
 
     module_lines =
       specs
       |> Enum.map(fn spec -> "pub mod #{spec.file_name};" end)
       |> Enum.map(fn line -> "#[rustfmt::skip]\n#{line}" end)
+      |> Kernel.++(window_module_lines(window_specs))
       |> Enum.join("\n")
 
     enum_variants =
@@ -693,7 +797,7 @@ defmodule Mix.Tasks.Projection.Codegen do
 
     """
     use crate::AppWindow;
-    use projection_ui_host_runtime::PatchOp;
+    #{runtime_mod_use_line(window_specs)}
     use serde_json::Value;
 
     #{module_lines}
@@ -727,6 +831,58 @@ defmodule Mix.Tasks.Projection.Codegen do
     #{patch_dispatch_arms}
         }
     }
+
+    #{render_new_window(window_specs)}
+    """
+  end
+
+  defp runtime_mod_use_line([]) do
+    "use projection_ui_host_runtime::{PatchOp, ProjectionWindow};"
+  end
+
+  defp runtime_mod_use_line(_window_specs) do
+    "use projection_ui_host_runtime::{ComponentWindow, PatchOp, ProjectionWindow};"
+  end
+
+  defp window_module_lines(window_specs) do
+    Enum.map(window_specs, fn spec -> "#[rustfmt::skip]\npub mod #{spec.file_name};" end)
+  end
+
+  defp empty_mod_window_module_lines([]), do: ""
+
+  defp empty_mod_window_module_lines(window_specs) do
+    "\n" <> Enum.join(window_module_lines(window_specs), "\n") <> "\n"
+  end
+
+  defp render_new_window([]) do
+    """
+    pub fn new_window(kind: &str) -> Result<Box<dyn ProjectionWindow>, String> {
+        Err(format!("no window component for kind '{kind}'"))
+    }
+    """
+  end
+
+  defp render_new_window(window_specs) do
+    window_arms =
+      window_specs
+      |> Enum.map_join("", fn spec ->
+        """
+                "#{spec.kind}" => {
+                    let window = crate::#{spec.host_name}::new().map_err(|err| err.to_string())?;
+                    Ok(Box::new(
+                        ComponentWindow::new(window, #{spec.file_name}::apply)
+                            .with_intents(#{spec.file_name}::bind_intents),
+                    ))
+                }
+        """
+      end)
+
+    """
+    pub fn new_window(kind: &str) -> Result<Box<dyn ProjectionWindow>, String> {
+        match kind {
+    #{window_arms}        _ => Err(format!("no window component for kind '{kind}'")),
+        }
+    }
     """
   end
 
@@ -932,33 +1088,29 @@ defmodule Mix.Tasks.Projection.Codegen do
           ""
         end
 
+      list_fields = Enum.filter(direct_fields ++ component_fields, &(&1.type == :list))
+
       patch_screen_vm_line =
-        if id_table_roots == [] and component_id_table_roots == [] do
+        if id_table_roots == [] and component_id_table_roots == [] and list_fields == [] do
           ""
         else
           "    let screen_vm = vm.pointer(\"/screen/vm\").and_then(Value::as_object);\n"
         end
 
-      patch_vm_param =
-        if id_table_roots == [] and component_id_table_roots == [] do
-          "_vm"
-        else
-          "vm"
-        end
-
       needs_value =
-        direct_fields != [] or component_fields != [] or component_root_groups != []
+        Enum.any?(direct_fields ++ component_fields, &(&1.type != :list)) or
+          component_root_groups != []
 
-      replace_add_pattern =
+      written_pattern =
         if needs_value do
-          "PatchOp::Replace { path, value } | PatchOp::Add { path, value }"
+          "Some((path, value))"
         else
-          "PatchOp::Replace { path, value: _ } | PatchOp::Add { path, value: _ }"
+          "Some((path, _value))"
         end
 
       """
       use crate::AppWindow;
-      use projection_ui_host_runtime::PatchOp;
+      use projection_ui_host_runtime::{PatchOp, Pointer, Tokens};
       use slint::ComponentHandle;
       use serde_json::Value;
 
@@ -970,19 +1122,17 @@ defmodule Mix.Tasks.Projection.Codegen do
           Ok(())
       }
 
-      pub fn apply_patch(ui: &AppWindow, ops: &[PatchOp], #{patch_vm_param}: &Value) -> Result<(), String> {
+      pub fn apply_patch(ui: &AppWindow, ops: &[PatchOp], vm: &Value) -> Result<(), String> {
       #{patch_screen_vm_line}
           let g = ui.global::<#{global_type}>();
           for op in ops {
-              match op {
-                  #{replace_add_pattern} => {
-                      let field_path = path.strip_prefix("/screen/vm").unwrap_or(path);
-      #{patch_apply_lines}
-                  }
-                  PatchOp::Remove { path } => {
-                      let field_path = path.strip_prefix("/screen/vm").unwrap_or(path);
+              if let Some(path) = op.removed_path() {
+                  let field_path = screen_field_path(path);
       #{remove_apply_lines}
-                  }
+              }
+              if let #{written_pattern} = op.written_value(vm) {
+                  let field_path = screen_field_path(path);
+      #{patch_apply_lines}
               }
           }
 
@@ -992,6 +1142,12 @@ defmodule Mix.Tasks.Projection.Codegen do
 
       #{field_helpers}
 
+      fn screen_field_path(path: &Pointer) -> Tokens<'_> {
+          path.tokens()
+              .strip_prefix(&["screen", "vm"])
+              .unwrap_or_else(|| path.tokens())
+      }
+
       fn bump_vm_rev(ui: &AppWindow) {
           let next = ui.get_vm_rev().wrapping_add(1);
           ui.set_vm_rev(next);
@@ -1026,17 +1182,97 @@ defmodule Mix.Tasks.Projection.Codegen do
     """
   end
 
+  # Windows are applied whole: the runtime pushes the full declaration
+  # whenever it changes, so there is no patch dispatch here.
+  defp render_window_module(spec) do
+    global_type = "crate::#{spec.global_name}"
+
+    {value_use_line, field_setters} =
+      if spec.fields == [] do
+        {"", ""}
+      else
+        setters =
+          spec.fields
+          |> Enum.map_join("\n", fn field ->
+            "    set_#{field.name}_from_vm(&g, window_vm)?;"
+          end)
+
+        field_setters = """
+            let window_vm = declaration.vm.as_object();
+            let g = window.global::<#{global_type}>();
+        #{setters}
+        """
+
+        {"use serde_json::Value;\n", field_setters}
+      end
+
+    field_helpers =
+      spec.fields
+      |> Enum.map_join("\n", &render_field_helper(&1, global_type))
+
+    parse_helpers =
+      spec.fields
+      |> Enum.map(&parse_helper_key/1)
+      |> Enum.uniq()
+      |> Enum.sort_by(&parse_helper_sort_key/1)
+      |> Enum.map_join("\n", &render_parse_helper/1)
+
+    """
+    use crate::{#{spec.host_name}, UI};
+    use projection_ui_host_runtime::{WindowDeclaration, WindowIntentHandler};
+    use slint::ComponentHandle;
+    #{value_use_line}
+    pub fn apply(window: &#{spec.host_name}, declaration: &WindowDeclaration) -> Result<(), String> {
+        window.set_window_title(declaration.title.as_str().into());
+    #{field_setters}    Ok(())
+    }
+
+    pub fn bind_intents(window: &#{spec.host_name}, handler: WindowIntentHandler) {
+        window.global::<UI>().on_intent(move |intent_name, intent_arg| {
+            handler(intent_name.to_string(), intent_arg.to_string());
+        });
+    }
+    #{field_helpers}
+    #{parse_helpers}
+    """
+  end
+
+  defp render_window_host_slint(spec, ui_root_from_generated) do
+    field_bindings =
+      spec.fields
+      |> Enum.map_join("\n", fn field ->
+        "        #{field.name}: #{spec.global_name}.#{field.name};"
+      end)
+
+    """
+    // generated by mix projection.codegen; do not edit manually
+    import { #{spec.component_name} } from "#{ui_root_from_generated}/windows/#{spec.kind}.slint";
+    import { #{spec.global_name} } from "#{spec.state_file}";
+
+    export component #{spec.host_name} inherits Window {
+        in property <string> window_title: "";
+
+        title: root.window_title;
+
+        #{spec.component_name} {
+    #{field_bindings}
+        }
+    }
+    """
+  end
+
   defp render_field_helper(
          %{
            name: name,
            type: type,
            default: default,
            opts: opts,
-           source: %{kind: :direct}
+           source: %{kind: :direct} = source
          },
          global_name
        ) do
     field = Atom.to_string(name)
+    vm_path = Map.get(source, :vm_path, "/screen/vm")
     default_literal = rust_literal(type, default, opts)
     set_value_expr = rust_set_value_expr(name, type, opts, "g")
 
@@ -1046,7 +1282,7 @@ defmodule Mix.Tasks.Projection.Codegen do
         screen_vm: Option<&serde_json::Map<String, Value>>,
     ) -> Result<(), String> {
         if let Some(value) = screen_vm.and_then(|root| root.get("#{field}")) {
-            return set_#{field}_from_value(g, "/screen/vm/#{field}", value);
+            return set_#{field}_from_value(g, "#{vm_path}/#{field}", value);
         }
 
         set_#{field}_default(g);
@@ -1164,17 +1400,21 @@ defmodule Mix.Tasks.Projection.Codegen do
     """
   end
 
+  defp render_patch_apply_line(%{type: :list} = field), do: render_list_patch_apply_line(field)
+
   defp render_patch_apply_line(field) do
     condition = rust_patch_match_condition(field)
     target = Atom.to_string(field.name)
 
     """
                       if #{condition} {
-                          set_#{target}_from_value(&g, path, value)?;
+                          set_#{target}_from_value(&g, path.as_str(), value)?;
                       }
     """
   end
 
+  defp render_remove_apply_line(%{type: :list} = field), do: render_list_patch_apply_line(field)
+
   defp render_remove_apply_line(field) do
     condition = rust_patch_match_condition(field)
     target = Atom.to_string(field.name)
@@ -1186,8 +1426,34 @@ defmodule Mix.Tasks.Projection.Codegen do
     """
   end
 
+  # Element-level ops (`add /items/1`, `move`, `copy`, `remove /items/0`)
+  # land below the field, so the whole list is set again from the patched VM.
+  defp render_list_patch_apply_line(%{name: name, source: %{kind: :direct, root: root}}) do
+    """
+                      if field_path.starts_with(&#{rust_tokens([root])}) {
+                          set_#{name}_from_vm(&g, screen_vm)?;
+                      }
+    """
+  end
+
+  defp render_list_patch_apply_line(%{
+         name: name,
+         source: %{kind: :component, component: component, field: field}
+       }) do
+    component_vm = component_vm_var(component)
+
+    """
+                      if field_path.starts_with(&#{rust_tokens([component, field])}) {
+                          let #{component_vm} =
+                              screen_vm
+                                  .and_then(|root| root.get("#{component}"))
+                                  .and_then(Value::as_object);
+                          set_#{name}_from_component(&g, #{component_vm})?;
+                      }
+    """
+  end
+
   defp render_component_root_patch_apply_line(component, direct_group, id_table_group) do
-    component_name = Atom.to_string(component)
     component_vm = component_vm_var(component)
 
     direct_setters =
@@ -1208,7 +1474,7 @@ defmodule Mix.Tasks.Projection.Codegen do
       |> Enum.join("\n")
 
     """
-                      if field_path == "/#{component_name}" {
+                      if field_path == #{rust_tokens([component])} {
                           let #{component_vm} = value.as_object();
     #{setters}
                       }
@@ -1216,8 +1482,6 @@ defmodule Mix.Tasks.Projection.Codegen do
   end
 
   defp render_component_root_remove_apply_line(component, direct_group, id_table_group) do
-    component_name = Atom.to_string(component)
-
     direct_defaults =
       direct_group
       |> Enum.map_join("\n", fn field ->
@@ -1236,7 +1500,7 @@ defmodule Mix.Tasks.Projection.Codegen do
       |> Enum.join("\n")
 
     """
-                      if field_path == "/#{component_name}" {
+                      if field_path == #{rust_tokens([component])} {
     #{defaults}
                       }
     """
@@ -1299,33 +1563,27 @@ defmodule Mix.Tasks.Projection.Codegen do
   end
 
   defp rust_patch_match_condition(%{source: %{kind: :direct, root: root}}) do
-    root_name = Atom.to_string(root)
-    top_path = "/" <> root_name
-    ~s(field_path == "#{top_path}")
+    ~s(field_path == #{rust_tokens([root])})
   end
 
   defp rust_patch_match_condition(%{
          source: %{kind: :component, component: component, field: field}
        }) do
-    component_name = Atom.to_string(component)
-    field_name = Atom.to_string(field)
-    ~s(field_path == "/#{component_name}/#{field_name}")
+    ~s(field_path == #{rust_tokens([component, field])})
   end
 
   defp rust_id_table_root_patch_match_condition(root) when is_atom(root) do
-    root_name = Atom.to_string(root)
-    top_path = "/" <> root_name
-
-    "field_path == \"#{top_path}\" || field_path.starts_with(\"#{top_path}/\")"
+    "field_path.starts_with(&#{rust_tokens([root])})"
   end
 
   defp rust_component_id_table_patch_match_condition(component, root)
        when is_atom(component) and is_atom(root) do
-    component_name = Atom.to_string(component)
-    root_name = Atom.to_string(root)
-    top_path = "/#{component_name}/#{root_name}"
+    "field_path.starts_with(&#{rust_tokens([component, root])})"
+  end
 
-    "field_path == \"#{top_path}\" || field_path.starts_with(\"#{top_path}/\")"
+  # Pointer tokens as a Rust array literal, matched against `Tokens`.
+  defp rust_tokens(names) do
+    "[" <> Enum.map_join(names, ", ", &~s("#{&1}")) <> "]"
   end
 
   defp rust_id_table_extract_expr(:ids), do: "let values = parsed.ids.clone();"
@@ -1745,11 +2003,12 @@ defmodule Mix.Tasks.Projection.Codegen do
     """
   end
 
-  defp render_build_rs(specs, ui_root_from_ui_host) do
+  defp render_build_rs(specs, window_specs, ui_root_from_ui_host) do
     state_rerun_lines =
-      specs
-      |> Enum.map(fn spec ->
-        "    println!(\"cargo:rerun-if-changed=src/generated/#{spec.state_file}\");"
+      (Enum.map(specs, & &1.state_file) ++
+         Enum.flat_map(window_specs, &[&1.state_file, &1.host_file]))
+      |> Enum.map(fn file ->
+        "    println!(\"cargo:rerun-if-changed=src/generated/#{file}\");"
       end)
       |> Enum.sort()
 
@@ -1975,14 +2234,19 @@ defmodule Mix.Tasks.Projection.Codegen do
   defp slint_literal(:float, value, _opts), do: format_float(value)
   defp slint_literal(:list, value, opts), do: slint_list_literal(value, opts)
 
-  defp render_generated_app_slint(specs, routes, ui_root_from_generated) do
+  defp render_generated_app_slint(specs, routes, window_specs, ui_root_from_generated) do
     active_screen_default = default_active_screen(routes)
 
     state_export_lines =
-      specs
+      (specs ++ window_specs)
       |> Enum.map(fn spec ->
         "export { #{spec.global_name} } from \"#{spec.state_file}\";"
       end)
+      |> Kernel.++(
+        Enum.map(window_specs, fn spec ->
+          "export { #{spec.host_name} } from \"#{spec.host_file}\";"
+        end)
+      )
       |> Enum.sort()
       |> Enum.join("\n")
 
@@ -1999,6 +2263,8 @@ defmodule Mix.Tasks.Projection.Codegen do
         in property <string> app_title: "Projection";
         in property <string> active_screen: "#{escape_slint_string(active_screen_default)}";
         in property <bool> nav_can_back: false;
+        in property <bool> connection_stale: false;
+        in property <bool> modal_open: false;
 
         callback ui_intent(intent_name: string, intent_arg: string);
         callback navigate(route_name: string, params_json: string);
@@ -2016,6 +2282,41 @@ defmodule Mix.Tasks.Projection.Codegen do
             }
         }
 
+        if root.connection_stale: Rectangle {
+            x: 0;
+            y: 0;
+            width: parent.width;
+            height: parent.height;
+            background: #1a1a2ec0;
+
+            Text {
+                text: "Connection stale: showing last received data";
+                color: #f5f5f5;
+                font-size: 14px;
+                horizontal-alignment: center;
+                vertical-alignment: center;
+            }
+        }
+
+        // Swallows pointer and key input while a modal window is open.
+        if root.modal_open: Rectangle {
+            x: 0;
+            y: 0;
+            width: parent.width;
+            height: parent.height;
+            background: #1a1a2e80;
+
+            TouchArea {
+                scroll-event(event) => { accept }
+            }
+
+            FocusScope {
+                init => { self.focus(); }
+                key-pressed(event) => { accept }
+                key-released(event) => { accept }
+            }
+        }
+
         width: shell.window_width;
         height: shell.window_height;
         background: #1a1a2e;
@@ -0,0 +1,150 @@
+defmodule Projection.MsgPack do
+  @moduledoc """
+  MessagePack encoding for protocol envelopes, the binary alternative to JSON
+  a host can list under `capabilities.codecs`.
+
+  Covers the JSON data model only: maps, lists, strings, integers, floats,
+  booleans and `nil`. Atoms other than `true`, `false` and `nil` encode as
+  strings and map keys are stringified, as `Jason` does; structs are not
+  supported. Decoded strings and binaries both come back as Elixir binaries.
+  """
+
+  @doc "Encodes a JSON-compatible term as MessagePack."
+  @spec encode(term()) :: {:ok, binary()} | {:error, :encode_error}
+  def encode(term) do
+    {:ok, term |> do_encode() |> IO.iodata_to_binary()}
+  catch
+    :throw, :unsupported -> {:error, :encode_error}
+  end
+
+  @doc "Decodes one MessagePack value that spans the whole binary."
+  @spec decode(binary()) :: {:ok, term()} | {:error, :decode_error}
+  def decode(binary) when is_binary(binary) do
+    case do_decode(binary) do
+      {value, <<>>} -> {:ok, value}
+      {_value, _trailing} -> {:error, :decode_error}
+    end
+  catch
+    :throw, :invalid -> {:error, :decode_error}
+  end
+
+  defp do_encode(nil), do: <<0xC0>>
+  defp do_encode(false), do: <<0xC2>>
+  defp do_encode(true), do: <<0xC3>>
+  defp do_encode(atom) when is_atom(atom), do: atom |> Atom.to_string() |> do_encode()
+
+  defp do_encode(int) when is_integer(int) and int >= 0 do
+    cond do
+      int < 0x80 -> <<int>>
+      int <= 0xFF -> <<0xCC, int>>
+      int <= 0xFFFF -> <<0xCD, int::16>>
+      int <= 0xFFFFFFFF -> <<0xCE, int::32>>
+      int <= 0xFFFFFFFFFFFFFFFF -> <<0xCF, int::64>>
+      true -> throw(:unsupported)
+    end
+  end
+
+  defp do_encode(int) when is_integer(int) do
+    cond do
+      int >= -32 -> <<int::signed-8>>
+      int >= -0x80 -> <<0xD0, int::signed-8>>
+      int >= -0x8000 -> <<0xD1, int::signed-16>>
+      int >= -0x80000000 -> <<0xD2, int::signed-32>>
+      int >= -0x8000000000000000 -> <<0xD3, int::signed-64>>
+      true -> throw(:unsupported)
+    end
+  end
+
+  defp do_encode(float) when is_float(float), do: <<0xCB, float::float-64>>
+
+  defp do_encode(string) when is_binary(string) do
+    size = byte_size(string)
+
+    header =
+      cond do
+        size < 32 -> <<0b101::3, size::5>>
+        size <= 0xFF -> <<0xD9, size>>
+        size <= 0xFFFF -> <<0xDA, size::16>>
+        size <= 0xFFFFFFFF -> <<0xDB, size::32>>
+        true -> throw(:unsupported)
+      end
+
+    [header, string]
+  end
+
+  defp do_encode(list) when is_list(list) do
+    [collection_header(length(list), 0x90, 0xDC), Enum.map(list, &do_encode/1)]
+  end
+
+  defp do_encode(map) when is_map(map) and not is_struct(map) do
+    pairs = Enum.map(map, fn {key, value} -> [do_encode(to_string(key)), do_encode(value)] end)
+    [collection_header(map_size(map), 0x80, 0xDE), pairs]
+  end
+
+  defp do_encode(_other), do: throw(:unsupported)
+
+  # `fix_tag` holds up to 15 entries in its low nibble; `tag16` is followed
+  # by the 16-bit form and `tag16 + 1` by the 32-bit one.
+  defp collection_header(count, fix_tag, _tag16) when count < 16, do: <<fix_tag + count>>
+  defp collection_header(count, _fix_tag, tag16) when count <= 0xFFFF, do: <<tag16, count::16>>
+
+  defp collection_header(count, _fix_tag, tag16) when count <= 0xFFFFFFFF,
+    do: <<tag16 + 1, count::32>>
+
+  defp collection_header(_count, _fix_tag, _tag16), do: throw(:unsupported)
+
+  defp do_decode(<<0xC0, rest::binary>>), do: {nil, rest}
+  defp do_decode(<<0xC2, rest::binary>>), do: {false, rest}
+  defp do_decode(<<0xC3, rest::binary>>), do: {true, rest}
+  defp do_decode(<<0::1, int::7, rest::binary>>), do: {int, rest}
+  defp do_decode(<<int::signed-8, rest::binary>>) when int >= -32 and int < 0, do: {int, rest}
+  defp do_decode(<<0xCC, int, rest::binary>>), do: {int, rest}
+  defp do_decode(<<0xCD, int::16, rest::binary>>), do: {int, rest}
+  defp do_decode(<<0xCE, int::32, rest::binary>>), do: {int, rest}
+  defp do_decode(<<0xCF, int::64, rest::binary>>), do: {int, rest}
+  defp do_decode(<<0xD0, int::signed-8, rest::binary>>), do: {int, rest}
+  defp do_decode(<<0xD1, int::signed-16, rest::binary>>), do: {int, rest}
+  defp do_decode(<<0xD2, int::signed-32, rest::binary>>), do: {int, rest}
+  defp do_decode(<<0xD3, int::signed-64, rest::binary>>), do: {int, rest}
+  defp do_decode(<<0xCA, float::float-32, rest::binary>>), do: {float, rest}
+  defp do_decode(<<0xCB, float::float-64, rest::binary>>), do: {float, rest}
+  defp do_decode(<<0b101::3, size::5, rest::binary>>), do: take_bytes(rest, size)
+  defp do_decode(<<tag, size, rest::binary>>) when tag in [0xC4, 0xD9],
+    do: take_bytes(rest, size)
+
+  defp do_decode(<<tag, size::16, rest::binary>>) when tag in [0xC5, 0xDA],
+    do: take_bytes(rest, size)
+
+  defp do_decode(<<tag, size::32, rest::binary>>) when tag in [0xC6, 0xDB],
+    do: take_bytes(rest, size)
+
+  defp do_decode(<<0b1001::4, count::4, rest::binary>>), do: decode_list(rest, count, [])
+  defp do_decode(<<0xDC, count::16, rest::binary>>), do: decode_list(rest, count, [])
+  defp do_decode(<<0xDD, count::32, rest::binary>>), do: decode_list(rest, count, [])
+  defp do_decode(<<0b1000::4, count::4, rest::binary>>), do: decode_map(rest, count, [])
+  defp do_decode(<<0xDE, count::16, rest::binary>>), do: decode_map(rest, count, [])
+  defp do_decode(<<0xDF, count::32, rest::binary>>), do: decode_map(rest, count, [])
+  defp do_decode(_unsupported_or_truncated), do: throw(:invalid)
+
+  defp take_bytes(data, size) do
+    case data do
+      <<bytes::binary-size(size), rest::binary>> -> {bytes, rest}
+      _truncated -> throw(:invalid)
+    end
+  end
+
+  defp decode_list(rest, 0, acc), do: {Enum.reverse(acc), rest}
+
+  defp decode_list(data, count, acc) do
+    {value, rest} = do_decode(data)
+    decode_list(rest, count - 1, [value | acc])
+  end
+
+  defp decode_map(rest, 0, acc), do: {Map.new(acc), rest}
+
+  defp decode_map(data, count, acc) do
+    {key, rest} = do_decode(data)
+    {value, rest} = do_decode(rest)
+    decode_map(rest, count - 1, [{key, value} | acc])
+  end
+end
@@ -2,8 +2,14 @@ defmodule Projection.Patch do
   @moduledoc """
   JSON Patch helpers for Projection.
 
-  Supports the RFC6902 subset used by Projection (`add`, `remove`, `replace`)
-  plus RFC6901 token escaping helpers for pointer paths.
+  Supports the RFC6902 operations (`add`, `remove`, `replace`, `move`,
+  `copy`, `test`) plus RFC6901 token escaping helpers for pointer paths.
+
+  As in RFC6902, an `add` at an array index inserts the value before that
+  index and shifts the following items; the `"-"` token appends:
+
+      Projection.Patch.add("/items/0", "first")
+      Projection.Patch.add("/items/-", "last")
   """
 
   @typedoc "An RFC 6902 patch operation map with `\"op\"`, `\"path\"`, and optionally `\"value\"` keys."
@@ -30,6 +36,39 @@ defmodule Projection.Patch do
     %{"op" => "remove", "path" => path}
   end
 
+  @doc """
+  Builds a `move` op that removes the value at `from` and adds it at `path`.
+
+  Reorders a list without re-sending it:
+
+      Projection.Patch.move("/items/0", "/items/-")
+  """
+  @spec move(String.t(), String.t()) :: op()
+  def move(from, path) when is_binary(from) and is_binary(path) do
+    validate_pointer_path!(from)
+    validate_pointer_path!(path)
+    %{"op" => "move", "from" => from, "path" => path}
+  end
+
+  @doc "Builds a `copy` op that adds the value at `from` at `path` as well."
+  @spec copy(String.t(), String.t()) :: op()
+  def copy(from, path) when is_binary(from) and is_binary(path) do
+    validate_pointer_path!(from)
+    validate_pointer_path!(path)
+    %{"op" => "copy", "from" => from, "path" => path}
+  end
+
+  @doc """
+  Builds a `test` op asserting the value at `path` equals `value`.
+
+  When it fails the host rejects the whole patch and asks for a resync.
+  """
+  @spec test(String.t(), any()) :: op()
+  def test(path, value) when is_binary(path) do
+    validate_pointer_path!(path)
+    %{"op" => "test", "path" => path, "value" => value}
+  end
+
   @doc """
   Joins a list of path tokens into an RFC 6901 JSON Pointer string.
 
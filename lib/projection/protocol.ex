@@ -1,21 +1,39 @@
 defmodule Projection.Protocol do
   @moduledoc """
-  JSON envelope helpers and cap enforcement for Projection port traffic.
+  Envelope helpers and cap enforcement for Projection port traffic.
 
   Notes:
   - OTP `{:packet, 4}` handles length framing at the port boundary.
-  - This module is responsible for JSON encoding/decoding and payload caps.
+  - This module is responsible for envelope encoding/decoding and payload caps.
+  - Envelopes are JSON unless the host listed `"msgpack"` under
+    `capabilities.codecs`; see `codec/1`. Inbound payloads are told apart by
+    their first byte, since every envelope is a map.
   """
 
   require Logger
 
+  alias Projection.MsgPack
+
+  @protocol_version 1
+  @supported_features ["applied", "heartbeat", "log", "intent_replay"]
   @ui_to_elixir_cap 65_536
   @elixir_to_ui_cap 1_048_576
   @warn_threshold_percent 80
+  @compression_threshold 16_384
+  @zlib_marker 0xC0
+  @chunk_marker 0xC1
+  @chunk_header_len 13
+  @codecs ["msgpack", "json"]
+  @fnv_offset 0xCBF29CE484222325
+  @fnv_prime 0x100000001B3
 
   @typedoc "A JSON-serializable map representing a protocol envelope."
   @type envelope :: map()
 
+  @doc "Protocol version this side speaks, echoed in the `accept` envelope."
+  @spec protocol_version() :: pos_integer()
+  def protocol_version, do: @protocol_version
+
   @doc "Maximum payload size (bytes) accepted from the UI host."
   @spec ui_to_elixir_cap() :: pos_integer()
   def ui_to_elixir_cap, do: @ui_to_elixir_cap
@@ -28,7 +46,7 @@ defmodule Projection.Protocol do
   Decodes a binary payload received from the UI host.
 
   Returns `{:error, :frame_too_large}` if the payload exceeds `ui_to_elixir_cap/0`,
-  or `{:error, :decode_error}` if JSON parsing fails.
+  or `{:error, :decode_error}` if JSON or MessagePack parsing fails.
   """
   @spec decode_inbound(binary()) :: {:ok, envelope()} | {:error, atom()}
   def decode_inbound(payload) when is_binary(payload) do
@@ -37,7 +55,7 @@ defmodule Projection.Protocol do
     if byte_size(payload) > @ui_to_elixir_cap do
       {:error, :frame_too_large}
     else
-      case Jason.decode(payload) do
+      case decode_payload(payload) do
         {:ok, envelope} when is_map(envelope) -> {:ok, envelope}
         {:ok, _not_envelope} -> {:error, :invalid_envelope}
         {:error, _reason} -> {:error, :decode_error}
@@ -46,28 +64,102 @@ defmodule Projection.Protocol do
   end
 
   @doc """
-  Encodes an envelope map to a JSON binary for the UI host.
+  Encodes an envelope map to a binary for the UI host.
 
   Returns `{:error, :frame_too_large}` if the encoded payload exceeds
-  `elixir_to_ui_cap/0`.
+  `elixir_to_ui_cap/0`. The cap applies to the encoded payload before compression.
+
+  ## Options
+
+    * `:codec` — `"json"` (default) or `"msgpack"`, as named in the `accept`
+      envelope (see `codec/1`)
+    * `:compress` — when `true`, payloads of at least 16 KiB are sent as the
+      `0xC0` marker byte followed by a zlib stream, if that is smaller. Only
+      set this once the host listed `"zlib"` under `capabilities.compression`
+      in its `ready` envelope.
+
   """
-  @spec encode_outbound(envelope()) :: {:ok, binary()} | {:error, atom()}
-  def encode_outbound(envelope) when is_map(envelope) do
-    with {:ok, payload} <- Jason.encode(envelope),
+  @spec encode_outbound(envelope(), keyword()) :: {:ok, binary()} | {:error, atom()}
+  def encode_outbound(envelope, opts \\ []) when is_map(envelope) do
+    with {:ok, payload} <- encode_payload(envelope, Keyword.get(opts, :codec, "json")),
          :ok <- maybe_warn_frame_size(:elixir_to_ui, byte_size(payload), @elixir_to_ui_cap),
-         :ok <- validate_outbound_size(payload) do
-      {:ok, payload}
-    else
-      {:error, %Jason.EncodeError{}} -> {:error, :encode_error}
-      {:error, reason} when is_atom(reason) -> {:error, reason}
+         :ok <- validate_outbound_size(payload, @elixir_to_ui_cap) do
+      {:ok, maybe_compress(payload, Keyword.get(opts, :compress, false))}
+    end
+  end
+
+  @doc """
+  Encodes an envelope into the list of frames to send to the UI host.
+
+  Behaves like `encode_outbound/2`, but when `:chunk_limit` is set payloads
+  larger than `elixir_to_ui_cap/0` (and at most `:chunk_limit` bytes) are split
+  into chunk frames that the host reassembles. Without `:chunk_limit` the result
+  is always a single frame.
+
+  ## Options
+
+    * `:codec` and `:compress` — see `encode_outbound/2`
+    * `:chunk_limit` — the `capabilities.chunking.max_payload` the host
+      advertised in its `ready` envelope, or `nil`
+
+  """
+  @spec encode_outbound_frames(envelope(), keyword()) :: {:ok, [binary()]} | {:error, atom()}
+  def encode_outbound_frames(envelope, opts \\ []) when is_map(envelope) do
+    case Keyword.get(opts, :chunk_limit) do
+      limit when is_integer(limit) and limit > @elixir_to_ui_cap ->
+        with {:ok, payload} <- encode_payload(envelope, Keyword.get(opts, :codec, "json")),
+             :ok <- validate_outbound_size(payload, limit) do
+          payload = maybe_compress(payload, Keyword.get(opts, :compress, false))
+
+          if byte_size(payload) > @elixir_to_ui_cap do
+            {:ok, split_chunks(payload, :erlang.unique_integer([:positive]), @elixir_to_ui_cap)}
+          else
+            {:ok, [payload]}
+          end
+        end
+
+      _no_chunking ->
+        with {:ok, payload} <- encode_outbound(envelope, opts), do: {:ok, [payload]}
+    end
+  end
+
+  @doc """
+  Splits `payload` into chunk frames of at most `max_frame` bytes each.
+
+  Every chunk starts with a 13-byte header: the `0xC1` marker, then the
+  message id, chunk index and chunk count as big-endian 32-bit integers.
+  """
+  @spec split_chunks(binary(), non_neg_integer(), pos_integer()) :: [binary()]
+  def split_chunks(payload, message_id, max_frame)
+      when is_binary(payload) and max_frame > @chunk_header_len do
+    data_len = max_frame - @chunk_header_len
+    message_id = Bitwise.band(message_id, 0xFFFFFFFF)
+    count = max(div(byte_size(payload) + data_len - 1, data_len), 1)
+
+    for index <- 0..(count - 1) do
+      offset = index * data_len
+      data = binary_part(payload, offset, min(data_len, byte_size(payload) - offset))
+      <<@chunk_marker, message_id::32, index::32, count::32, data::binary>>
     end
   end
 
+  @doc """
+  Returns the reassembly limit a `ready` envelope advertises under
+  `capabilities.chunking.max_payload`, or `nil` if the host cannot reassemble chunks.
+  """
+  @spec chunk_limit(envelope()) :: pos_integer() | nil
+  def chunk_limit(%{"capabilities" => %{"chunking" => %{"max_payload" => limit}}})
+      when is_integer(limit) and limit > 0,
+      do: limit
+
+  def chunk_limit(_envelope), do: nil
+
   @doc """
   Builds a `render` envelope containing a full view-model snapshot.
 
-  Accepts an optional `:ack` in `opts` to acknowledge the intent that
-  triggered this render.
+  Accepts an optional `:ack` in `opts` to acknowledge the last intent the
+  session processed; hosts with the `intent_replay` feature replay the
+  intents after it.
   """
   @spec render_envelope(String.t(), non_neg_integer(), map(), keyword()) :: envelope()
   def render_envelope(sid, rev, vm, opts \\ []) do
@@ -83,15 +175,95 @@ defmodule Projection.Protocol do
   Builds a `patch` envelope containing RFC 6902 ops and a new revision.
 
   Accepts an optional `:ack` in `opts` to acknowledge the intent that
-  triggered this patch.
+  triggered this patch, and `:resume_from` when the patch answers a resuming
+  `ready`: its ops then apply to the view-model the host kept at that
+  revision rather than to the previous one.
   """
   @spec patch_envelope(String.t(), non_neg_integer(), [map()], keyword()) :: envelope()
   def patch_envelope(sid, rev, ops, opts \\ []) when is_list(ops) do
     base = %{"t" => "patch", "sid" => sid, "rev" => rev, "ops" => ops}
 
-    case Keyword.fetch(opts, :ack) do
-      {:ok, ack} -> Map.put(base, "ack", ack)
-      :error -> base
+    Enum.reduce([ack: "ack", resume_from: "resume_from"], base, fn {opt, key}, envelope ->
+      case Keyword.fetch(opts, opt) do
+        {:ok, value} -> Map.put(envelope, key, value)
+        :error -> envelope
+      end
+    end)
+  end
+
+  @doc """
+  Hashes a view-model the way the host does, to check that a host resuming
+  from a revision still holds what the session sent at that revision.
+
+  FNV-1a (64-bit) over the canonical JSON (object keys sorted, no whitespace),
+  as 16 lowercase hex digits. Floats are written with their shortest
+  round-trip digits in `1.5e-7` form (`1e20`, `1e0`, `-2.5e-1`), since JSON
+  encoders disagree on notation.
+  """
+  @spec vm_hash(term()) :: String.t()
+  def vm_hash(vm) do
+    vm
+    |> canonical_json()
+    |> IO.iodata_to_binary()
+    |> fnv1a_64()
+    |> Integer.to_string(16)
+    |> String.downcase()
+    |> String.pad_leading(16, "0")
+  end
+
+  defp canonical_json(map) when is_map(map) and not is_struct(map) do
+    pairs =
+      map
+      |> Enum.map(fn {key, value} -> {to_string(key), value} end)
+      |> Enum.sort_by(&elem(&1, 0))
+      |> Enum.map(fn {key, value} -> [Jason.encode!(key), ?:, canonical_json(value)] end)
+
+    [?{, Enum.intersperse(pairs, ?,), ?}]
+  end
+
+  defp canonical_json(list) when is_list(list) do
+    [?[, list |> Enum.map(&canonical_json/1) |> Enum.intersperse(?,), ?]]
+  end
+
+  defp canonical_json(float) when is_float(float), do: canonical_float(float)
+  defp canonical_json(value), do: Jason.encode!(value)
+
+  defp canonical_float(float) do
+    {sign, short} =
+      case :erlang.float_to_binary(float, [:short]) do
+        "-" <> short -> {"-", short}
+        short -> {"", short}
+      end
+
+    {mantissa, exponent} =
+      case String.split(short, "e") do
+        [mantissa, exponent] -> {mantissa, String.to_integer(exponent)}
+        [mantissa] -> {mantissa, 0}
+      end
+
+    {int, frac} =
+      case String.split(mantissa, ".") do
+        [int, frac] -> {int, frac}
+        [int] -> {int, ""}
+      end
+
+    digits = String.trim_leading(int <> frac, "0")
+
+    case String.trim_trailing(digits, "0") do
+      "" ->
+        [sign, "0e0"]
+
+      <<first::binary-size(1), rest::binary>> = significant ->
+        # The value is `significant * 10^scale`.
+        scale = exponent - byte_size(frac) + byte_size(digits) - byte_size(significant)
+        point = if rest == "", do: "", else: [".", rest]
+        [sign, first, point, ?e, Integer.to_string(scale + byte_size(significant) - 1)]
+    end
+  end
+
+  defp fnv1a_64(binary) do
+    for <<byte <- binary>>, reduce: @fnv_offset do
+      hash -> Bitwise.band(Bitwise.bxor(hash, byte) * @fnv_prime, 0xFFFFFFFFFFFFFFFF)
     end
   end
 
@@ -103,13 +275,130 @@ defmodule Projection.Protocol do
     if is_nil(rev), do: envelope, else: Map.put(envelope, "rev", rev)
   end
 
+  @doc "Builds a `pong` envelope answering a `ping` with the same `nonce`."
+  @spec pong_envelope(String.t(), non_neg_integer()) :: envelope()
+  def pong_envelope(sid, nonce), do: %{"t" => "pong", "sid" => sid, "nonce" => nonce}
+
+  @doc """
+  Builds the `accept` envelope answering a host `ready`.
+
+  It carries the protocol version spoken here and the features switched on
+  for the connection: the codec picked by `codec/1`, zlib compression when
+  the host advertised it, and the optional host features this side understands
+  (`"applied"` acknowledgements, `"heartbeat"` pings and `"log"` forwarding).
+
+  ## Options
+
+    * `:intent_policies` — map of intent name to how the host coalesces bursts
+      of it before sending: `:latest_wins` (one per UI frame), `{:debounce, ms}`,
+      `{:throttle, ms}` or `:immediate`. Policies set by the host app win.
+
+  """
+  @spec accept_envelope(String.t(), envelope(), keyword()) :: envelope()
+  def accept_envelope(sid, ready, opts \\ []) do
+    host_features =
+      case ready do
+        %{"capabilities" => %{"features" => features}} when is_list(features) -> features
+        _ -> []
+      end
+
+    base = %{
+      "t" => "accept",
+      "sid" => sid,
+      "protocol_version" => @protocol_version,
+      "codec" => codec(ready),
+      "features" => Enum.filter(@supported_features, &(&1 in host_features))
+    }
+
+    base =
+      case Keyword.get(opts, :intent_policies, %{}) do
+        policies when map_size(policies) == 0 ->
+          base
+
+        policies ->
+          encoded =
+            Map.new(policies, fn {name, policy} ->
+              {to_string(name), encode_intent_policy(policy)}
+            end)
+
+          Map.put(base, "intent_policies", encoded)
+      end
+
+    if compression_supported?(ready), do: Map.put(base, "compression", "zlib"), else: base
+  end
+
+  defp encode_intent_policy(:immediate), do: %{"mode" => "immediate"}
+  defp encode_intent_policy(:latest_wins), do: %{"mode" => "latest_wins"}
+
+  defp encode_intent_policy({mode, interval_ms})
+       when mode in [:debounce, :throttle] and is_integer(interval_ms) and interval_ms >= 0,
+       do: %{"mode" => Atom.to_string(mode), "interval_ms" => interval_ms}
+
+  defp encode_intent_policy(other) do
+    raise ArgumentError,
+          "expected an intent policy of :immediate, :latest_wins, {:debounce, ms} or {:throttle, ms}, got: #{inspect(other)}"
+  end
+
+  @doc """
+  Returns the protocol version a `ready` envelope advertises, or `nil` for
+  hosts that predate version negotiation.
+  """
+  @spec host_protocol_version(envelope()) :: pos_integer() | nil
+  def host_protocol_version(%{"capabilities" => %{"protocol_version" => version}})
+      when is_integer(version),
+      do: version
+
+  def host_protocol_version(_envelope), do: nil
+
+  @doc """
+  Returns the codec to speak with the host that sent a `ready` envelope:
+  `"msgpack"` if it lists it under `capabilities.codecs`, otherwise `"json"`.
+  """
+  @spec codec(envelope()) :: String.t()
+  def codec(%{"capabilities" => %{"codecs" => codecs}}) when is_list(codecs) do
+    Enum.find(@codecs, "json", &(&1 in codecs))
+  end
+
+  def codec(_envelope), do: "json"
+
+  @doc "Returns `true` if a `ready` envelope advertises zlib frame compression."
+  @spec compression_supported?(envelope()) :: boolean()
+  def compression_supported?(%{"capabilities" => %{"compression" => compression}})
+      when is_list(compression),
+      do: "zlib" in compression
+
+  def compression_supported?(_envelope), do: false
+
   @doc "Returns `true` if the envelope is a valid `ready` handshake."
   @spec ready?(envelope()) :: boolean()
   def ready?(%{"t" => "ready", "sid" => sid}) when is_binary(sid), do: true
   def ready?(_), do: false
 
-  defp validate_outbound_size(payload) do
-    if byte_size(payload) > @elixir_to_ui_cap do
+  defp encode_payload(envelope, "msgpack"), do: MsgPack.encode(envelope)
+
+  defp encode_payload(envelope, "json") do
+    case Jason.encode(envelope) do
+      {:ok, payload} -> {:ok, payload}
+      {:error, %Jason.EncodeError{}} -> {:error, :encode_error}
+    end
+  end
+
+  # Envelopes are maps: a MessagePack map header can never start a JSON text.
+  defp decode_payload(<<tag, _rest::binary>> = payload)
+       when tag in 0x80..0x8F or tag in [0xDE, 0xDF],
+       do: MsgPack.decode(payload)
+
+  defp decode_payload(payload), do: Jason.decode(payload)
+
+  defp maybe_compress(payload, true) when byte_size(payload) >= @compression_threshold do
+    compressed = <<@zlib_marker, :zlib.compress(payload)::binary>>
+    if byte_size(compressed) < byte_size(payload), do: compressed, else: payload
+  end
+
+  defp maybe_compress(payload, _compress), do: payload
+
+  defp validate_outbound_size(payload, cap) do
+    if byte_size(payload) > cap do
       {:error, :frame_too_large}
     else
       :ok
@@ -8,7 +8,22 @@ defmodule Projection.Session do
   - keep monotonic `rev`
   - keep stable `sid` for a running session
   - emit periodic `patch` updates from screen state changes
+  - track the `applied` revisions the host reports, measure render latency,
+    and optionally hold patches back while the display falls behind
+  - acknowledge the last processed intent in every `render`, so a host that
+    resyncs knows which of its intents to replay; a `ready` from a new host
+    `instance` starts the count over
+  - answer a `ready` that names a recently sent revision with a patch from
+    that revision instead of a full render
   - optionally run route-aware screen switching via a router built with `Projection.Router.DSL`
+
+  Secondary windows and dialogs are declared by a `windows` map in the VM,
+  keyed by window id, each entry with a `kind`, optional `title` and `modal`,
+  and its own `vm`; `declare/2` on a `use ProjectionUI, :window` module
+  builds one. Routed screens return `windows` from `render/1` and the
+  session lifts it to the VM root next to `app` and `nav`. The host forwards a
+  close request as a `ui.window.close` intent with the window `id`; the window
+  stays open until the screen drops its entry.
   """
 
   use GenServer
@@ -24,6 +39,9 @@ defmodule Projection.Session do
   @event_intent_received [:session, :intent, :received]
   @event_render_complete [:session, :render, :complete]
   @event_patch_sent [:session, :patch, :sent]
+  @event_applied [:session, :applied]
+  @max_tracked_sent_revs 256
+  @default_resume_history 16
   @event_error [:session, :error]
 
   @typedoc "Internal GenServer state for a running session."
@@ -35,7 +53,14 @@ defmodule Projection.Session do
           max_pending_ops: pos_integer(),
           pending_patch_ops: [map()],
           pending_ack: non_neg_integer() | nil,
+          last_intent_id: non_neg_integer() | nil,
+          host_instance: String.t() | nil,
           patch_flush_ref: {reference(), reference()} | nil,
+          applied_rev: non_neg_integer() | nil,
+          sent_at_ms: %{optional(non_neg_integer()) => integer()} | nil,
+          max_unapplied_revs: pos_integer() | nil,
+          resume_history: non_neg_integer(),
+          vm_history: [{non_neg_integer(), map()}],
           tick_ms: pos_integer() | nil,
           tick_ref: reference() | nil,
           host_bridge: GenServer.server() | nil,
@@ -64,6 +89,12 @@ defmodule Projection.Session do
     * `:screen_session` — session map passed to `c:ProjectionUI.Screen.mount/3`
     * `:batch_window_ms` — patch batch flush window in milliseconds (default `16`)
     * `:max_pending_ops` — max coalesced ops kept before immediate flush (default `128`)
+    * `:max_unapplied_revs` — once the host reports `applied` revisions, hold
+      pending patches while this many sent revisions are still unapplied
+      (nil, the default, never holds)
+    * `:resume_history` — how many recently sent revisions are kept so a
+      resyncing host can resume from one with a patch (default `16`, `0`
+      always answers with a full render)
     * `:tick_ms` — interval for `:tick` messages (nil disables)
     * `:host_bridge` — name or pid of the `ProjectionUI.HostBridge` for outbound envelopes
     * `:subscription_hook` — `(action, topic -> any())` callback for pub/sub
@@ -121,7 +152,16 @@ defmodule Projection.Session do
         max_pending_ops: normalize_max_pending_ops(Keyword.get(opts, :max_pending_ops, 128)),
         pending_patch_ops: [],
         pending_ack: nil,
+        last_intent_id: nil,
+        host_instance: nil,
         patch_flush_ref: nil,
+        applied_rev: nil,
+        sent_at_ms: nil,
+        max_unapplied_revs:
+          normalize_max_unapplied_revs(Keyword.get(opts, :max_unapplied_revs)),
+        resume_history:
+          normalize_resume_history(Keyword.get(opts, :resume_history, @default_resume_history)),
+        vm_history: [],
         tick_ms: normalize_tick_ms(Keyword.get(opts, :tick_ms)),
         tick_ref: nil,
         host_bridge: Keyword.get(opts, :host_bridge),
@@ -204,18 +244,23 @@ defmodule Projection.Session do
   defp process_ui_envelope(envelope, state) do
     case envelope do
       %{"t" => "ready", "sid" => incoming_sid} when is_binary(incoming_sid) ->
-        state = clear_pending_patch_batch(state)
+        state = state |> clear_pending_patch_batch() |> track_host_instance(envelope)
         sid = ensure_stable_sid(state.sid, incoming_sid)
         rev = state.rev + 1
-        next_state = maybe_schedule_tick(%{state | sid: sid, rev: rev})
+
+        next_state =
+          %{state | sid: sid, rev: rev, applied_rev: nil, sent_at_ms: applied_tracking(envelope)}
+          |> record_sent_at(rev)
+          |> maybe_schedule_tick()
+
         put_logger_metadata(next_state)
-        Logger.info("ui ready received; sending render snapshot")
-        render = Protocol.render_envelope(sid, rev, state.vm)
-        {:ok, [render], next_state}
+        reply = ready_reply(state, envelope, sid, rev)
+        {:ok, [reply], record_vm_history(next_state, rev)}
 
       %{"t" => "intent", "name" => name} = intent when is_binary(name) ->
         payload = normalize_payload(Map.get(intent, "payload"))
         ack = normalize_ack(Map.get(intent, "id"))
+        state = %{state | last_intent_id: merge_patch_ack(state.last_intent_id, ack)}
         emit_intent_received(state, name, ack)
         Logger.debug("ui intent received name=#{name} ack=#{inspect(ack)}")
 
@@ -231,6 +276,9 @@ defmodule Projection.Session do
             {:ok, [], next_state}
         end
 
+      %{"t" => "applied", "rev" => rev} when is_integer(rev) ->
+        {:ok, [], handle_applied(state, rev, Map.get(envelope, "applied_at_ms"))}
+
       _ ->
         {:ok, [], state}
     end
@@ -457,6 +505,8 @@ defmodule Projection.Session do
     result =
       case safe_render_screen(state.screen_module, state.screen_state.assigns, state) do
         {:ok, screen_vm} ->
+          {windows, screen_vm} = Map.pop(screen_vm, :windows)
+
           {:ok,
            %{
              app: %{title: state.app_title},
@@ -466,7 +516,8 @@ defmodule Projection.Session do
                action: current.action,
                vm: screen_vm
              }
-           }}
+           }
+           |> maybe_put_windows(windows)}
 
         {:error, error_vm} ->
           {:error, render_error_vm(state, error_vm)}
@@ -476,6 +527,9 @@ defmodule Projection.Session do
     result
   end
 
+  defp maybe_put_windows(vm, nil), do: vm
+  defp maybe_put_windows(vm, windows), do: Map.put(vm, :windows, windows)
+
   defp safe_render_screen(screen_module, assigns, context_state) when is_map(assigns) do
     try do
       vm = render_screen(screen_module, assigns)
@@ -589,6 +643,7 @@ defmodule Projection.Session do
     global_paths = [
       ["app"],
       ["nav"],
+      ["windows"],
       ["screen", "name"],
       ["screen", "action"]
     ]
@@ -839,6 +894,19 @@ defmodule Projection.Session do
   end
 
   defp flush_pending_patch_batch(%{sid: sid} = state) when is_binary(sid) do
+    if display_behind?(state) do
+      Logger.debug(
+        "patch held rev=#{state.rev} applied_rev=#{state.applied_rev} ops=#{length(state.pending_patch_ops)}"
+      )
+
+      cancel_patch_flush_timer(state.patch_flush_ref)
+      %{state | patch_flush_ref: nil}
+    else
+      send_pending_patch_batch(state)
+    end
+  end
+
+  defp send_pending_patch_batch(%{sid: sid} = state) do
     ops_count = length(state.pending_patch_ops)
     rev = state.rev + 1
     patch_opts = if is_nil(state.pending_ack), do: [], else: [ack: state.pending_ack]
@@ -848,6 +916,8 @@ defmodule Projection.Session do
       state
       |> clear_pending_patch_batch()
       |> Map.put(:rev, rev)
+      |> record_sent_at(rev)
+      |> record_vm_history(rev)
 
     put_logger_metadata(next_state)
     Logger.debug("patch sent rev=#{rev} ops=#{ops_count} ack=#{inspect(state.pending_ack)}")
@@ -861,6 +931,60 @@ defmodule Projection.Session do
     dispatch_outbound(next_state, [patch])
   end
 
+  # Revisions at or below `rev` are painted; anything held back while the display
+  # was behind goes out once it catches up.
+  defp handle_applied(state, rev, applied_at_ms) do
+    {sent_at, sent_at_ms} = Map.pop(state.sent_at_ms || %{}, rev)
+    sent_at_ms = Map.reject(sent_at_ms, fn {sent_rev, _at} -> sent_rev < rev end)
+    applied_rev = max(rev, state.applied_rev || 0)
+    next_state = %{state | applied_rev: applied_rev, sent_at_ms: sent_at_ms}
+
+    measurements =
+      if is_integer(sent_at) and is_integer(applied_at_ms),
+        do: %{count: 1, lag: state.rev - applied_rev, latency_ms: max(applied_at_ms - sent_at, 0)},
+        else: %{count: 1, lag: state.rev - applied_rev}
+
+    Telemetry.execute(
+      @event_applied,
+      measurements,
+      telemetry_metadata(next_state, %{applied_rev: rev})
+    )
+
+    if next_state.pending_patch_ops != [] and not display_behind?(next_state) do
+      flush_pending_patch_batch(next_state)
+    else
+      next_state
+    end
+  end
+
+  defp display_behind?(%{max_unapplied_revs: nil}), do: false
+  defp display_behind?(%{applied_rev: nil}), do: false
+
+  defp display_behind?(state) do
+    state.rev - state.applied_rev >= state.max_unapplied_revs
+  end
+
+  # Send times are only kept for hosts that report `applied` revisions, and
+  # only for revisions not yet acknowledged.
+  defp applied_tracking(%{"capabilities" => %{"features" => features}}) when is_list(features) do
+    if "applied" in features, do: %{}, else: nil
+  end
+
+  defp applied_tracking(_ready), do: nil
+
+  defp record_sent_at(%{sent_at_ms: nil} = state, _rev), do: state
+
+  defp record_sent_at(state, rev) do
+    sent_at_ms = Map.put(state.sent_at_ms, rev, System.system_time(:millisecond))
+
+    sent_at_ms =
+      if map_size(sent_at_ms) > @max_tracked_sent_revs,
+        do: Map.delete(sent_at_ms, Enum.min(Map.keys(sent_at_ms))),
+        else: sent_at_ms
+
+    %{state | sent_at_ms: sent_at_ms}
+  end
+
   defp schedule_patch_batch_flush(
          %{patch_flush_ref: nil, batch_window_ms: batch_window_ms} = state
        ) do
@@ -906,6 +1030,63 @@ defmodule Projection.Session do
   defp patch_op_path(%{"path" => path}) when is_binary(path), do: path
   defp patch_op_path(_op), do: nil
 
+  # A host that kept its view-model through a resync names the revision it
+  # holds and a hash of it. When that revision is still in the history and the
+  # hashes agree, a patch from it replaces the full render.
+  defp ready_reply(state, ready, sid, rev) do
+    case resume_ops(state, ready) do
+      {:ok, from_rev, ops} ->
+        Logger.info("ui ready received; resuming from rev=#{from_rev} with #{length(ops)} op(s)")
+
+        Protocol.patch_envelope(
+          sid,
+          rev,
+          ops,
+          [resume_from: from_rev] ++ render_ack_opts(state)
+        )
+
+      :error ->
+        Logger.info("ui ready received; sending render snapshot")
+        Protocol.render_envelope(sid, rev, state.vm, render_ack_opts(state))
+    end
+  end
+
+  defp resume_ops(state, %{"resume" => %{"rev" => from_rev, "vm_hash" => vm_hash}})
+       when is_integer(from_rev) and is_binary(vm_hash) do
+    with {^from_rev, base_vm} <- List.keyfind(state.vm_history, from_rev, 0),
+         ^vm_hash <- Protocol.vm_hash(base_vm) do
+      {:ok, from_rev, vm_patch_ops(base_vm, state.vm)}
+    else
+      _ -> :error
+    end
+  end
+
+  defp resume_ops(_state, _ready), do: :error
+
+  defp record_vm_history(%{resume_history: 0} = state, _rev), do: state
+
+  defp record_vm_history(state, rev) do
+    history = Enum.take([{rev, state.vm} | state.vm_history], state.resume_history)
+    %{state | vm_history: history}
+  end
+
+  # Intents are processed in id order, so the highest id seen covers every
+  # intent before it, including the ones whose changes were still batched
+  # when the `ready` cleared the batch.
+  defp render_ack_opts(%{last_intent_id: nil}), do: []
+  defp render_ack_opts(%{last_intent_id: id}), do: [ack: id]
+
+  # A restarted host numbers its intents from 1 again, so the highest id seen
+  # from the previous process would acknowledge intents the new one has not
+  # sent yet. A `ready` without an `instance` comes from the same host.
+  defp track_host_instance(%{host_instance: instance} = state, %{"instance" => instance}),
+    do: state
+
+  defp track_host_instance(state, %{"instance" => instance}) when is_binary(instance),
+    do: %{state | host_instance: instance, last_intent_id: nil}
+
+  defp track_host_instance(state, _ready), do: state
+
   defp merge_patch_ack(nil, nil), do: nil
   defp merge_patch_ack(ack, nil), do: ack
   defp merge_patch_ack(nil, ack), do: ack
@@ -923,6 +1104,15 @@ defmodule Projection.Session do
 
   defp normalize_max_pending_ops(_max_pending_ops), do: 128
 
+  defp normalize_max_unapplied_revs(max_unapplied_revs)
+       when is_integer(max_unapplied_revs) and max_unapplied_revs > 0,
+       do: max_unapplied_revs
+
+  defp normalize_max_unapplied_revs(_max_unapplied_revs), do: nil
+
+  defp normalize_resume_history(limit) when is_integer(limit) and limit >= 0, do: limit
+  defp normalize_resume_history(_limit), do: @default_resume_history
+
   defp normalize_screen_session(session) when is_map(session), do: session
 
   defp normalize_screen_session(other) do
@@ -17,6 +17,20 @@ defmodule ProjectionUI do
   DSL, and provides default implementations for all optional callbacks.
 
   Reusable component schemas can be declared with `use ProjectionUI, :component`.
+
+  Secondary window kinds are declared with `use ProjectionUI, :window`. The
+  kind is the underscored module name, and `declare/2` builds the entry a
+  screen returns under `windows`:
+
+      defmodule MyApp.Windows.Confirm do
+        use ProjectionUI, :window
+
+        schema do
+          field :message, :string
+        end
+      end
+
+      %{windows: %{discard: MyApp.Windows.Confirm.declare(%{message: "Discard?"}, modal: true)}}
   """
 
   @doc false
@@ -101,6 +115,33 @@ defmodule ProjectionUI do
     end
   end
 
+  @doc false
+  def window do
+    quote do
+      use ProjectionUI.Schema, owner: :window
+
+      @doc false
+      @spec __projection_window__() :: true
+      def __projection_window__, do: true
+
+      @doc """
+      Builds a `windows` entry of this kind, filling `vm` with the schema
+      defaults. Accepts `:title` and `:modal` options.
+      """
+      @spec declare(map(), keyword()) :: map()
+      def declare(vm \\ %{}, opts \\ []) when is_map(vm) and is_list(opts) do
+        defaults = schema()
+
+        %{
+          kind: __MODULE__ |> Module.split() |> List.last() |> Macro.underscore(),
+          title: Keyword.get(opts, :title, ""),
+          modal: Keyword.get(opts, :modal, false),
+          vm: Map.merge(defaults, Map.take(vm, Map.keys(defaults)))
+        }
+      end
+    end
+  end
+
   defmacro __using__(which) when is_atom(which) do
     apply(__MODULE__, which, [])
   end
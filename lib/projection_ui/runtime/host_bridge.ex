@@ -4,10 +4,47 @@ defmodule ProjectionUI.HostBridge do
   the host and `Projection.Session`.
 
   M1 behavior:
-  - decode inbound JSON envelopes from the port
+  - decode inbound JSON or MessagePack envelopes from the port
   - forward to `Projection.Session`
   - encode outbound envelopes back to the port
   - reconnect using bounded exponential backoff
+
+  With `transport: :fd`, the port is opened with `:nouse_stdio` and the host is
+  told via `PROJECTION_TRANSPORT=fd` to frame on fds 3/4, leaving the host's
+  stdout/stderr free for ordinary logs. The transport the host reports in its
+  `ready` capabilities is checked against the one the bridge opened.
+
+  `diagnostic` envelopes, which the host sends whenever it requests a resync,
+  are logged and emitted as `[:projection, :host_bridge, :diagnostic]`
+  telemetry with the failure `code`, `message`, `rev` and patch `path`.
+
+  Every `ready` that advertises a `protocol_version` is answered with an
+  `accept` envelope before the session renders, naming the protocol version
+  and the optional features in use. Hosts that list `"msgpack"` under
+  `capabilities.codecs` are sent MessagePack from then on. A host that speaks
+  a different version exits with a clear error instead of failing later on
+  decode.
+
+  When the `"heartbeat"` feature is accepted, the host pings periodically and
+  shows a "connection stale" overlay once nothing has arrived for a while
+  (`PROJECTION_HEARTBEAT_INTERVAL_MS`, `PROJECTION_HEARTBEAT_STALE_AFTER_MS`).
+  The bridge answers each `ping` with a `pong` straight away; neither reaches
+  the session.
+
+  With the `"log"` feature, host log records arrive as `log` envelopes and are
+  written to `Logger` at the host's level, with `host_target` and `host_fields`
+  metadata. `sid` metadata is the session the record names, and is unset for
+  records about the connection itself. The host filters them by
+  `PROJECTION_LOG_LEVEL` and rate-limits them (`PROJECTION_LOG_RATE_PER_SEC`).
+
+  With `:socket_path`, the bridge listens on a unix domain socket instead of
+  spawning the host. A long-lived `ui_host` started with `PROJECTION_SOCKET`
+  connects to it and reconnects on its own when the BEAM restarts.
+
+  A host serving several sessions (`PROJECTION_SIDS`) is bridged with
+  `:sessions`, a map of sid to session: inbound envelopes go to the session
+  named by their `sid`, each `ready` is accepted under its own sid, and a
+  decode failure resyncs every session. Envelopes for other sids are dropped.
   """
 
   use GenServer
@@ -20,16 +57,26 @@ defmodule ProjectionUI.HostBridge do
 
   @backoff_steps_ms [100, 200, 500, 1_000, 2_000, 5_000]
   @event_error [:host_bridge, :error]
+  @event_diagnostic [:host_bridge, :diagnostic]
 
   @typedoc "Internal state for the port owner process."
   @type state :: %{
-          session: GenServer.server(),
+          session: GenServer.server() | nil,
+          sessions: %{optional(String.t()) => GenServer.server()},
           sid: String.t(),
           port: port() | nil,
+          socket_path: String.t() | nil,
+          listen_socket: port() | nil,
+          socket: port() | nil,
           command: String.t() | nil,
+          transport: :stdio | :fd,
           args: [String.t()],
           env: [{String.t(), String.t()}],
           cd: String.t(),
+          intent_policies: %{optional(String.t()) => term()},
+          codec: String.t(),
+          compress: boolean(),
+          chunk_limit: pos_integer() | nil,
           reconnect_idx: non_neg_integer()
         }
 
@@ -39,11 +86,19 @@ defmodule ProjectionUI.HostBridge do
   ## Options
 
     * `:name` — registered process name
-    * `:session` — (required) name or pid of the `Projection.Session` to forward envelopes to
+    * `:session` — name or pid of the `Projection.Session` to forward envelopes to
+      (required unless `:sessions` is given)
+    * `:sessions` — map of sid to session for a host serving several sessions; envelopes
+      are routed by their `sid`
     * `:command` — path to the UI host executable (nil keeps the port disconnected)
+    * `:socket_path` — listen on this unix domain socket for an externally started host
+      instead of spawning `:command`
+    * `:transport` — `:stdio` (default) or `:fd` to frame on fds 3/4 via `:nouse_stdio`
     * `:args` — command-line arguments for the host binary
     * `:env` — list of `{key, value}` environment variable tuples
     * `:cd` — working directory for the host process
+    * `:intent_policies` — how the host coalesces high-frequency intents, sent in
+      `accept` (see `Projection.Protocol.accept_envelope/3`)
 
   """
   @spec start_link(keyword()) :: GenServer.on_start()
@@ -61,14 +116,33 @@ defmodule ProjectionUI.HostBridge do
   def init(opts) do
     Process.flag(:trap_exit, true)
 
+    sessions =
+      Map.new(Keyword.get(opts, :sessions, %{}), fn {sid, session} ->
+        {to_string(sid), session}
+      end)
+
+    session =
+      if map_size(sessions) == 0,
+        do: Keyword.fetch!(opts, :session),
+        else: Keyword.get(opts, :session)
+
     state = %{
-      session: Keyword.fetch!(opts, :session),
+      session: session,
+      sessions: sessions,
       sid: normalize_sid(Keyword.get(opts, :sid, "S1")),
       port: nil,
+      socket_path: Keyword.get(opts, :socket_path),
+      listen_socket: nil,
+      socket: nil,
       command: Keyword.get(opts, :command),
+      transport: normalize_transport(Keyword.get(opts, :transport, :stdio)),
       args: Keyword.get(opts, :args, []),
       env: Keyword.get(opts, :env, []),
       cd: Keyword.get(opts, :cd, File.cwd!()),
+      intent_policies: Keyword.get(opts, :intent_policies, %{}),
+      codec: "json",
+      compress: false,
+      chunk_limit: nil,
       reconnect_idx: 0
     }
 
@@ -95,25 +169,50 @@ defmodule ProjectionUI.HostBridge do
 
   def handle_info({port, {:data, payload}}, %{port: port} = state) when is_binary(payload) do
     put_logger_metadata(state)
+    next_state = handle_inbound_payload(payload, state)
+    put_logger_metadata(next_state)
+    {:noreply, next_state}
+  end
 
-    next_state =
-      case Protocol.decode_inbound(payload) do
-        {:ok, envelope} ->
-          next_state = maybe_track_sid_from_envelope(envelope, state)
-          put_logger_metadata(next_state)
-          Session.handle_ui_envelope(state.session, envelope)
-          next_state
-
-        {:error, reason} ->
-          Logger.warning("ui_host inbound decode failed: #{inspect(reason)}")
-          emit_error(reason, state, %{source: :decode_inbound})
-          handle_decode_error(reason, state)
-      end
-
+  def handle_info({:tcp, socket, payload}, %{socket: socket} = state) when is_binary(payload) do
+    put_logger_metadata(state)
+    next_state = handle_inbound_payload(payload, state)
     put_logger_metadata(next_state)
     {:noreply, next_state}
   end
 
+  def handle_info({:socket_accepted, socket}, state) do
+    put_logger_metadata(state)
+    close_socket(state.socket)
+
+    case :inet.setopts(socket, active: true) do
+      :ok ->
+        Logger.info("ui_host connected on #{state.socket_path}")
+        {:noreply, %{state | socket: socket}}
+
+      {:error, reason} ->
+        Logger.warning("failed to activate ui_host socket: #{inspect(reason)}")
+        emit_error(:socket_setopts_failed, state, %{reason: inspect(reason)})
+        close_socket(socket)
+        {:noreply, %{state | socket: nil}}
+    end
+  end
+
+  def handle_info({:tcp_closed, socket}, %{socket: socket} = state) do
+    put_logger_metadata(state)
+    Logger.warning("ui_host socket closed; waiting for host to reconnect")
+    emit_error(:socket_closed, state, %{})
+    {:noreply, %{state | socket: nil}}
+  end
+
+  def handle_info({:tcp_error, socket, reason}, %{socket: socket} = state) do
+    put_logger_metadata(state)
+    Logger.warning("ui_host socket error #{inspect(reason)}; waiting for host to reconnect")
+    emit_error(:socket_error, state, %{reason: inspect(reason)})
+    close_socket(socket)
+    {:noreply, %{state | socket: nil}}
+  end
+
   def handle_info({port, {:exit_status, status}}, %{port: port} = state) do
     put_logger_metadata(state)
     Logger.warning("ui_host exited with status #{status}; scheduling reconnect")
@@ -134,26 +233,94 @@ defmodule ProjectionUI.HostBridge do
   end
 
   @impl true
-  def terminate(_reason, %{port: port}) when is_port(port) do
-    try do
-      Port.close(port)
-    catch
-      :error, _ -> :ok
+  def terminate(_reason, state) do
+    if is_port(state.port) do
+      try do
+        Port.close(state.port)
+      catch
+        :error, _ -> :ok
+      end
+    end
+
+    close_socket(state.socket)
+
+    if state.listen_socket do
+      close_socket(state.listen_socket)
+      _ = File.rm(state.socket_path)
     end
 
     :ok
   end
 
-  def terminate(_reason, _state), do: :ok
+  defp handle_inbound_payload(payload, state) do
+    case Protocol.decode_inbound(payload) do
+      {:ok, %{"t" => "ping", "nonce" => nonce} = ping} when is_integer(nonce) ->
+        dispatch_to_port(Protocol.pong_envelope(Map.get(ping, "sid", state.sid), nonce), state)
+
+      {:ok, %{"t" => "pong"}} ->
+        state
+
+      {:ok, %{"t" => "log"} = envelope} ->
+        log_host_record(envelope)
+        state
+
+      {:ok, envelope} ->
+        next_state =
+          envelope
+          |> maybe_track_sid_from_envelope(state)
+          |> maybe_track_compression(envelope)
+
+        put_logger_metadata(next_state)
+        verify_host_transport(envelope, next_state)
+        maybe_report_diagnostic(envelope, next_state)
+        next_state = maybe_accept(envelope, next_state)
+        forward_to_session(envelope, next_state)
+        next_state
+
+      {:error, reason} ->
+        Logger.warning("ui_host inbound decode failed: #{inspect(reason)}")
+        emit_error(reason, state, %{source: :decode_inbound})
+        handle_decode_error(reason, state)
+    end
+  end
+
+  defp forward_to_session(envelope, %{sessions: sessions} = state)
+       when map_size(sessions) == 0 do
+    Session.handle_ui_envelope(state.session, envelope)
+  end
+
+  defp forward_to_session(envelope, state) do
+    sid = envelope["sid"]
+
+    case Map.fetch(state.sessions, sid) do
+      {:ok, session} ->
+        Session.handle_ui_envelope(session, envelope)
+
+      :error ->
+        Logger.warning(
+          "dropping ui_host #{envelope["t"]} envelope for unknown sid #{inspect(sid)}"
+        )
+        emit_error(:unknown_sid, state, %{reported_sid: sid})
+    end
+  end
+
+  defp routed_sessions(%{sessions: sessions} = state) when map_size(sessions) == 0,
+    do: [{state.sid, state.session}]
 
-  defp dispatch_to_port(envelope, %{port: nil} = state) do
+  defp routed_sessions(state), do: Map.to_list(state.sessions)
+
+  defp dispatch_to_port(envelope, %{port: nil, socket: nil} = state) do
     maybe_track_sid_from_envelope(envelope, state)
   end
 
-  defp dispatch_to_port(envelope, %{port: port} = state) do
-    case Protocol.encode_outbound(envelope) do
-      {:ok, payload} ->
-        true = Port.command(port, payload)
+  defp dispatch_to_port(envelope, state) do
+    case Protocol.encode_outbound_frames(envelope,
+           codec: state.codec,
+           compress: state.compress,
+           chunk_limit: state.chunk_limit
+         ) do
+      {:ok, payloads} ->
+        Enum.each(payloads, &send_payload(state, &1))
         maybe_track_sid_from_envelope(envelope, state)
 
       {:error, reason} ->
@@ -163,6 +330,47 @@ defmodule ProjectionUI.HostBridge do
     end
   end
 
+  defp send_payload(%{port: port}, payload) when is_port(port) do
+    true = Port.command(port, payload)
+    :ok
+  end
+
+  defp send_payload(%{socket: socket} = state, payload) do
+    case :gen_tcp.send(socket, payload) do
+      :ok ->
+        :ok
+
+      {:error, reason} ->
+        # The socket owner receives `:tcp_closed`/`:tcp_error` and clears the socket.
+        Logger.warning("ui_host socket send failed: #{inspect(reason)}")
+        emit_error(:socket_send_failed, state, %{reason: inspect(reason)})
+        :ok
+    end
+  end
+
+  defp maybe_connect(%{socket_path: socket_path} = state) when is_binary(socket_path) do
+    _ = File.rm(socket_path)
+
+    listen_opts = [
+      :binary,
+      {:packet, 4},
+      {:active, false},
+      {:ifaddr, {:local, String.to_charlist(socket_path)}}
+    ]
+
+    case :gen_tcp.listen(0, listen_opts) do
+      {:ok, listen_socket} ->
+        owner = self()
+        spawn_link(fn -> accept_loop(listen_socket, owner) end)
+        %{state | listen_socket: listen_socket, reconnect_idx: 0}
+
+      {:error, reason} ->
+        Logger.warning("failed to listen on #{socket_path}: #{inspect(reason)}")
+        emit_error(:listen_failed, state, %{reason: inspect(reason)})
+        schedule_reconnect(state)
+    end
+  end
+
   defp maybe_connect(%{command: nil} = state) do
     Logger.debug("ProjectionUI.HostBridge started without :command; port remains disconnected")
     state
@@ -173,16 +381,13 @@ defmodule ProjectionUI.HostBridge do
       port =
         Port.open(
           {:spawn_executable, state.command},
-          [
-            :binary,
-            {:packet, 4},
-            :exit_status,
-            :use_stdio,
-            :stderr_to_stdout,
-            args: state.args,
-            env: normalize_env(state.env),
-            cd: state.cd
-          ]
+          [:binary, {:packet, 4}, :exit_status] ++
+            stdio_port_opts(state.transport) ++
+            [
+              args: state.args,
+              env: normalize_env(transport_env(state.transport) ++ state.env),
+              cd: state.cd
+            ]
         )
 
       %{state | port: port, reconnect_idx: 0}
@@ -194,7 +399,7 @@ defmodule ProjectionUI.HostBridge do
     end
   end
 
-  defp schedule_reconnect(%{command: nil} = state), do: state
+  defp schedule_reconnect(%{command: nil, socket_path: nil} = state), do: state
 
   defp schedule_reconnect(state) do
     idx = min(state.reconnect_idx, length(@backoff_steps_ms) - 1)
@@ -207,6 +412,130 @@ defmodule ProjectionUI.HostBridge do
     %{state | reconnect_idx: min(idx + 1, length(@backoff_steps_ms) - 1)}
   end
 
+  defp accept_loop(listen_socket, owner) do
+    case :gen_tcp.accept(listen_socket) do
+      {:ok, socket} ->
+        case :gen_tcp.controlling_process(socket, owner) do
+          :ok -> send(owner, {:socket_accepted, socket})
+          {:error, _reason} -> :gen_tcp.close(socket)
+        end
+
+        accept_loop(listen_socket, owner)
+
+      {:error, :closed} ->
+        :ok
+
+      {:error, reason} ->
+        exit({:accept_failed, reason})
+    end
+  end
+
+  defp close_socket(nil), do: :ok
+  defp close_socket(socket), do: :gen_tcp.close(socket)
+
+  defp stdio_port_opts(:fd), do: [:nouse_stdio]
+  defp stdio_port_opts(:stdio), do: [:use_stdio, :stderr_to_stdout]
+
+  defp transport_env(:fd), do: [{"PROJECTION_TRANSPORT", "fd"}]
+  defp transport_env(:stdio), do: []
+
+  defp expected_transport_name(%{socket_path: socket_path}) when is_binary(socket_path),
+    do: "unix-packet-4"
+
+  defp expected_transport_name(%{transport: :fd}), do: "fd-packet-4"
+  defp expected_transport_name(%{transport: :stdio}), do: "stdio-packet-4"
+
+  defp verify_host_transport(
+         %{"t" => "ready", "capabilities" => %{"transport" => reported}},
+         state
+       )
+       when is_binary(reported) do
+    expected = expected_transport_name(state)
+
+    if reported != expected do
+      Logger.warning("ui_host transport mismatch: expected #{expected}, host reported #{reported}")
+
+      emit_error(:transport_mismatch, state, %{expected: expected, reported: reported})
+    end
+
+    :ok
+  end
+
+  defp verify_host_transport(_envelope, _state), do: :ok
+
+  # The host sends a diagnostic alongside every resync it requests.
+  defp maybe_report_diagnostic(%{"t" => "diagnostic", "code" => code} = envelope, state)
+       when is_binary(code) do
+    Logger.warning(
+      "ui_host diagnostic #{code}: #{envelope["message"]}" <>
+        " rev=#{inspect(envelope["rev"])} path=#{inspect(envelope["path"])}"
+    )
+
+    Telemetry.execute(@event_diagnostic, %{count: 1}, %{
+      sid: state.sid,
+      rev: envelope["rev"],
+      screen: "host_bridge",
+      code: code,
+      message: envelope["message"],
+      path: envelope["path"]
+    })
+  end
+
+  defp maybe_report_diagnostic(_envelope, _state), do: :ok
+
+  # A record names its session itself; records about the connection carry no sid.
+  defp log_host_record(envelope) do
+    fields = if is_map(envelope["fields"]), do: envelope["fields"], else: %{}
+    Logger.metadata(sid: envelope["sid"], rev: nil, screen: "host_bridge")
+
+    Logger.log(
+      host_log_level(envelope["level"]),
+      fn -> "ui_host #{envelope["target"]}: #{envelope["message"]}" end,
+      host_target: envelope["target"],
+      host_fields: fields
+    )
+  end
+
+  defp host_log_level("error"), do: :error
+  defp host_log_level("warn"), do: :warning
+  defp host_log_level("info"), do: :info
+  defp host_log_level(_debug_or_trace), do: :debug
+
+  # Hosts that advertise a protocol version expect an `accept` before any render.
+  defp maybe_accept(%{"t" => "ready"} = envelope, state) do
+    case Protocol.host_protocol_version(envelope) do
+      nil ->
+        state
+
+      version ->
+        if version != Protocol.protocol_version() do
+          Logger.error(
+            "ui_host protocol version mismatch: bridge speaks #{Protocol.protocol_version()}, host speaks #{version}"
+          )
+
+          emit_error(:protocol_version_mismatch, state, %{
+            expected: Protocol.protocol_version(),
+            reported: version
+          })
+        end
+
+        accept =
+          Protocol.accept_envelope(Map.get(envelope, "sid", state.sid), envelope,
+            intent_policies: state.intent_policies
+          )
+
+        dispatch_to_port(accept, state)
+    end
+  end
+
+  defp maybe_accept(_envelope, state), do: state
+
+  defp normalize_transport(transport) when transport in [:stdio, :fd], do: transport
+
+  defp normalize_transport(other) do
+    raise ArgumentError, "expected :transport to be :stdio or :fd, got: #{inspect(other)}"
+  end
+
   defp normalize_env(env) do
     Enum.map(env, fn {key, value} ->
       {to_charlist(key), to_charlist(value)}
@@ -218,7 +547,10 @@ defmodule ProjectionUI.HostBridge do
 
     state = dispatch_to_port(Protocol.error_envelope(state.sid, nil, code, message), state)
 
-    Session.handle_ui_envelope(state.session, %{"t" => "ready", "sid" => state.sid})
+    Enum.each(routed_sessions(state), fn {sid, session} ->
+      Session.handle_ui_envelope(session, %{"t" => "ready", "sid" => sid})
+    end)
+
     state
   end
 
@@ -226,20 +558,36 @@ defmodule ProjectionUI.HostBridge do
     do: {"frame_too_large", "inbound frame exceeds ui_to_elixir cap"}
 
   defp decode_error_details(:decode_error),
-    do: {"decode_error", "malformed inbound json payload"}
+    do: {"decode_error", "malformed inbound payload"}
 
   defp decode_error_details(:invalid_envelope),
-    do: {"invalid_envelope", "inbound payload must decode to a json object"}
+    do: {"invalid_envelope", "inbound payload must decode to an object"}
 
   defp decode_error_details(other),
     do: {"decode_error", "inbound decode failed: #{inspect(other)}"}
 
+  # With several sessions the bridge keeps the sid it started with for its own envelopes.
+  defp maybe_track_sid_from_envelope(_envelope, %{sessions: sessions} = state)
+       when map_size(sessions) > 0,
+       do: state
+
   defp maybe_track_sid_from_envelope(%{"sid" => sid}, state) when is_binary(sid) and sid != "" do
     %{state | sid: sid}
   end
 
   defp maybe_track_sid_from_envelope(_envelope, state), do: state
 
+  defp maybe_track_compression(state, %{"t" => "ready"} = envelope) do
+    %{
+      state
+      | codec: Protocol.codec(envelope),
+        compress: Protocol.compression_supported?(envelope),
+        chunk_limit: Protocol.chunk_limit(envelope)
+    }
+  end
+
+  defp maybe_track_compression(state, _envelope), do: state
+
   defp normalize_sid(sid) when is_binary(sid) and sid != "", do: sid
   defp normalize_sid(_sid), do: "S1"
 
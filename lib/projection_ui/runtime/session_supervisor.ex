@@ -3,6 +3,10 @@ defmodule ProjectionUI.SessionSupervisor do
   Supervises one authoritative `Projection.Session` and its `ProjectionUI.HostBridge`.
 
   Strategy is `:rest_for_one` to ensure port restarts follow session restarts.
+
+  With `:sids`, one `ui_host` serves several sessions: a session is started per
+  sid, registered as `Module.concat(session_name, sid)`, the host is told the
+  sids through `PROJECTION_SIDS`, and the bridge routes envelopes by sid.
   """
 
   use Supervisor
@@ -16,8 +20,13 @@ defmodule ProjectionUI.SessionSupervisor do
 
     * `:name` — supervisor name
     * `:session_name` — registered name for the session (default: `Projection.Session`)
+    * `:sids` — sids of the sessions one host serves, e.g. `["left", "right"]`; replaces `:sid`
     * `:host_bridge_name` — registered name for the bridge (default: `ProjectionUI.HostBridge`)
     * `:command` — path to the UI host executable
+    * `:socket_path` — unix socket the bridge listens on for an externally started host
+    * `:transport` — `:stdio` (default) or `:fd` for the port protocol channel
+    * `:intent_policies` — per-intent coalescing the host applies before sending,
+      e.g. `%{"volume.changed" => {:throttle, 50}}`
 
   You must provide either `:router` or `:screen_module`.
 
@@ -31,6 +40,7 @@ defmodule ProjectionUI.SessionSupervisor do
   def init(opts) do
     session_name = Keyword.get(opts, :session_name, Projection.Session)
     host_bridge_name = Keyword.get(opts, :host_bridge_name, ProjectionUI.HostBridge)
+    sids = Keyword.get(opts, :sids)
 
     router = Keyword.get(opts, :router)
     route = Keyword.get(opts, :route)
@@ -42,8 +52,6 @@ defmodule ProjectionUI.SessionSupervisor do
 
     session_opts =
       [
-        name: session_name,
-        sid: Keyword.get(opts, :sid),
         tick_ms: Keyword.get(opts, :tick_ms),
         host_bridge: host_bridge_name
       ]
@@ -54,19 +62,25 @@ defmodule ProjectionUI.SessionSupervisor do
       |> maybe_put(:screen_session, screen_session)
       |> maybe_put(:subscription_hook, subscription_hook)
 
-    children = [
-      {Projection.Session, session_opts},
-      {ProjectionUI.HostBridge,
-       [
-         name: host_bridge_name,
-         session: session_name,
-         sid: Keyword.get(opts, :sid, "S1"),
-         command: Keyword.get(opts, :command),
-         args: Keyword.get(opts, :args, []),
-         env: Keyword.get(opts, :env, []),
-         cd: Keyword.get(opts, :cd, File.cwd!())
-       ]}
-    ]
+    {session_children, session_routing} =
+      session_children(sids, session_name, session_opts, opts)
+
+    children =
+      session_children ++
+        [
+          {ProjectionUI.HostBridge,
+           session_routing ++
+             [
+               name: host_bridge_name,
+               command: Keyword.get(opts, :command),
+               socket_path: Keyword.get(opts, :socket_path),
+               transport: Keyword.get(opts, :transport, :stdio),
+               args: Keyword.get(opts, :args, []),
+               env: Keyword.get(opts, :env, []) ++ sids_env(sids),
+               cd: Keyword.get(opts, :cd, File.cwd!()),
+               intent_policies: Keyword.get(opts, :intent_policies, %{})
+             ]}
+        ]
 
     Supervisor.init(children,
       strategy: :rest_for_one,
@@ -75,6 +89,29 @@ defmodule ProjectionUI.SessionSupervisor do
     )
   end
 
+  defp session_children(nil, session_name, session_opts, opts) do
+    session_opts = [name: session_name, sid: Keyword.get(opts, :sid)] ++ session_opts
+    routing = [session: session_name, sid: Keyword.get(opts, :sid, "S1")]
+    {[{Projection.Session, session_opts}], routing}
+  end
+
+  defp session_children([_ | _] = sids, session_name, session_opts, _opts) do
+    names = Map.new(sids, fn sid -> {sid, Module.concat(session_name, sid)} end)
+
+    children =
+      Enum.map(sids, fn sid ->
+        Supervisor.child_spec(
+          {Projection.Session, [name: names[sid], sid: sid] ++ session_opts},
+          id: {Projection.Session, sid}
+        )
+      end)
+
+    {children, [sessions: names, sid: hd(sids)]}
+  end
+
+  defp sids_env(nil), do: []
+  defp sids_env(sids), do: [{"PROJECTION_SIDS", Enum.join(sids, ",")}]
+
   defp maybe_put(opts, _key, nil), do: opts
   defp maybe_put(opts, key, value), do: Keyword.put(opts, key, value)
 
@@ -43,7 +43,7 @@ defmodule ProjectionUI.Schema do
   defmacro __using__(opts) do
     owner = Keyword.get(opts, :owner)
 
-    if owner in [:screen, :component] do
+    if owner in [:screen, :component, :window] do
       Module.put_attribute(__CALLER__.module, :projection_schema_owner, owner)
     end
 
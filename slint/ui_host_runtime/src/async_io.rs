@@ -0,0 +1,196 @@
+//! Tokio counterparts of the transports and of `reader_loop`/`writer_loop`,
+//! for hosts whose app code already runs on a tokio runtime.
+
+use crate::outbound::OutboundReceiver;
+use crate::protocol::{
+    Codec, ELIXIR_TO_UI_CAP, ElixirEnvelope, InboundFrames, UiEnvelope, encode_envelope_frame,
+    frame_len,
+};
+use std::future::Future;
+use std::io;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Async byte channel carrying the framed protocol, like `Transport`.
+pub trait AsyncTransport: Send + 'static {
+    type Reader: AsyncRead + Unpin + Send + 'static;
+    type Writer: AsyncWrite + Unpin + Send + 'static;
+
+    fn connect(&mut self) -> impl Future<Output = io::Result<(Self::Reader, Self::Writer)>> + Send;
+
+    /// Advertised as `capabilities.transport` in the `ready` envelope.
+    fn name(&self) -> &'static str;
+
+    /// Whether the runtime should call `connect` again after the connection drops.
+    fn reconnects(&self) -> bool {
+        false
+    }
+}
+
+/// Frames over the process stdin/stdout, as spawned by an OTP port.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioStdioTransport;
+
+impl AsyncTransport for TokioStdioTransport {
+    type Reader = tokio::io::Stdin;
+    type Writer = tokio::io::Stdout;
+
+    async fn connect(&mut self) -> io::Result<(Self::Reader, Self::Writer)> {
+        Ok((tokio::io::stdin(), tokio::io::stdout()))
+    }
+
+    fn name(&self) -> &'static str {
+        "stdio-packet-4"
+    }
+}
+
+/// Frames over a unix domain socket served by an Elixir-side listener;
+/// reconnects when the socket drops.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct TokioUnixSocketTransport {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl TokioUnixSocketTransport {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(unix)]
+impl AsyncTransport for TokioUnixSocketTransport {
+    type Reader = tokio::net::unix::OwnedReadHalf;
+    type Writer = tokio::net::unix::OwnedWriteHalf;
+
+    async fn connect(&mut self) -> io::Result<(Self::Reader, Self::Writer)> {
+        let stream = tokio::net::UnixStream::connect(&self.path).await?;
+        Ok(stream.into_split())
+    }
+
+    fn name(&self) -> &'static str {
+        "unix-packet-4"
+    }
+
+    fn reconnects(&self) -> bool {
+        true
+    }
+}
+
+/// Reads frames until EOF, like `reader_loop`.
+pub async fn reader_loop_async<F, D>(
+    mut reader: impl AsyncRead + Unpin,
+    mut on_envelope: F,
+    mut on_discard: D,
+) -> io::Result<()>
+where
+    F: FnMut(ElixirEnvelope) -> io::Result<()>,
+    D: FnMut(io::Error),
+{
+    let mut frames = InboundFrames::new();
+
+    loop {
+        let mut len_buf = [0_u8; 4];
+        match reader.read_exact(&mut len_buf).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+
+        let len = match frame_len(len_buf, ELIXIR_TO_UI_CAP) {
+            Ok(len) => len,
+            Err(err) => {
+                let skip = u64::from(u32::from_be_bytes(len_buf));
+                tokio::io::copy(&mut (&mut reader).take(skip), &mut tokio::io::sink()).await?;
+                on_discard(err);
+                continue;
+            }
+        };
+        let mut frame = vec![0_u8; len];
+        reader.read_exact(&mut frame).await?;
+
+        if let Some(envelope) = frames.next_envelope(frame, &mut on_discard) {
+            on_envelope(envelope)?;
+        }
+    }
+}
+
+/// Writes queued envelopes until every sender is gone, like `writer_loop`.
+pub async fn writer_loop_async(
+    mut writer: impl AsyncWrite + Unpin,
+    rx: &OutboundReceiver,
+) -> io::Result<()> {
+    while let Some(envelope) = rx.recv().await {
+        write_envelope_async(&mut writer, &envelope, Codec::Json).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn write_envelope_async(
+    writer: &mut (impl AsyncWrite + Unpin),
+    envelope: &UiEnvelope,
+    codec: Codec,
+) -> io::Result<()> {
+    writer
+        .write_all(&encode_envelope_frame(envelope, codec)?)
+        .await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outbound::outbound_queue;
+    use crate::protocol::{ready_envelope, write_envelope};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn async_loops_speak_the_same_frames_as_the_blocking_ones() {
+        let (tx, rx) = outbound_queue(4, 4);
+        tx.try_send(ready_envelope("S1".to_string(), "stdio-packet-4"))
+            .expect("ready");
+        drop(tx);
+
+        let mut written = Vec::new();
+        writer_loop_async(&mut written, &rx).await.expect("write");
+
+        let mut expected = Vec::new();
+        write_envelope(
+            &mut expected,
+            &ready_envelope("S1".to_string(), "stdio-packet-4"),
+        )
+        .expect("blocking write");
+        assert_eq!(written, expected);
+
+        let render = serde_json::to_vec(&json!({
+            "t": "render", "sid": "S1", "rev": 1, "vm": { "clock_text": "10:42:17" }
+        }))
+        .expect("render json");
+        let mut inbound = (render.len() as u32).to_be_bytes().to_vec();
+        inbound.extend_from_slice(&render);
+
+        let mut received = Vec::new();
+        reader_loop_async(
+            inbound.as_slice(),
+            |envelope| {
+                received.push(envelope);
+                Ok(())
+            },
+            |err| panic!("unexpected discard: {err}"),
+        )
+        .await
+        .expect("read");
+
+        assert!(matches!(
+            received.as_slice(),
+            [ElixirEnvelope::Render { rev: 1, .. }]
+        ));
+    }
+}
@@ -0,0 +1,312 @@
+use crate::protocol::ELIXIR_TO_UI_CAP;
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// First byte of a chunk frame. `0xC1` is never used by MessagePack and cannot
+/// start a JSON or compressed payload, so chunks are told apart from whole frames.
+pub const CHUNK_MARKER: u8 = 0xC1;
+/// Marker, then message id, chunk index and chunk count as big-endian `u32`s.
+pub const CHUNK_HEADER_LEN: usize = 13;
+
+pub const MAX_REASSEMBLED_PAYLOAD: usize = 16 * ELIXIR_TO_UI_CAP;
+pub const MAX_CHUNKS_PER_MESSAGE: u32 = 1_024;
+pub const MAX_PENDING_MESSAGES: usize = 4;
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn is_chunk(frame: &[u8]) -> bool {
+    frame.first() == Some(&CHUNK_MARKER)
+}
+
+/// Splits `payload` into chunk frames carrying at most `max_frame` bytes each,
+/// header included.
+pub fn split_into_chunks(payload: &[u8], message_id: u32, max_frame: usize) -> Vec<Vec<u8>> {
+    let data_len = max_frame.saturating_sub(CHUNK_HEADER_LEN).max(1);
+    let count = payload.len().div_ceil(data_len).max(1) as u32;
+
+    (0..count)
+        .map(|index| {
+            let start = index as usize * data_len;
+            let end = (start + data_len).min(payload.len());
+            let mut frame = Vec::with_capacity(CHUNK_HEADER_LEN + end - start);
+            frame.push(CHUNK_MARKER);
+            frame.extend_from_slice(&message_id.to_be_bytes());
+            frame.extend_from_slice(&index.to_be_bytes());
+            frame.extend_from_slice(&count.to_be_bytes());
+            frame.extend_from_slice(&payload[start..end]);
+            frame
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct PartialMessage {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: u32,
+    bytes: usize,
+    last_chunk_at: Instant,
+}
+
+/// Collects chunk frames until a message is complete.
+///
+/// Memory stays bounded: at most `MAX_PENDING_MESSAGES` messages are held at
+/// once (the oldest is abandoned to make room), each capped at
+/// `MAX_REASSEMBLED_PAYLOAD` bytes, and messages that stop receiving chunks for
+/// `REASSEMBLY_TIMEOUT` are dropped.
+#[derive(Debug)]
+pub struct Reassembler {
+    pending: HashMap<u32, PartialMessage>,
+    max_payload: usize,
+    max_pending: usize,
+    timeout: Duration,
+    abandoned: usize,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Self::with_limits(
+            MAX_REASSEMBLED_PAYLOAD,
+            MAX_PENDING_MESSAGES,
+            REASSEMBLY_TIMEOUT,
+        )
+    }
+
+    pub fn with_limits(max_payload: usize, max_pending: usize, timeout: Duration) -> Self {
+        Self {
+            pending: HashMap::new(),
+            max_payload,
+            max_pending: max_pending.max(1),
+            timeout,
+            abandoned: 0,
+        }
+    }
+
+    pub fn pending_messages(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Messages abandoned since the last call, whose envelopes are lost.
+    pub fn take_abandoned(&mut self) -> usize {
+        std::mem::take(&mut self.abandoned)
+    }
+
+    /// Feeds one chunk frame. Returns the reassembled payload once its last
+    /// chunk arrives. Malformed chunks and oversized messages are errors.
+    pub fn push(&mut self, frame: &[u8], now: Instant) -> io::Result<Option<Vec<u8>>> {
+        self.abandon_expired(now);
+
+        let (message_id, index, count) = parse_header(frame)?;
+        let data = &frame[CHUNK_HEADER_LEN..];
+
+        if !self.pending.contains_key(&message_id) {
+            if self.pending.len() >= self.max_pending {
+                self.abandon_oldest();
+            }
+
+            self.pending.insert(
+                message_id,
+                PartialMessage {
+                    chunks: vec![None; count as usize],
+                    received: 0,
+                    bytes: 0,
+                    last_chunk_at: now,
+                },
+            );
+        }
+
+        let partial = self
+            .pending
+            .get_mut(&message_id)
+            .expect("partial message was just inserted");
+
+        if partial.chunks.len() != count as usize {
+            self.pending.remove(&message_id);
+            return Err(invalid_chunk(format!(
+                "chunk count changed mid-message for id {message_id}"
+            )));
+        }
+
+        let slot = &mut partial.chunks[index as usize];
+        if slot.is_some() {
+            self.pending.remove(&message_id);
+            return Err(invalid_chunk(format!(
+                "duplicate chunk {index} for id {message_id}"
+            )));
+        }
+
+        partial.bytes += data.len();
+        if partial.bytes > self.max_payload {
+            self.pending.remove(&message_id);
+            return Err(invalid_chunk(format!(
+                "chunked message {message_id} exceeds {}",
+                self.max_payload
+            )));
+        }
+
+        *slot = Some(data.to_vec());
+        partial.received += 1;
+        partial.last_chunk_at = now;
+
+        if partial.received < count {
+            return Ok(None);
+        }
+
+        let partial = self
+            .pending
+            .remove(&message_id)
+            .expect("complete message is pending");
+        let mut payload = Vec::with_capacity(partial.bytes);
+        for chunk in partial.chunks.into_iter().flatten() {
+            payload.extend_from_slice(&chunk);
+        }
+
+        Ok(Some(payload))
+    }
+
+    /// Drops messages that stopped receiving chunks. `push` does this too;
+    /// calling it for other frames notices a message whose chunks never resumed.
+    pub fn abandon_expired(&mut self, now: Instant) {
+        let timeout = self.timeout;
+        let before = self.pending.len();
+        self.pending.retain(|message_id, partial| {
+            let expired = now.saturating_duration_since(partial.last_chunk_at) >= timeout;
+            if expired {
+                log::warn!(
+                    "abandoning chunked message {message_id} after {timeout:?} ({}/{} chunks)",
+                    partial.received,
+                    partial.chunks.len()
+                );
+            }
+            !expired
+        });
+        self.abandoned += before - self.pending.len();
+    }
+
+    fn abandon_oldest(&mut self) {
+        let oldest = self
+            .pending
+            .iter()
+            .min_by_key(|(_, partial)| partial.last_chunk_at)
+            .map(|(message_id, _)| *message_id);
+
+        if let Some(message_id) = oldest {
+            log::warn!("abandoning chunked message {message_id}: too many messages in flight");
+            self.pending.remove(&message_id);
+            self.abandoned += 1;
+        }
+    }
+}
+
+fn parse_header(frame: &[u8]) -> io::Result<(u32, u32, u32)> {
+    if frame.len() < CHUNK_HEADER_LEN || !is_chunk(frame) {
+        return Err(invalid_chunk("truncated chunk header".to_string()));
+    }
+
+    let read_u32 =
+        |at: usize| u32::from_be_bytes([frame[at], frame[at + 1], frame[at + 2], frame[at + 3]]);
+    let (message_id, index, count) = (read_u32(1), read_u32(5), read_u32(9));
+
+    if count == 0 || count > MAX_CHUNKS_PER_MESSAGE {
+        return Err(invalid_chunk(format!(
+            "chunk count {count} out of range for id {message_id}"
+        )));
+    }
+
+    if index >= count {
+        return Err(invalid_chunk(format!(
+            "chunk index {index} out of range for id {message_id}"
+        )));
+    }
+
+    Ok((message_id, index, count))
+}
+
+fn invalid_chunk(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reassembles_chunks_in_any_order() {
+        let payload: Vec<u8> = (0..100_u8).collect();
+        let mut chunks = split_into_chunks(&payload, 9, CHUNK_HEADER_LEN + 30);
+        assert_eq!(chunks.len(), 4);
+        chunks.swap(0, 3);
+
+        let mut reassembler = Reassembler::new();
+        let now = Instant::now();
+        let mut completed = None;
+        for chunk in &chunks {
+            assert!(completed.is_none());
+            completed = reassembler.push(chunk, now).expect("valid chunk");
+        }
+
+        assert_eq!(completed, Some(payload));
+        assert_eq!(reassembler.pending_messages(), 0);
+    }
+
+    #[test]
+    fn stale_messages_are_abandoned_after_timeout() {
+        let chunks = split_into_chunks(b"abcd", 1, CHUNK_HEADER_LEN + 2);
+        let mut reassembler = Reassembler::with_limits(1_024, 4, Duration::from_secs(1));
+        let start = Instant::now();
+
+        assert_eq!(reassembler.push(&chunks[0], start).expect("first"), None);
+        assert_eq!(reassembler.pending_messages(), 1);
+
+        let late = reassembler
+            .push(&chunks[1], start + Duration::from_secs(2))
+            .expect("late chunk starts over");
+        assert_eq!(late, None);
+        assert_eq!(reassembler.pending_messages(), 1);
+        assert_eq!(reassembler.take_abandoned(), 1);
+        assert_eq!(reassembler.take_abandoned(), 0);
+    }
+
+    #[test]
+    fn pending_messages_and_sizes_are_bounded() {
+        let mut reassembler = Reassembler::with_limits(8, 2, REASSEMBLY_TIMEOUT);
+        let now = Instant::now();
+
+        for message_id in 0..3 {
+            let chunks = split_into_chunks(b"abcd", message_id, CHUNK_HEADER_LEN + 2);
+            reassembler.push(&chunks[0], now).expect("partial chunk");
+        }
+        assert_eq!(reassembler.pending_messages(), 2);
+        assert_eq!(reassembler.take_abandoned(), 1);
+
+        let oversized = split_into_chunks(b"0123456789", 7, CHUNK_HEADER_LEN + 5);
+        reassembler
+            .push(&oversized[0], now)
+            .expect("first half fits");
+        let err = reassembler
+            .push(&oversized[1], now)
+            .expect_err("message over the reassembly cap");
+        assert!(err.to_string().contains("exceeds 8"));
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        let mut reassembler = Reassembler::new();
+        let now = Instant::now();
+
+        let err = reassembler
+            .push(&[CHUNK_MARKER, 0, 0], now)
+            .expect_err("truncated header");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut frame = split_into_chunks(b"abc", 1, 64).remove(0);
+        frame[5..9].copy_from_slice(&5_u32.to_be_bytes());
+        let err = reassembler.push(&frame, now).expect_err("index past count");
+        assert!(err.to_string().contains("index 5"));
+    }
+}
@@ -0,0 +1,328 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How bursts of one intent name are merged before they reach Elixir.
+///
+/// Declared by the app through `HostBindings::intent_policy` or by the server
+/// in `accept.intent_policies`; the app wins. Modes this host does not know
+/// decode as `Immediate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum IntentPolicy {
+    /// Events within one turn of the UI event loop collapse into the last one.
+    LatestWins,
+    /// Sent once no new event arrived for `interval_ms`, with the last payload.
+    Debounce { interval_ms: u64 },
+    /// At most one event per `interval_ms`: the first goes out at once, the
+    /// last one of the window follows when it closes.
+    Throttle { interval_ms: u64 },
+    /// Every event is sent as it happens.
+    #[default]
+    #[serde(other)]
+    Immediate,
+}
+
+#[derive(Debug)]
+struct PendingIntent {
+    payload: Value,
+    due: Instant,
+    /// Order of the latest event folded in, so held intents go out in the
+    /// order they happened.
+    seq: u64,
+}
+
+/// Per-name coalescing state. Lives behind the intent callbacks and is
+/// flushed by a timer on the UI thread.
+#[derive(Debug)]
+pub struct IntentCoalescer {
+    host_policy: fn(&str) -> Option<IntentPolicy>,
+    server_policies: HashMap<String, IntentPolicy>,
+    pending: HashMap<String, PendingIntent>,
+    last_sent: HashMap<String, Instant>,
+    wake_at: Option<Instant>,
+    merged: u64,
+    next_seq: u64,
+}
+
+impl IntentCoalescer {
+    pub fn new(host_policy: fn(&str) -> Option<IntentPolicy>) -> Self {
+        Self {
+            host_policy,
+            server_policies: HashMap::new(),
+            pending: HashMap::new(),
+            last_sent: HashMap::new(),
+            wake_at: None,
+            merged: 0,
+            next_seq: 0,
+        }
+    }
+
+    pub fn set_server_policies(&mut self, policies: HashMap<String, IntentPolicy>) {
+        self.server_policies = policies;
+    }
+
+    pub fn policy(&self, name: &str) -> IntentPolicy {
+        (self.host_policy)(name)
+            .or_else(|| self.server_policies.get(name).copied())
+            .unwrap_or_default()
+    }
+
+    /// Number of events folded into a later one instead of being sent.
+    pub fn merged(&self) -> u64 {
+        self.merged
+    }
+
+    /// Returns the intents to send right away. An intent that is not held
+    /// goes out after every intent still held, so none overtakes an earlier
+    /// event; otherwise it is held (replacing any payload already held for
+    /// `name`) until `take_due` and nothing is returned.
+    pub fn submit(&mut self, name: &str, payload: Value, now: Instant) -> Vec<(String, Value)> {
+        let policy = self.policy(name);
+        let due = match policy {
+            IntentPolicy::Immediate => return self.send_after_held(name, payload, now),
+            IntentPolicy::LatestWins => now,
+            IntentPolicy::Debounce { interval_ms } => now + Duration::from_millis(interval_ms),
+            IntentPolicy::Throttle { interval_ms } => {
+                let window_ends = self
+                    .last_sent
+                    .get(name)
+                    .map(|sent_at| *sent_at + Duration::from_millis(interval_ms));
+
+                match window_ends {
+                    Some(window_ends) if window_ends > now => window_ends,
+                    _ if !self.pending.contains_key(name) => {
+                        let ready = self.send_after_held(name, payload, now);
+                        self.last_sent.insert(name.to_string(), now);
+                        return ready;
+                    }
+                    _ => now,
+                }
+            }
+        };
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let held = PendingIntent { payload, due, seq };
+        if let Some(previous) = self.pending.get_mut(name) {
+            self.merged += 1;
+            previous.payload = held.payload;
+            previous.seq = seq;
+            // A throttle window closes on schedule however many events arrive.
+            if !matches!(policy, IntentPolicy::Throttle { .. }) {
+                previous.due = held.due;
+            }
+        } else {
+            self.pending.insert(name.to_string(), held);
+        }
+
+        Vec::new()
+    }
+
+    /// Removes and returns the held intents that are due at `now`, oldest
+    /// first.
+    pub fn take_due(&mut self, now: Instant) -> Vec<(String, Value)> {
+        self.take_held(now, |pending| pending.due <= now)
+    }
+
+    fn send_after_held(
+        &mut self,
+        name: &str,
+        payload: Value,
+        now: Instant,
+    ) -> Vec<(String, Value)> {
+        let mut ready = self.take_held(now, |_| true);
+        ready.push((name.to_string(), payload));
+        ready
+    }
+
+    fn take_held(
+        &mut self,
+        now: Instant,
+        take: impl Fn(&PendingIntent) -> bool,
+    ) -> Vec<(String, Value)> {
+        let mut names: Vec<(u64, String)> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| take(pending))
+            .map(|(name, pending)| (pending.seq, name.clone()))
+            .collect();
+        names.sort_unstable();
+
+        names
+            .into_iter()
+            .filter_map(|(_, name)| {
+                let pending = self.pending.remove(&name)?;
+                self.last_sent.insert(name.clone(), now);
+                Some((name, pending.payload))
+            })
+            .collect()
+    }
+
+    /// Returns when the flush timer should fire next, if that is earlier than
+    /// a wake-up already scheduled.
+    pub fn schedule_wake(&mut self) -> Option<Instant> {
+        let next_due = self.pending.values().map(|pending| pending.due).min()?;
+        match self.wake_at {
+            Some(wake_at) if wake_at <= next_due => None,
+            _ => {
+                self.wake_at = Some(next_due);
+                Some(next_due)
+            }
+        }
+    }
+
+    pub fn woke(&mut self) {
+        self.wake_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policies(name: &str) -> Option<IntentPolicy> {
+        match name {
+            "slider.moved" => Some(IntentPolicy::Throttle { interval_ms: 100 }),
+            "search.changed" => Some(IntentPolicy::Debounce { interval_ms: 300 }),
+            "scroll.moved" => Some(IntentPolicy::LatestWins),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn latest_wins_keeps_the_last_payload_of_a_burst() {
+        let mut coalescer = IntentCoalescer::new(policies);
+        let now = Instant::now();
+
+        for offset in 0..5 {
+            assert_eq!(
+                coalescer.submit("scroll.moved", json!({ "arg": offset }), now),
+                vec![]
+            );
+        }
+
+        assert_eq!(coalescer.schedule_wake(), Some(now));
+        assert_eq!(
+            coalescer.take_due(now),
+            vec![("scroll.moved".to_string(), json!({ "arg": 4 }))]
+        );
+        assert_eq!(coalescer.merged(), 4);
+    }
+
+    #[test]
+    fn debounce_waits_for_quiet() {
+        let mut coalescer = IntentCoalescer::new(policies);
+        let start = Instant::now();
+
+        coalescer.submit("search.changed", json!({ "arg": "a" }), start);
+        coalescer.submit(
+            "search.changed",
+            json!({ "arg": "ab" }),
+            start + Duration::from_millis(200),
+        );
+
+        assert!(
+            coalescer
+                .take_due(start + Duration::from_millis(300))
+                .is_empty()
+        );
+        assert_eq!(
+            coalescer.take_due(start + Duration::from_millis(500)),
+            vec![("search.changed".to_string(), json!({ "arg": "ab" }))]
+        );
+    }
+
+    #[test]
+    fn throttle_sends_leading_and_trailing_values() {
+        let mut coalescer = IntentCoalescer::new(policies);
+        let start = Instant::now();
+
+        assert_eq!(
+            coalescer.submit("slider.moved", json!({ "arg": 1 }), start),
+            vec![("slider.moved".to_string(), json!({ "arg": 1 }))]
+        );
+        for (offset, value) in [(10, 2), (40, 3), (90, 4)] {
+            let at = start + Duration::from_millis(offset);
+            assert_eq!(
+                coalescer.submit("slider.moved", json!({ "arg": value }), at),
+                vec![]
+            );
+        }
+
+        assert_eq!(
+            coalescer.schedule_wake(),
+            Some(start + Duration::from_millis(100))
+        );
+        assert_eq!(
+            coalescer.take_due(start + Duration::from_millis(100)),
+            vec![("slider.moved".to_string(), json!({ "arg": 4 }))]
+        );
+
+        let later = start + Duration::from_millis(250);
+        assert_eq!(
+            coalescer.submit("slider.moved", json!({ "arg": 5 }), later),
+            vec![("slider.moved".to_string(), json!({ "arg": 5 }))]
+        );
+    }
+
+    #[test]
+    fn immediate_intents_follow_the_intents_held_before_them() {
+        let mut coalescer = IntentCoalescer::new(policies);
+        let start = Instant::now();
+
+        coalescer.submit("search.changed", json!({ "arg": "a" }), start);
+        coalescer.submit("scroll.moved", json!({ "arg": 1 }), start);
+        coalescer.submit("search.changed", json!({ "arg": "ab" }), start);
+
+        assert_eq!(
+            coalescer.submit("form.submit", json!({}), start),
+            vec![
+                ("scroll.moved".to_string(), json!({ "arg": 1 })),
+                ("search.changed".to_string(), json!({ "arg": "ab" })),
+                ("form.submit".to_string(), json!({})),
+            ]
+        );
+        assert_eq!(coalescer.schedule_wake(), None);
+        assert!(
+            coalescer
+                .take_due(start + Duration::from_secs(1))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn host_policy_overrides_server_declarations() {
+        let mut coalescer = IntentCoalescer::new(policies);
+        coalescer.set_server_policies(HashMap::from([
+            ("scroll.moved".to_string(), IntentPolicy::Immediate),
+            (
+                "volume.changed".to_string(),
+                IntentPolicy::Throttle { interval_ms: 50 },
+            ),
+        ]));
+
+        assert_eq!(coalescer.policy("scroll.moved"), IntentPolicy::LatestWins);
+        assert_eq!(
+            coalescer.policy("volume.changed"),
+            IntentPolicy::Throttle { interval_ms: 50 }
+        );
+        assert_eq!(coalescer.policy("clock.pause"), IntentPolicy::Immediate);
+    }
+
+    #[test]
+    fn server_policies_decode_with_unknown_modes_as_immediate() {
+        let policies: HashMap<String, IntentPolicy> = serde_json::from_value(json!({
+            "a": {"mode": "debounce", "interval_ms": 300},
+            "b": {"mode": "latest_wins"},
+            "c": {"mode": "sample_every_frame"}
+        }))
+        .expect("decode policies");
+
+        assert_eq!(policies["a"], IntentPolicy::Debounce { interval_ms: 300 });
+        assert_eq!(policies["b"], IntentPolicy::LatestWins);
+        assert_eq!(policies["c"], IntentPolicy::Immediate);
+    }
+}
@@ -0,0 +1,162 @@
+use std::time::{Duration, Instant};
+
+const DEFAULT_INTERVAL_MS: u64 = 5_000;
+const DEFAULT_STALE_AFTER_INTERVALS: u32 = 3;
+
+/// How often the host pings Elixir and how long it waits for any inbound
+/// message before it reports the connection as stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    pub interval: Duration,
+    pub stale_after: Duration,
+}
+
+impl HeartbeatConfig {
+    /// Reads `PROJECTION_HEARTBEAT_INTERVAL_MS` (default 5000, `0` disables) and
+    /// `PROJECTION_HEARTBEAT_STALE_AFTER_MS` (default three intervals).
+    pub fn from_env() -> Option<Self> {
+        let interval_ms = env_ms("PROJECTION_HEARTBEAT_INTERVAL_MS").unwrap_or(DEFAULT_INTERVAL_MS);
+        if interval_ms == 0 {
+            return None;
+        }
+
+        let interval = Duration::from_millis(interval_ms);
+        let stale_after = env_ms("PROJECTION_HEARTBEAT_STALE_AFTER_MS")
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis)
+            .unwrap_or(interval * DEFAULT_STALE_AFTER_INTERVALS);
+
+        Some(Self {
+            interval,
+            stale_after,
+        })
+    }
+}
+
+fn env_ms(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|raw| raw.parse().ok())
+}
+
+/// Tracks when the last inbound message arrived.
+///
+/// Staleness is only judged once the server accepted the heartbeat feature,
+/// since older servers never answer pings. Methods return `Some(stale)` when
+/// the stale state flips, so the caller can update the UI.
+#[derive(Debug)]
+pub struct Liveness {
+    enabled: bool,
+    stale: bool,
+    last_inbound: Instant,
+    next_nonce: u64,
+}
+
+impl Liveness {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            enabled: false,
+            stale: false,
+            last_inbound: now,
+            next_nonce: 1,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn enable(&mut self, now: Instant) {
+        self.enabled = true;
+        self.last_inbound = now;
+    }
+
+    pub fn touch(&mut self, now: Instant) -> Option<bool> {
+        self.last_inbound = now;
+        self.set_stale(false)
+    }
+
+    pub fn check(&mut self, now: Instant, stale_after: Duration) -> Option<bool> {
+        if !self.enabled || now.saturating_duration_since(self.last_inbound) < stale_after {
+            return None;
+        }
+
+        self.set_stale(true)
+    }
+
+    /// The screen keeps showing the last frame while the transport is down.
+    pub fn disconnected(&mut self) -> Option<bool> {
+        self.enabled = false;
+        self.set_stale(true)
+    }
+
+    pub fn next_nonce(&mut self) -> u64 {
+        let nonce = self.next_nonce;
+        self.next_nonce = self.next_nonce.wrapping_add(1);
+        nonce
+    }
+
+    fn set_stale(&mut self, stale: bool) -> Option<bool> {
+        if self.stale == stale {
+            None
+        } else {
+            self.stale = stale;
+            Some(stale)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goes_stale_only_after_heartbeat_is_enabled() {
+        let start = Instant::now();
+        let stale_after = Duration::from_secs(3);
+        let mut liveness = Liveness::new(start);
+
+        assert_eq!(
+            liveness.check(start + Duration::from_secs(10), stale_after),
+            None
+        );
+
+        liveness.enable(start + Duration::from_secs(10));
+        assert_eq!(
+            liveness.check(start + Duration::from_secs(12), stale_after),
+            None
+        );
+        assert_eq!(
+            liveness.check(start + Duration::from_secs(13), stale_after),
+            Some(true)
+        );
+        assert_eq!(
+            liveness.check(start + Duration::from_secs(14), stale_after),
+            None
+        );
+        assert!(liveness.is_stale());
+
+        assert_eq!(liveness.touch(start + Duration::from_secs(15)), Some(false));
+        assert_eq!(liveness.touch(start + Duration::from_secs(16)), None);
+    }
+
+    #[test]
+    fn disconnect_marks_stale_until_the_next_message() {
+        let start = Instant::now();
+        let mut liveness = Liveness::new(start);
+        liveness.enable(start);
+
+        assert_eq!(liveness.disconnected(), Some(true));
+        assert!(!liveness.is_enabled());
+        assert_eq!(liveness.touch(start), Some(false));
+    }
+
+    #[test]
+    fn nonces_increase() {
+        let mut liveness = Liveness::new(Instant::now());
+        assert_eq!(liveness.next_nonce(), 1);
+        assert_eq!(liveness.next_nonce(), 2);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::outbound::OutboundSender;
+use crate::protocol::log_envelope;
+use log::kv::{self, Key, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Value};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// Key of the record field naming the session a record is about. It becomes
+/// the envelope's `sid`; records without it are sent without one.
+pub const SID_KEY: &str = "sid";
+
+/// Target for records about the outbound queue itself. They stay on stderr:
+/// forwarding them would feed the queue they report on.
+pub const TRANSPORT_LOG_TARGET: &str = "projection::transport";
+
+const DEFAULT_RATE_PER_SEC: u32 = 20;
+
+/// Token bucket for forwarded records; stderr output is never limited.
+#[derive(Debug)]
+pub struct LogRateLimiter {
+    per_sec: f64,
+    tokens: f64,
+    refilled_at: Instant,
+    dropped: u64,
+}
+
+impl LogRateLimiter {
+    pub fn new(per_sec: u32, now: Instant) -> Self {
+        let per_sec = f64::from(per_sec.max(1));
+        Self {
+            per_sec,
+            tokens: per_sec,
+            refilled_at: now,
+            dropped: 0,
+        }
+    }
+
+    /// Returns `Some(dropped)` if a record may be sent now, with the number of
+    /// records dropped since the last one that was.
+    pub fn admit(&mut self, now: Instant) -> Option<u64> {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.per_sec).min(self.per_sec);
+        self.refilled_at = now;
+
+        if self.tokens < 1.0 {
+            self.dropped += 1;
+            return None;
+        }
+
+        self.tokens -= 1.0;
+        Some(std::mem::take(&mut self.dropped))
+    }
+
+    pub fn record_dropped(&mut self) {
+        self.dropped += 1;
+    }
+}
+
+struct Forwarding {
+    tx: OutboundSender,
+    enabled: bool,
+    limiter: LogRateLimiter,
+}
+
+/// `log` backend for the host. Every record goes to stderr; once the server
+/// accepted the `log` feature, records are also sent upstream as `log`
+/// envelopes through the writer queue.
+pub struct HostLogger {
+    level: LevelFilter,
+    rate_per_sec: u32,
+    forwarding: Mutex<Option<Forwarding>>,
+}
+
+static LOGGER: OnceLock<HostLogger> = OnceLock::new();
+static INSTALLED: OnceLock<bool> = OnceLock::new();
+
+/// Installs the host logger as the `log` backend, unless the app already set
+/// its own. Reads `PROJECTION_LOG_LEVEL` (default `info`) and
+/// `PROJECTION_LOG_RATE_PER_SEC` (default 20).
+pub fn install() -> bool {
+    *INSTALLED.get_or_init(|| {
+        let logger = LOGGER.get_or_init(|| HostLogger {
+            level: parse_level(),
+            rate_per_sec: parse_rate(),
+            forwarding: Mutex::new(None),
+        });
+
+        if log::set_logger(logger).is_err() {
+            return false;
+        }
+
+        log::set_max_level(logger.level);
+        true
+    })
+}
+
+/// Routes forwarded records through `tx`. Forwarding stays off until
+/// `set_forwarding(true)`.
+pub fn attach(tx: OutboundSender) {
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+
+    if let Ok(mut forwarding) = logger.forwarding.lock() {
+        *forwarding = Some(Forwarding {
+            tx,
+            enabled: false,
+            limiter: LogRateLimiter::new(logger.rate_per_sec, Instant::now()),
+        });
+    }
+}
+
+/// Releases the queue sender so the writer thread can finish.
+pub fn detach() {
+    if let Some(logger) = LOGGER.get()
+        && let Ok(mut forwarding) = logger.forwarding.lock()
+    {
+        *forwarding = None;
+    }
+}
+
+pub fn set_forwarding(enabled: bool) {
+    if let Some(logger) = LOGGER.get()
+        && let Ok(mut forwarding) = logger.forwarding.lock()
+        && let Some(forwarding) = forwarding.as_mut()
+    {
+        forwarding.enabled = enabled;
+    }
+}
+
+impl Log for HostLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        eprintln!("[{}] {}", record.level(), record.args());
+
+        if record.target() == TRANSPORT_LOG_TARGET {
+            return;
+        }
+
+        let Ok(mut guard) = self.forwarding.lock() else {
+            return;
+        };
+        let Some(forwarding) = guard.as_mut().filter(|forwarding| forwarding.enabled) else {
+            return;
+        };
+        let Some(dropped) = forwarding.limiter.admit(Instant::now()) else {
+            return;
+        };
+
+        let mut fields = record_fields(record);
+        let sid = match fields.remove(SID_KEY) {
+            Some(Value::String(sid)) => Some(sid),
+            _ => None,
+        };
+        if dropped > 0 {
+            fields.insert("dropped_before".to_string(), Value::from(dropped));
+        }
+
+        let envelope = log_envelope(
+            sid,
+            record.level().as_str().to_ascii_lowercase(),
+            record.target(),
+            record.args().to_string(),
+            fields,
+        );
+
+        if forwarding.tx.try_send(envelope).is_err() {
+            forwarding.limiter.record_dropped();
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn record_fields(record: &Record) -> Map<String, Value> {
+    let mut fields = Map::new();
+    let _ = record.key_values().visit(&mut FieldCollector(&mut fields));
+    fields
+}
+
+struct FieldCollector<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for FieldCollector<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.insert(key.to_string(), field_value(&value));
+        Ok(())
+    }
+}
+
+fn field_value(value: &kv::Value) -> Value {
+    if let Some(value) = value.to_bool() {
+        Value::Bool(value)
+    } else if let Some(value) = value.to_i64() {
+        Value::from(value)
+    } else if let Some(value) = value.to_u64() {
+        Value::from(value)
+    } else if let Some(number) = value.to_f64().and_then(serde_json::Number::from_f64) {
+        Value::Number(number)
+    } else {
+        Value::String(value.to_string())
+    }
+}
+
+fn parse_level() -> LevelFilter {
+    std::env::var("PROJECTION_LOG_LEVEL")
+        .ok()
+        .and_then(|raw| raw.parse().ok())
+        .unwrap_or(LevelFilter::Info)
+}
+
+fn parse_rate() -> u32 {
+    std::env::var("PROJECTION_LOG_RATE_PER_SEC")
+        .ok()
+        .and_then(|raw| raw.parse().ok())
+        .filter(|rate| *rate > 0)
+        .unwrap_or(DEFAULT_RATE_PER_SEC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn rate_limiter_drops_bursts_and_reports_the_gap() {
+        let start = Instant::now();
+        let mut limiter = LogRateLimiter::new(2, start);
+
+        assert_eq!(limiter.admit(start), Some(0));
+        assert_eq!(limiter.admit(start), Some(0));
+        assert_eq!(limiter.admit(start), None);
+        assert_eq!(limiter.admit(start), None);
+
+        assert_eq!(limiter.admit(start + Duration::from_millis(500)), Some(2));
+        assert_eq!(limiter.admit(start + Duration::from_millis(500)), None);
+    }
+
+    #[test]
+    fn structured_fields_keep_their_json_types() {
+        let kvs: [(&str, kv::Value); 4] = [
+            ("dropped", kv::Value::from(3_u64)),
+            ("ratio", kv::Value::from(0.5_f64)),
+            ("full", kv::Value::from(true)),
+            ("queue", kv::Value::from("intents")),
+        ];
+        let args = format_args!("queue full");
+        let record = Record::builder().args(args).key_values(&kvs).build();
+
+        assert_eq!(
+            Value::Object(record_fields(&record)),
+            serde_json::json!({"dropped": 3, "ratio": 0.5, "full": true, "queue": "intents"})
+        );
+    }
+}
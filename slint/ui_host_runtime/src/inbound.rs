@@ -0,0 +1,210 @@
+use crate::protocol::PatchOp;
+use log::debug;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// A render or patch waiting for the UI thread.
+#[derive(Debug)]
+pub enum Frame {
+    Render {
+        rev: u64,
+        ack: Option<u64>,
+        vm: Value,
+    },
+    /// `base_rev` is the revision of the first patch merged into the frame,
+    /// and `rev` that of the last.
+    Patch {
+        base_rev: u64,
+        rev: u64,
+        ack: Option<u64>,
+        resume_from: Option<u64>,
+        ops: Vec<PatchOp>,
+    },
+}
+
+/// Counts since the session started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InboundStats {
+    /// Patch envelopes received.
+    pub patches: u64,
+    /// Patches merged into the one before them instead of being applied alone.
+    pub coalesced: u64,
+}
+
+#[derive(Default)]
+struct Pending {
+    frames: VecDeque<Frame>,
+    drain_scheduled: bool,
+    stats: InboundStats,
+}
+
+/// Frames from the reader, drained by the UI thread. Only the last of a burst
+/// of patches is visible, so consecutive revisions are merged into one frame
+/// and the bindings run once for all of them.
+#[derive(Default)]
+pub struct InboundQueue {
+    pending: Mutex<Pending>,
+}
+
+impl InboundQueue {
+    /// Queues `frame`. Returns `true` when no drain is scheduled yet, and the
+    /// caller must schedule one on the UI thread.
+    pub fn push(&self, frame: Frame) -> bool {
+        let Ok(mut pending) = self.pending.lock() else {
+            return false;
+        };
+
+        if matches!(frame, Frame::Patch { .. }) {
+            pending.stats.patches += 1;
+        }
+        pending.frames.push_back(frame);
+
+        !std::mem::replace(&mut pending.drain_scheduled, true)
+    }
+
+    /// Called when the drain `push` asked for could not be scheduled. The
+    /// frames stay queued and the next `push` schedules again.
+    pub fn schedule_failed(&self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.drain_scheduled = false;
+        }
+    }
+
+    /// Takes every queued frame, merging each patch into the one before it
+    /// when their revisions follow each other. A patch that does not continue
+    /// the chain stays separate and fails revision validation on its own.
+    pub fn drain(&self) -> Vec<Frame> {
+        let Ok(mut pending) = self.pending.lock() else {
+            return Vec::new();
+        };
+
+        pending.drain_scheduled = false;
+        let queued = pending.frames.len();
+        let mut frames: Vec<Frame> = Vec::with_capacity(queued);
+
+        for frame in std::mem::take(&mut pending.frames) {
+            match (frames.last_mut(), frame) {
+                (
+                    Some(Frame::Patch {
+                        rev: last_rev,
+                        ack: last_ack,
+                        ops: last_ops,
+                        ..
+                    }),
+                    Frame::Patch {
+                        rev,
+                        ack,
+                        resume_from: None,
+                        ops,
+                        ..
+                    },
+                ) if rev == last_rev.wrapping_add(1) => {
+                    *last_rev = rev;
+                    *last_ack = (*last_ack).max(ack);
+                    last_ops.extend(ops);
+                    pending.stats.coalesced += 1;
+                }
+                (_, frame) => frames.push(frame),
+            }
+        }
+
+        if frames.len() < queued {
+            let InboundStats { patches, coalesced } = pending.stats;
+            debug!(
+                patches, coalesced;
+                "merged {queued} queued frame(s) into {}", frames.len()
+            );
+        }
+
+        frames
+    }
+
+    pub fn stats(&self) -> InboundStats {
+        self.pending
+            .lock()
+            .map(|pending| pending.stats)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn patch(rev: u64, ack: Option<u64>, value: u64) -> Frame {
+        Frame::Patch {
+            base_rev: rev,
+            rev,
+            ack,
+            resume_from: None,
+            ops: serde_json::from_value(json!([
+                { "op": "replace", "path": "/screen/vm/count", "value": value }
+            ]))
+            .expect("ops"),
+        }
+    }
+
+    #[test]
+    fn consecutive_patches_drain_as_one_frame() {
+        let queue = InboundQueue::default();
+
+        assert!(queue.push(patch(2, Some(1), 2)));
+        assert!(!queue.push(patch(3, None, 3)));
+        assert!(!queue.push(patch(4, Some(4), 4)));
+        // Skips rev 5, so it must be validated on its own.
+        assert!(!queue.push(patch(6, None, 6)));
+
+        let frames = queue.drain();
+        assert!(matches!(
+            frames.as_slice(),
+            [
+                Frame::Patch { base_rev: 2, rev: 4, ack: Some(4), ops, .. },
+                Frame::Patch { base_rev: 6, rev: 6, .. },
+            ] if ops.len() == 3
+        ));
+        assert_eq!(
+            queue.stats(),
+            InboundStats {
+                patches: 4,
+                coalesced: 2
+            }
+        );
+
+        assert!(queue.push(patch(7, None, 7)), "a drain clears the schedule");
+    }
+
+    #[test]
+    fn renders_and_resuming_patches_start_a_new_frame() {
+        let queue = InboundQueue::default();
+
+        queue.push(patch(1, None, 1));
+        queue.push(Frame::Render {
+            rev: 2,
+            ack: None,
+            vm: json!({}),
+        });
+        queue.push(patch(3, None, 3));
+        queue.push(Frame::Patch {
+            base_rev: 4,
+            rev: 4,
+            ack: None,
+            resume_from: Some(2),
+            ops: Vec::new(),
+        });
+
+        assert_eq!(queue.drain().len(), 4);
+        assert_eq!(queue.stats().coalesced, 0);
+    }
+
+    #[test]
+    fn a_failed_schedule_is_retried_by_the_next_push() {
+        let queue = InboundQueue::default();
+
+        assert!(queue.push(patch(1, None, 1)));
+        queue.schedule_failed();
+        assert!(queue.push(patch(2, None, 2)));
+        assert_eq!(queue.drain().len(), 1, "the first frame is kept");
+    }
+}
@@ -0,0 +1,154 @@
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// What happens to an intent the server never received once the host resynced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IntentReplay {
+    /// Sent again, under a new id, once the fresh render is on screen.
+    #[default]
+    Replay,
+    /// Dropped and reported to the server in an `intents_discarded` diagnostic.
+    Discard,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnackedIntent {
+    pub id: u64,
+    pub name: String,
+    pub payload: Value,
+}
+
+/// Intents queued for the server and not yet covered by an `ack`.
+///
+/// A new connection writes off everything sent before it: the writer skips
+/// written-off intents still in the queue, and the `ack` of the next render
+/// tells which of the rest the server processed. `take_lost` hands back the
+/// others. A resync on a live connection writes nothing off, since the
+/// intents behind its `ready` still arrive.
+#[derive(Debug)]
+pub struct IntentLog {
+    capacity: usize,
+    entries: VecDeque<UnackedIntent>,
+    written_off: Arc<AtomicU64>,
+    replay_enabled: bool,
+}
+
+impl IntentLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: VecDeque::new(),
+            written_off: Arc::new(AtomicU64::new(0)),
+            replay_enabled: false,
+        }
+    }
+
+    /// Intents with an id at or below this value must not be written.
+    pub fn written_off(&self) -> Arc<AtomicU64> {
+        self.written_off.clone()
+    }
+
+    pub fn replay_enabled(&self) -> bool {
+        self.replay_enabled
+    }
+
+    pub fn set_replay_enabled(&mut self, enabled: bool) {
+        self.replay_enabled = enabled;
+    }
+
+    /// Ids must increase; the oldest entry is forgotten once the log is full.
+    pub fn record(&mut self, id: u64, name: &str, payload: Value) {
+        if self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(UnackedIntent {
+            id,
+            name: name.to_string(),
+            payload,
+        });
+    }
+
+    /// The server processes intents in order, so an ack covers every id up to it.
+    pub fn ack(&mut self, ack: u64) {
+        while self.entries.front().is_some_and(|intent| intent.id <= ack) {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn write_off(&mut self) {
+        if let Some(last) = self.entries.back() {
+            self.written_off.fetch_max(last.id, Ordering::AcqRel);
+        }
+    }
+
+    /// Returns the written-off intents the render `ack` does not cover.
+    pub fn take_lost(&mut self, ack: Option<u64>) -> Vec<UnackedIntent> {
+        if let Some(ack) = ack {
+            self.ack(ack);
+        }
+
+        let written_off = self.written_off.load(Ordering::Acquire);
+        let lost = self
+            .entries
+            .iter()
+            .take_while(|intent| intent.id <= written_off)
+            .count();
+
+        self.entries.drain(..lost).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn log_with(ids: std::ops::RangeInclusive<u64>) -> IntentLog {
+        let mut log = IntentLog::new(16);
+        for id in ids {
+            log.record(id, "clock.pause", json!({ "arg": id }));
+        }
+        log
+    }
+
+    fn ids(intents: &[UnackedIntent]) -> Vec<u64> {
+        intents.iter().map(|intent| intent.id).collect()
+    }
+
+    #[test]
+    fn render_ack_separates_processed_from_lost_intents() {
+        let mut log = log_with(1..=4);
+        log.ack(1);
+        log.write_off();
+        assert_eq!(log.written_off().load(Ordering::Acquire), 4);
+
+        log.record(5, "clock.resume", json!({}));
+
+        assert_eq!(ids(&log.take_lost(Some(2))), vec![3, 4]);
+        assert!(log.take_lost(None).is_empty());
+
+        log.ack(5);
+        assert!(log.entries.is_empty());
+    }
+
+    #[test]
+    fn nothing_is_lost_without_a_write_off() {
+        let mut log = log_with(1..=3);
+        assert!(log.take_lost(None).is_empty());
+        assert_eq!(log.entries.len(), 3);
+    }
+
+    #[test]
+    fn a_full_log_forgets_the_oldest_intent() {
+        let mut log = IntentLog::new(2);
+        for id in 1..=3 {
+            log.record(id, "clock.pause", json!({}));
+        }
+        log.write_off();
+
+        assert_eq!(ids(&log.take_lost(None)), vec![2, 3]);
+    }
+}
@@ -1,22 +1,70 @@
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod chunk;
+pub mod coalesce;
+pub mod heartbeat;
+pub mod host_log;
+pub mod inbound;
+pub mod intent_log;
+pub mod outbound;
+pub mod pointer;
 pub mod protocol;
-
-use crate::protocol::{intent_envelope, reader_loop, ready_envelope, writer_loop};
+pub mod transport;
+pub mod windows;
+
+#[cfg(feature = "async")]
+use crate::async_io::{reader_loop_async, write_envelope_async};
+use crate::coalesce::IntentCoalescer;
+use crate::heartbeat::{HeartbeatConfig, Liveness};
+use crate::host_log::TRANSPORT_LOG_TARGET;
+use crate::inbound::{Frame, InboundQueue};
+use crate::intent_log::IntentLog;
+use crate::outbound::{OutboundReceiver, OutboundSender, outbound_queue};
+use crate::pointer::array_index;
+use crate::protocol::{
+    Codec, FEATURE_APPLIED, FEATURE_HEARTBEAT, FEATURE_INTENT_REPLAY, FEATURE_LOG,
+    applied_envelope, check_protocol_version, diagnostic_envelope, intent_envelope, ping_envelope,
+    pong_envelope, reader_loop, ready_envelope, vm_hash, write_envelope, write_envelope_with,
+};
+use crate::windows::{WINDOW_CLOSE_INTENT, WINDOWS_PATH};
+use log::{error, info, warn};
 use serde_json::Value;
 use serde_json::json;
 use slint::ComponentHandle;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufWriter, Write};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::mpsc::TrySendError;
 use std::sync::{Arc, Mutex};
 use std::thread;
-
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(all(feature = "async", unix))]
+pub use crate::async_io::TokioUnixSocketTransport;
+#[cfg(feature = "async")]
+pub use crate::async_io::{AsyncTransport, TokioStdioTransport};
+pub use crate::coalesce::IntentPolicy;
+pub use crate::inbound::InboundStats;
+pub use crate::intent_log::IntentReplay;
+pub use crate::pointer::{Pointer, Tokens};
 pub use crate::protocol::{
-    ELIXIR_TO_UI_CAP, ElixirEnvelope, PatchOp, UI_TO_ELIXIR_CAP, UiEnvelope,
+    Capabilities, ELIXIR_TO_UI_CAP, ElixirEnvelope, ErrorCode, PROTOCOL_VERSION, PatchOp,
+    ResumePoint, UI_TO_ELIXIR_CAP, UiEnvelope,
+};
+#[cfg(unix)]
+pub use crate::transport::{FdTransport, UnixSocketTransport};
+pub use crate::transport::{StdioTransport, StreamTransport, Transport};
+pub use crate::windows::{
+    ComponentWindow, ProjectionWindow, WindowDeclaration, WindowIntentHandler,
 };
 pub use serde_json;
 
 const DEFAULT_UI_OUTBOUND_QUEUE_CAP: usize = 256;
+const DEFAULT_UI_CONTROL_QUEUE_CAP: usize = 64;
+const RECONNECT_BACKOFF_MS: [u64; 6] = [100, 200, 500, 1_000, 2_000, 5_000];
 
-pub trait HostBindings {
+pub trait HostBindings: 'static {
     type Ui: ComponentHandle + 'static;
     type ScreenId: Copy + Default + Send + 'static;
 
@@ -37,6 +85,15 @@ pub trait HostBindings {
     fn set_app_title(ui: &Self::Ui, title: &str);
     fn set_active_screen(ui: &Self::Ui, active_screen: &str);
     fn set_nav_can_back(ui: &Self::Ui, nav_can_back: bool);
+
+    /// Raised when nothing arrived from the server within the heartbeat
+    /// timeout, or the transport dropped; the last frame stays on screen.
+    fn set_connection_stale(_ui: &Self::Ui, _stale: bool) {}
+
+    /// Raised while a window declared with `modal: true` is open, so the
+    /// main window stops taking input until it goes.
+    fn set_modal_open(_ui: &Self::Ui, _open: bool) {}
+
     fn set_error_title(ui: &Self::Ui, title: &str);
     fn set_error_message(ui: &Self::Ui, message: &str);
     fn set_error_screen_module(ui: &Self::Ui, screen_module: &str);
@@ -53,6 +110,61 @@ pub trait HostBindings {
     fn patch_changes_screen(path: &str) -> bool {
         path == "/screen/name"
     }
+
+    /// How to react to an `error` envelope from the server. Override to change
+    /// the policy for some codes and defer to `default_error_policy` for the rest.
+    fn error_policy(code: &ErrorCode) -> ErrorPolicy {
+        default_error_policy(code)
+    }
+
+    /// Coalescing for high-frequency intents such as slider drags. Takes
+    /// precedence over policies the server declares in `accept`.
+    fn intent_policy(_name: &str) -> Option<IntentPolicy> {
+        None
+    }
+
+    /// Whether an intent the server never received before a resync is sent
+    /// again. Only applies when the server accepted `intent_replay`; otherwise
+    /// such intents are always discarded.
+    fn intent_replay(_name: &str) -> IntentReplay {
+        IntentReplay::Replay
+    }
+
+    /// Receives the inbound counts of the session shown in `ui` after each
+    /// drain of its queue.
+    fn inbound_stats(_ui: &Self::Ui, _stats: InboundStats) {}
+
+    /// Creates the component for a window declared under `/windows/<id>`,
+    /// by its `kind`. `app_bindings!` maps it to the generated `new_window`.
+    fn new_window(kind: &str) -> Result<Box<dyn ProjectionWindow>, String> {
+        Err(format!("no window component for kind '{kind}'"))
+    }
+}
+
+/// Host reaction to an `error` envelope. Every error is logged regardless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Drop local state and send a fresh `ready`.
+    Resync,
+    /// Switch to the error screen with the code and message.
+    ShowError,
+    /// Stop the host, even on transports that reconnect.
+    Exit,
+    Ignore,
+}
+
+/// Errors about the wire or the revision chain resync; codes this host does
+/// not know are shown on the error screen so they do not go unnoticed.
+pub fn default_error_policy(code: &ErrorCode) -> ErrorPolicy {
+    match code {
+        ErrorCode::DecodeError
+        | ErrorCode::FrameTooLarge
+        | ErrorCode::InvalidEnvelope
+        | ErrorCode::ResyncRequired
+        | ErrorCode::RevMismatch
+        | ErrorCode::PatchApplyError => ErrorPolicy::Resync,
+        ErrorCode::Unknown(_) => ErrorPolicy::ShowError,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -61,6 +173,8 @@ pub struct UiModelState<ScreenId: Copy + Default> {
     pub vm: Value,
     pub last_rev: Option<u64>,
     pub last_ack: Option<u64>,
+    /// Set while a resync is pending and `vm` is still whole at that revision.
+    pub resume: Option<ResumePoint>,
 }
 
 impl<ScreenId: Copy + Default> Default for UiModelState<ScreenId> {
@@ -70,234 +184,1113 @@ impl<ScreenId: Copy + Default> Default for UiModelState<ScreenId> {
             vm: Value::Object(serde_json::Map::new()),
             last_rev: None,
             last_ack: None,
+            resume: None,
         }
     }
 }
 
 pub fn run<B: HostBindings>() -> Result<(), Box<dyn std::error::Error>> {
-    let ui = B::new_ui()?;
-    let ui_weak = ui.as_weak();
-    let ui_model_state = Arc::new(Mutex::new(UiModelState::<B::ScreenId>::default()));
-    let next_intent_id = Arc::new(AtomicU64::new(1));
-    let dropped_intent_count = Arc::new(AtomicU64::new(0));
-    let resync_pending = Arc::new(AtomicBool::new(false));
-    let outbound_queue_cap = parse_outbound_queue_capacity();
-    let (tx, rx) = mpsc::sync_channel(outbound_queue_cap);
-    let sid = std::env::var("PROJECTION_SID").unwrap_or_else(|_| "S1".to_string());
-    let resync_tx = tx.clone();
-    let resync_sid = sid.clone();
-    let resync_flag = resync_pending.clone();
-
-    install_callbacks::<B>(
-        &ui,
-        tx.clone(),
-        sid.clone(),
-        next_intent_id,
-        dropped_intent_count,
-        outbound_queue_cap,
-    );
+    if let Some(path) = std::env::var_os("PROJECTION_SOCKET") {
+        return run_with_socket::<B>(path.into());
+    }
+
+    match std::env::var("PROJECTION_TRANSPORT").as_deref() {
+        Ok("fd") => run_with_fds::<B>(),
+        Ok("stdio") | Err(_) => run_with_transport::<B, _>(StdioTransport),
+        Ok(other) => Err(format!("unsupported PROJECTION_TRANSPORT '{other}'").into()),
+    }
+}
 
-    let writer_handle = thread::spawn(move || writer_loop(rx));
+#[cfg(unix)]
+fn run_with_fds<B: HostBindings>() -> Result<(), Box<dyn std::error::Error>> {
+    run_with_transport::<B, _>(FdTransport::otp())
+}
 
-    tx.send(ready_envelope(sid))
-        .map_err(|_| "failed to queue ready envelope")?;
+#[cfg(not(unix))]
+fn run_with_fds<B: HostBindings>() -> Result<(), Box<dyn std::error::Error>> {
+    Err("PROJECTION_TRANSPORT=fd is only supported on unix platforms".into())
+}
+
+#[cfg(unix)]
+fn run_with_socket<B: HostBindings>(
+    path: std::path::PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_with_transport::<B, _>(UnixSocketTransport::new(path))
+}
+
+#[cfg(not(unix))]
+fn run_with_socket<B: HostBindings>(
+    _path: std::path::PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("PROJECTION_SOCKET is only supported on unix platforms".into())
+}
+
+pub fn run_with_transport<B: HostBindings, T: Transport>(
+    mut transport: T,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let HostSetup {
+        host,
+        uis,
+        heartbeat_timer,
+        tx,
+        rx,
+        written_off_intents,
+    } = start_host::<B>(transport.name())?;
+
+    let writer_slot: ConnectionWriter<T::Writer> = Arc::new(Mutex::new(None));
+    let reader_writer_slot = writer_slot.clone();
+    let writer_handle =
+        thread::spawn(move || connection_writer_loop(&writer_slot, rx, &written_off_intents));
 
     let reader_handle = thread::spawn(move || {
-        let shared_state = ui_model_state.clone();
-        let read_result = reader_loop(|envelope| match envelope {
-            ElixirEnvelope::Render { sid, rev, vm } => {
-                let state_for_render = shared_state.clone();
-                let tx_for_resync = resync_tx.clone();
-                let sid_for_resync = resync_sid.clone();
-                let resync_pending_for_render = resync_flag.clone();
-
-                let _ = ui_weak.upgrade_in_event_loop(move |ui| {
-                    let Ok(mut state) = state_for_render.lock() else {
-                        request_resync(
-                            &tx_for_resync,
-                            &sid_for_resync,
-                            "failed to lock UI model state for render",
-                            &resync_pending_for_render,
-                            outbound_queue_cap,
-                        );
-                        return;
-                    };
+        let result = serve_blocking(&host, &mut transport, &reader_writer_slot);
+        quit_ui(&result);
+        result
+    });
 
-                    if sid != sid_for_resync {
-                        reset_for_resync(&mut state);
-                        request_resync(
-                            &tx_for_resync,
-                            &sid_for_resync,
-                            "sid mismatch for render envelope",
-                            &resync_pending_for_render,
-                            outbound_queue_cap,
-                        );
-                        return;
-                    }
+    run_ui::<B>(uis, heartbeat_timer, tx)?;
 
-                    if let Err(err) = validate_render_rev(&state, rev) {
-                        reset_for_resync(&mut state);
-                        request_resync(
-                            &tx_for_resync,
-                            &sid_for_resync,
-                            &format!("invalid render revision: {err}"),
-                            &resync_pending_for_render,
-                            outbound_queue_cap,
-                        );
-                        return;
-                    }
+    if reader_handle.is_finished() {
+        match reader_handle.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!("reader thread returned error: {err}"),
+            Err(err) => error!("reader thread join failed: {err:?}"),
+        }
+    } else {
+        // Avoid hanging process exit on a blocked transport read during teardown.
+        warn!("reader thread still active during shutdown; skipping join");
+    }
+
+    if writer_handle.is_finished() {
+        if let Err(err) = writer_handle.join() {
+            error!("writer thread join failed: {err:?}");
+        }
+    } else {
+        // Avoid hanging process exit on a blocked transport write during teardown.
+        warn!("writer thread still active during shutdown; skipping join");
+    }
+
+    Ok(())
+}
+
+/// Like `run`, but the transport I/O runs as tasks on `runtime`, which app
+/// code can share. The Slint event loop still runs on the calling thread, so
+/// the runtime must be multi-threaded to make progress alongside it.
+#[cfg(feature = "async")]
+pub fn run_async<B: HostBindings>(
+    runtime: &tokio::runtime::Handle,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(path) = std::env::var_os("PROJECTION_SOCKET") {
+        return run_async_with_socket::<B>(runtime, path.into());
+    }
+
+    match std::env::var("PROJECTION_TRANSPORT").as_deref() {
+        Ok("stdio") | Err(_) => run_with_async_transport::<B, _>(runtime, TokioStdioTransport),
+        Ok(other) => {
+            Err(format!("unsupported PROJECTION_TRANSPORT '{other}' in async mode").into())
+        }
+    }
+}
+
+#[cfg(all(feature = "async", unix))]
+fn run_async_with_socket<B: HostBindings>(
+    runtime: &tokio::runtime::Handle,
+    path: std::path::PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_with_async_transport::<B, _>(runtime, TokioUnixSocketTransport::new(path))
+}
+
+#[cfg(all(feature = "async", not(unix)))]
+fn run_async_with_socket<B: HostBindings>(
+    _runtime: &tokio::runtime::Handle,
+    _path: std::path::PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("PROJECTION_SOCKET is only supported on unix platforms".into())
+}
+
+#[cfg(feature = "async")]
+pub fn run_with_async_transport<B: HostBindings, T: AsyncTransport>(
+    runtime: &tokio::runtime::Handle,
+    transport: T,
+) -> Result<(), Box<dyn std::error::Error>> {
+    require_multi_thread(runtime)?;
+
+    let HostSetup {
+        host,
+        uis,
+        heartbeat_timer,
+        tx,
+        rx,
+        written_off_intents,
+    } = start_host::<B>(transport.name())?;
+
+    // Blocking on the task's handle would panic when called from within the
+    // runtime, so its result comes back over a channel instead.
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    let serve_handle = runtime.spawn(async move {
+        let result =
+            serve_async(host, transport, Arc::new(rx), Arc::new(written_off_intents)).await;
+        quit_ui(&result);
+        let _ = done_tx.send(result);
+    });
+
+    run_ui::<B>(uis, heartbeat_timer, tx)?;
+
+    match done_rx.try_recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => error!("transport task returned error: {err}"),
+        Err(std::sync::mpsc::TryRecvError::Empty) => serve_handle.abort(),
+        Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+            error!("transport task ended without a result")
+        }
+    }
+
+    Ok(())
+}
+
+/// Tasks on a current-thread runtime only run while something blocks on it,
+/// which the Slint event loop on the calling thread never does.
+#[cfg(feature = "async")]
+fn require_multi_thread(
+    runtime: &tokio::runtime::Handle,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if runtime.runtime_flavor() == tokio::runtime::RuntimeFlavor::CurrentThread {
+        return Err("run_async needs a multi-thread tokio runtime".into());
+    }
+    Ok(())
+}
+
+/// What `start_host` hands to the transport side.
+struct HostSetup<B: HostBindings> {
+    host: Host<B>,
+    uis: Vec<B::Ui>,
+    heartbeat_timer: Option<slint::Timer>,
+    tx: OutboundSender,
+    rx: OutboundReceiver,
+    written_off_intents: HashMap<String, Arc<AtomicU64>>,
+}
+
+/// Opens a window per session and wires its callbacks. Runs on the UI thread.
+fn start_host<B: HostBindings>(
+    transport_name: &'static str,
+) -> Result<HostSetup<B>, slint::PlatformError> {
+    host_log::install();
+    let sids = parse_session_ids();
+    let outbound_queue_cap = parse_outbound_queue_capacity();
+    let applied_min_interval = parse_applied_min_interval();
+    let (tx, rx) = outbound_queue(parse_control_queue_capacity(), outbound_queue_cap);
+    // Pings belong to the connection; they go out under the first sid.
+    let connection_sid = sids[0].clone();
+    host_log::attach(tx.clone());
+
+    let mut uis = Vec::with_capacity(sids.len());
+    let mut sessions = Vec::with_capacity(sids.len());
+    let mut written_off_intents = HashMap::new();
+    for sid in sids {
+        let ui = B::new_ui()?;
+        let intent_log = IntentLog::new(outbound_queue_cap);
+        written_off_intents.insert(sid.clone(), intent_log.written_off());
+
+        let intents = IntentSender {
+            tx: tx.clone(),
+            sid: sid.clone(),
+            next_intent_id: Arc::new(AtomicU64::new(1)),
+            dropped_intent_count: Arc::new(AtomicU64::new(0)),
+            queue_capacity: outbound_queue_cap,
+            coalescer: Arc::new(Mutex::new(IntentCoalescer::new(B::intent_policy))),
+            log: Arc::new(Mutex::new(intent_log)),
+        };
+        install_callbacks::<B>(&ui, intents.clone());
+
+        sessions.push(Session::<B> {
+            ready: ready_envelope(sid.clone(), transport_name),
+            sid,
+            ui_weak: ui.as_weak(),
+            state: Arc::new(Mutex::new(UiModelState::default())),
+            resync_pending: Arc::new(AtomicBool::new(false)),
+            applied_reporter: Arc::new(Mutex::new(AppliedReporter::new(applied_min_interval))),
+            intents,
+            inbound: Arc::new(InboundQueue::default()),
+        });
+        uis.push(ui);
+    }
+
+    let ui_weaks: Vec<_> = uis.iter().map(ComponentHandle::as_weak).collect();
+    let heartbeat = HeartbeatConfig::from_env();
+    let liveness = Arc::new(Mutex::new(Liveness::new(Instant::now())));
+    let heartbeat_timer = heartbeat.map(|config| {
+        start_heartbeat::<B>(
+            ui_weaks.clone(),
+            config,
+            liveness.clone(),
+            tx.clone(),
+            connection_sid,
+        )
+    });
+
+    Ok(HostSetup {
+        host: Host {
+            sessions,
+            ui_weaks,
+            liveness,
+            heartbeat: heartbeat.is_some(),
+            pong_tx: tx.clone(),
+        },
+        uis,
+        heartbeat_timer,
+        tx,
+        rx,
+        written_off_intents,
+    })
+}
+
+/// Shows every session window and runs the event loop until the last one is
+/// closed.
+fn run_ui<B: HostBindings>(
+    uis: Vec<B::Ui>,
+    heartbeat_timer: Option<slint::Timer>,
+    tx: OutboundSender,
+) -> Result<(), slint::PlatformError> {
+    for ui in &uis {
+        ui.show()?;
+    }
+    slint::run_event_loop()?;
+    windows::close_all_windows();
+    for ui in &uis {
+        ui.hide()?;
+    }
+
+    // Drop the windows, the heartbeat timer and the log forwarder first so
+    // they release their `tx` clones.
+    drop(heartbeat_timer);
+    drop(uis);
+    host_log::detach();
+    drop(tx);
+    Ok(())
+}
+
+fn quit_ui(result: &io::Result<()>) {
+    if let Err(err) = result {
+        error!("reader loop terminated with error: {err}");
+    }
+
+    let quit_result = slint::invoke_from_event_loop(|| {
+        let _ = slint::quit_event_loop();
+    });
+
+    if let Err(err) = quit_result {
+        error!("failed to request UI event loop quit: {err}");
+    }
+}
+
+/// Connects, reads until the connection drops and reconnects when the
+/// transport allows it. Runs on the reader thread.
+fn serve_blocking<B: HostBindings, T: Transport>(
+    host: &Host<B>,
+    transport: &mut T,
+    slot: &ConnectionWriter<T::Writer>,
+) -> io::Result<()> {
+    let mut reconnect_attempt = 0_u32;
+    loop {
+        let readies = host.readies();
+        let connected = transport.connect().and_then(|(reader, writer)| {
+            let mut writer = BufWriter::new(writer);
+            for ready in &readies {
+                write_envelope(&mut writer, ready)?;
+            }
+            Ok((reader, writer))
+        });
+
+        let (reader, writer) = match connected {
+            Ok(streams) => streams,
+            Err(err) if transport.reconnects() => {
+                let delay = reconnect_delay(reconnect_attempt);
+                reconnect_attempt = reconnect_attempt.saturating_add(1);
+                warn!("transport connect failed: {err}; retrying in {delay:?}");
+                thread::sleep(delay);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        reconnect_attempt = 0;
+        host.install_connection(|| {
+            if let Ok(mut slot) = slot.lock() {
+                *slot = Some(Connection {
+                    writer,
+                    codec: Codec::Json,
+                });
+            }
+        });
+
+        let result = reader_loop(
+            reader,
+            |envelope| {
+                host.note_inbound();
+                if let Some(codec) = host.dispatch(envelope)?
+                    && let Ok(mut slot) = slot.lock()
+                    && let Some(connection) = slot.as_mut()
+                {
+                    connection.codec = codec;
+                }
+                Ok(())
+            },
+            |err| host.discarded(&err),
+        );
+
+        if let Ok(mut slot) = slot.lock() {
+            *slot = None;
+        }
+        host.disconnected();
 
-                    if let Err(err) = apply_render::<B>(&ui, &vm, &mut state) {
-                        reset_for_resync(&mut state);
-                        request_resync(
-                            &tx_for_resync,
-                            &sid_for_resync,
-                            &format!("render apply failed: {err}"),
-                            &resync_pending_for_render,
-                            outbound_queue_cap,
-                        );
-                        return;
+        if !transport.reconnects() || is_fatal(&result) {
+            return result;
+        }
+
+        match &result {
+            Ok(()) => info!("transport closed; reconnecting"),
+            Err(err) => warn!("transport failed: {err}; reconnecting"),
+        }
+
+        host.prepare_reconnect();
+        thread::sleep(reconnect_delay(0));
+    }
+}
+
+/// `serve_blocking` as a tokio task; each connection gets its own writer task.
+#[cfg(feature = "async")]
+async fn serve_async<B: HostBindings, T: AsyncTransport>(
+    host: Host<B>,
+    mut transport: T,
+    rx: Arc<OutboundReceiver>,
+    written_off_intents: Arc<HashMap<String, Arc<AtomicU64>>>,
+) -> io::Result<()> {
+    let mut reconnect_attempt = 0_u32;
+    loop {
+        let readies = host.readies();
+        let connected = match transport.connect().await {
+            Ok((reader, mut writer)) => {
+                let mut written = Ok(());
+                for ready in &readies {
+                    written = write_envelope_async(&mut writer, ready, Codec::Json).await;
+                    if written.is_err() {
+                        break;
                     }
+                }
+                written.map(|()| (reader, writer))
+            }
+            Err(err) => Err(err),
+        };
+
+        let (reader, writer) = match connected {
+            Ok(streams) => streams,
+            Err(err) if transport.reconnects() => {
+                let delay = reconnect_delay(reconnect_attempt);
+                reconnect_attempt = reconnect_attempt.saturating_add(1);
+                warn!("transport connect failed: {err}; retrying in {delay:?}");
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        reconnect_attempt = 0;
+        let codec = Arc::new(Mutex::new(Codec::Json));
+        let mut writer_task = None;
+        host.install_connection(|| {
+            // Like the blocking writer, drop what was queued while disconnected.
+            while rx.try_recv().is_some() {}
+            writer_task = Some(tokio::spawn(connection_writer_task(
+                writer,
+                rx.clone(),
+                codec.clone(),
+                written_off_intents.clone(),
+            )));
+        });
+
+        let result = reader_loop_async(
+            reader,
+            |envelope| {
+                host.note_inbound();
+                if let Some(accepted) = host.dispatch(envelope)?
+                    && let Ok(mut codec) = codec.lock()
+                {
+                    *codec = accepted;
+                }
+                Ok(())
+            },
+            |err| host.discarded(&err),
+        )
+        .await;
+
+        if let Some(writer_task) = writer_task {
+            writer_task.abort();
+        }
+        host.disconnected();
+
+        if !transport.reconnects() || is_fatal(&result) {
+            return result;
+        }
+
+        match &result {
+            Ok(()) => info!("transport closed; reconnecting"),
+            Err(err) => warn!("transport failed: {err}; reconnecting"),
+        }
+
+        host.prepare_reconnect();
+        tokio::time::sleep(reconnect_delay(0)).await;
+    }
+}
 
-                    mark_applied_rev(&mut state, rev);
-                    resync_pending_for_render.store(false, Ordering::Release);
+/// A version mismatch or a fatal server error will not fix itself by
+/// reconnecting.
+fn is_fatal(result: &io::Result<()>) -> bool {
+    matches!(
+        result,
+        Err(err) if matches!(
+            err.kind(),
+            io::ErrorKind::Unsupported | io::ErrorKind::ConnectionAborted
+        )
+    )
+}
+
+/// The sessions served over one transport, plus the connection-wide state the
+/// read side needs.
+struct Host<B: HostBindings> {
+    sessions: Vec<Session<B>>,
+    ui_weaks: Vec<slint::Weak<B::Ui>>,
+    liveness: Arc<Mutex<Liveness>>,
+    heartbeat: bool,
+    pong_tx: OutboundSender,
+}
+
+impl<B: HostBindings> Host<B> {
+    /// One `ready` per session, written first on every connection.
+    fn readies(&self) -> Vec<UiEnvelope> {
+        self.sessions.iter().map(Session::resuming_ready).collect()
+    }
+
+    /// Whatever was sent before this connection is settled by the ack in its
+    /// first render. Holding the logs while `install` hands the connection to
+    /// the writer keeps new intents out until it can deliver them.
+    fn install_connection(&self, install: impl FnOnce()) {
+        let mut intent_logs: Vec<_> = self
+            .sessions
+            .iter()
+            .filter_map(|session| session.intents.log.lock().ok())
+            .collect();
+        for log in &mut intent_logs {
+            log.write_off();
+        }
+
+        install();
+        drop(intent_logs);
+    }
+
+    fn note_inbound(&self) {
+        let cleared = self
+            .liveness
+            .lock()
+            .ok()
+            .and_then(|mut liveness| liveness.touch(Instant::now()));
+
+        if let Some(stale) = cleared {
+            set_connection_stale::<B>(&self.ui_weaks, stale);
+        }
+    }
+
+    /// Routes an inbound envelope to its session. Returns the codec the
+    /// server accepted for this connection, if this was an `accept`.
+    fn dispatch(&self, envelope: ElixirEnvelope) -> io::Result<Option<Codec>> {
+        if let ElixirEnvelope::Ping { sid, nonce } = &envelope {
+            let _ = self.pong_tx.try_send(pong_envelope(sid.clone(), *nonce));
+            return Ok(None);
+        }
+
+        let Some(session) = self
+            .sessions
+            .iter()
+            .find(|session| session.sid == envelope.sid())
+        else {
+            warn!("no session for sid={}; dropping envelope", envelope.sid());
+            return Ok(None);
+        };
+
+        match envelope {
+            ElixirEnvelope::Accept {
+                protocol_version,
+                codec,
+                compression,
+                features,
+                intent_policies,
+                ..
+            } => {
+                check_protocol_version(protocol_version)
+                    .map_err(|err| io::Error::new(io::ErrorKind::Unsupported, err))?;
+
+                let codec = Codec::from_name(&codec).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("server accepted unsupported codec '{codec}'"),
+                    )
+                })?;
+
+                info!(
+                    sid = session.sid.as_str();
+                    "server accepted protocol v{protocol_version} codec={} compression={compression:?} features={features:?} sid={}",
+                    codec.name(),
+                    session.sid
+                );
+
+                session.accept(&features, intent_policies);
+                host_log::set_forwarding(features.iter().any(|feature| feature == FEATURE_LOG));
+
+                if self.heartbeat
+                    && features.iter().any(|feature| feature == FEATURE_HEARTBEAT)
+                    && let Ok(mut liveness) = self.liveness.lock()
+                {
+                    liveness.enable(Instant::now());
+                }
+
+                Ok(Some(codec))
+            }
+            ElixirEnvelope::Ping { .. } | ElixirEnvelope::Pong { .. } => Ok(None),
+            ElixirEnvelope::Render { rev, ack, vm, .. } => {
+                session.queue(Frame::Render { rev, ack, vm });
+                Ok(None)
+            }
+            ElixirEnvelope::Patch {
+                rev,
+                ack,
+                resume_from,
+                ops,
+                ..
+            } => {
+                session.queue(Frame::Patch {
+                    base_rev: rev,
+                    rev,
+                    ack,
+                    resume_from,
+                    ops,
                 });
+                Ok(None)
+            }
+            ElixirEnvelope::Error {
+                sid,
+                rev,
+                code,
+                message,
+            } => {
+                warn!(sid = sid.as_str(); "server error sid={sid} rev={rev:?}: {code}: {message}");
+                match B::error_policy(&code) {
+                    ErrorPolicy::Resync => session.resync(
+                        ResyncCause::new(
+                            "server_requested_resync",
+                            format!("server requested resync via error code '{code}'"),
+                        )
+                        .with_rev(rev),
+                    ),
+                    ErrorPolicy::ShowError => {
+                        let _ = session.ui_weak.upgrade_in_event_loop(move |ui| {
+                            show_server_error::<B>(&ui, &code, &message);
+                        });
+                    }
+                    ErrorPolicy::Exit => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::ConnectionAborted,
+                            format!("server error '{code}' is fatal: {message}"),
+                        ));
+                    }
+                    ErrorPolicy::Ignore => {}
+                }
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// The discarded message may have been meant for any session, so each
+    /// one asks to resume from the revision it holds. Runs on the UI thread
+    /// after the frames queued before it.
+    fn discarded(&self, err: &io::Error) {
+        warn!("discarding undecodable inbound message: {err}");
+
+        for session in &self.sessions {
+            let session = session.clone();
+            let cause = ResyncCause::new(
+                "message_discarded",
+                format!("inbound message discarded: {err}"),
+            );
+            let _ = session.ui_weak.clone().upgrade_in_event_loop(move |_ui| {
+                if let Ok(mut state) = session.state.lock() {
+                    session.resync_resuming(&mut state, cause);
+                }
+            });
+        }
+    }
+
+    /// The next connection negotiates features afresh in its `accept`.
+    fn disconnected(&self) {
+        for session in &self.sessions {
+            session.disconnect();
+        }
+        host_log::set_forwarding(false);
+    }
+
+    fn prepare_reconnect(&self) {
+        let went_stale = self
+            .liveness
+            .lock()
+            .ok()
+            .and_then(|mut liveness| liveness.disconnected());
+        if let Some(stale) = went_stale {
+            set_connection_stale::<B>(&self.ui_weaks, stale);
+        }
+
+        // Keep the last frame on screen but drop the revision chain, so the
+        // first render on the new connection is accepted, or a patch that
+        // resumes from the kept view-model. Runs on the UI thread after any
+        // updates still queued from the old connection.
+        for session in &self.sessions {
+            let session = session.clone();
+            let _ = session
+                .ui_weak
+                .clone()
+                .upgrade_in_event_loop(move |_ui| session.suspend());
+        }
+    }
+}
+
+/// One server session, bound to its own window. Clones share the same state.
+struct Session<B: HostBindings> {
+    sid: String,
+    ui_weak: slint::Weak<B::Ui>,
+    state: Arc<Mutex<UiModelState<B::ScreenId>>>,
+    resync_pending: Arc<AtomicBool>,
+    applied_reporter: Arc<Mutex<AppliedReporter>>,
+    intents: IntentSender,
+    inbound: Arc<InboundQueue>,
+    ready: UiEnvelope,
+}
+
+impl<B: HostBindings> Clone for Session<B> {
+    fn clone(&self) -> Self {
+        Self {
+            sid: self.sid.clone(),
+            ui_weak: self.ui_weak.clone(),
+            state: self.state.clone(),
+            resync_pending: self.resync_pending.clone(),
+            applied_reporter: self.applied_reporter.clone(),
+            intents: self.intents.clone(),
+            inbound: self.inbound.clone(),
+            ready: self.ready.clone(),
+        }
+    }
+}
+
+impl<B: HostBindings> Session<B> {
+    /// The `ready` sent on connect, naming the revision still on screen.
+    fn resuming_ready(&self) -> UiEnvelope {
+        let resume = self
+            .state
+            .lock()
+            .ok()
+            .and_then(|state| resume_point(&state));
+        self.ready.clone().resuming_from(resume)
+    }
+
+    fn resync(&self, cause: ResyncCause) {
+        self.resync_with(&self.ready, cause);
+    }
+
+    /// Hands a frame to the UI thread, which applies everything queued by
+    /// the time it gets to run.
+    fn queue(&self, frame: Frame) {
+        if self.inbound.push(frame) {
+            let session = self.clone();
+            if let Err(err) = self
+                .ui_weak
+                .upgrade_in_event_loop(move |ui| session.drain_inbound(&ui))
+            {
+                warn!(sid = self.sid.as_str(); "failed to schedule the inbound drain: {err}");
+                self.inbound.schedule_failed();
+            }
+        }
+    }
+
+    fn drain_inbound(&self, ui: &B::Ui) {
+        for frame in self.inbound.drain() {
+            match frame {
+                Frame::Render { rev, ack, vm } => self.render(ui, rev, ack, &vm),
+                Frame::Patch {
+                    base_rev,
+                    rev,
+                    ack,
+                    resume_from,
+                    ops,
+                } => self.patch(ui, base_rev, rev, ack, resume_from, &ops),
+            }
+        }
+        B::inbound_stats(ui, self.inbound.stats());
+    }
+
+    /// Keeps the view-model on screen and asks the server to patch it from
+    /// `last_rev`, or to render afresh when there is nothing to resume from.
+    fn resync_resuming(&self, state: &mut UiModelState<B::ScreenId>, cause: ResyncCause) {
+        suspend_for_resync(state);
+        self.resync_with(
+            &self.ready.clone().resuming_from(state.resume.clone()),
+            cause,
+        );
+    }
+
+    fn resync_with(&self, ready: &UiEnvelope, cause: ResyncCause) {
+        request_resync(
+            &self.intents.tx,
+            &self.sid,
+            ready,
+            cause,
+            &self.resync_pending,
+        );
+    }
+
+    fn accept(&self, features: &[String], intent_policies: HashMap<String, IntentPolicy>) {
+        if let Ok(mut reporter) = self.applied_reporter.lock() {
+            reporter.enabled = features.iter().any(|feature| feature == FEATURE_APPLIED);
+        }
+
+        if let Ok(mut log) = self.intents.log.lock() {
+            log.set_replay_enabled(
+                features
+                    .iter()
+                    .any(|feature| feature == FEATURE_INTENT_REPLAY),
+            );
+        }
+
+        if let Ok(mut coalescer) = self.intents.coalescer.lock() {
+            coalescer.set_server_policies(intent_policies);
+        }
+    }
+
+    fn disconnect(&self) {
+        if let Ok(mut reporter) = self.applied_reporter.lock() {
+            reporter.reset();
+        }
+        if let Ok(mut log) = self.intents.log.lock() {
+            log.set_replay_enabled(false);
+        }
+    }
+
+    /// Runs on the UI thread once the connection dropped.
+    fn suspend(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            suspend_for_resync(&mut state);
+        }
+        self.resync_pending.store(false, Ordering::Release);
+    }
+
+    /// Runs on the UI thread. A close request becomes a `ui.window.close` intent,
+    /// and the main window is blocked while a modal window is open.
+    fn sync_windows(&self, ui: &B::Ui, vm: &Value) -> Result<(), String> {
+        windows::with_session_windows(&self.sid, |windows| {
+            let synced = windows.sync(
+                vm,
+                B::new_window,
+                |id| {
+                    let intents = self.intents.clone();
+                    let id = id.to_string();
+                    Box::new(move || intents.submit(WINDOW_CLOSE_INTENT, json!({ "id": id })))
+                },
+                || {
+                    let intents = self.intents.clone();
+                    Box::new(move |intent_name, intent_arg| {
+                        if intent_name.is_empty() {
+                            return;
+                        }
+
+                        let payload = if intent_arg.is_empty() {
+                            json!({})
+                        } else {
+                            json!({ "arg": intent_arg })
+                        };
+
+                        intents.submit(&intent_name, payload);
+                    })
+                },
+            );
+            B::set_modal_open(ui, windows.modal_open());
+            synced
+        })
+    }
+
+    fn render(&self, ui: &B::Ui, rev: u64, ack: Option<u64>, vm: &Value) {
+        let Ok(mut state) = self.state.lock() else {
+            self.resync(
+                ResyncCause::new(
+                    "state_lock_failed",
+                    "failed to lock UI model state for render",
+                )
+                .at_rev(rev),
+            );
+            return;
+        };
+
+        if let Err(err) = validate_render_rev(&state, rev) {
+            reset_for_resync(&mut state);
+            self.resync(
+                ResyncCause::new("rev_mismatch", format!("invalid render revision: {err}"))
+                    .at_rev(rev),
+            );
+            return;
+        }
+
+        if let Err(err) = apply_render::<B>(ui, vm, &mut state) {
+            reset_for_resync(&mut state);
+            self.resync(
+                ResyncCause::new("render_apply_failed", format!("render apply failed: {err}"))
+                    .at_rev(rev),
+            );
+            return;
+        }
+
+        if let Err(err) = self.sync_windows(ui, &state.vm) {
+            reset_for_resync(&mut state);
+            self.resync(
+                ResyncCause::new("window_apply_failed", format!("window apply failed: {err}"))
+                    .at_rev(rev)
+                    .at_path(Some(WINDOWS_PATH.to_string())),
+            );
+            return;
+        }
+
+        mark_applied_rev(&mut state, rev);
+        mark_applied_ack(&mut state, ack);
+        self.resync_pending.store(false, Ordering::Release);
+        report_applied(&self.applied_reporter, &self.intents.tx, &self.sid, rev);
+        resolve_lost_intents::<B>(&self.intents, ack);
+    }
+
+    /// Applies patches `base_rev..=rev`, merged into one frame when there is
+    /// more than one.
+    fn patch(
+        &self,
+        ui: &B::Ui,
+        base_rev: u64,
+        rev: u64,
+        ack: Option<u64>,
+        resume_from: Option<u64>,
+        ops: &[PatchOp],
+    ) {
+        let Ok(mut state) = self.state.lock() else {
+            self.resync(
+                ResyncCause::new(
+                    "state_lock_failed",
+                    "failed to lock UI model state for patch",
+                )
+                .at_rev(rev),
+            );
+            return;
+        };
+
+        let held_rev = state.resume.as_ref().map(|resume| resume.rev);
+        match (resume_from, held_rev) {
+            // Sent before the server saw the resuming `ready`; its answer
+            // covers this patch.
+            (None, Some(_)) => return,
+            (None, None) => {
+                if let Err(err) = validate_patch_rev(&state, base_rev) {
+                    // The view-model is still whole at `last_rev`.
+                    self.resync_resuming(
+                        &mut state,
+                        ResyncCause::new("rev_mismatch", format!("invalid patch revision: {err}"))
+                            .at_rev(base_rev),
+                    );
+                    return;
+                }
+            }
+            (Some(base), held_rev) if held_rev != Some(base) => {
+                reset_for_resync(&mut state);
+                // The resuming `ready` was answered; ask again for a full render.
+                self.resync_pending.store(false, Ordering::Release);
+                self.resync(
+                    ResyncCause::new(
+                        "resume_mismatch",
+                        format!("patch resumes from rev={base} but the host holds {held_rev:?}"),
+                    )
+                    .at_rev(rev),
+                );
+                return;
+            }
+            (Some(_), _) => {}
+        }
+
+        if let Err(err) = apply_patch::<B>(ui, ops, &mut state) {
+            let cause = ResyncCause::new(
+                "patch_apply_failed",
+                format!("patch apply failed: {}", err.message),
+            )
+            .at_rev(rev)
+            .at_path(err.path);
+
+            if resume_from.is_some() {
+                // Resuming from this point already failed; start over.
+                reset_for_resync(&mut state);
+                self.resync_pending.store(false, Ordering::Release);
+                self.resync(cause);
+            } else {
+                // Nothing was committed, so the view-model is still whole at `last_rev`.
+                self.resync_resuming(&mut state, cause);
+            }
+            return;
+        }
+
+        if patch_touches_windows(ops)
+            && let Err(err) = self.sync_windows(ui, &state.vm)
+        {
+            // The view-model is whole at `rev`; only the windows lag behind it.
+            mark_applied_rev(&mut state, rev);
+            state.resume = None;
+            if resume_from.is_some() {
+                self.resync_pending.store(false, Ordering::Release);
             }
-            ElixirEnvelope::Patch { sid, rev, ack, ops } => {
-                let state_for_patch = shared_state.clone();
-                let tx_for_resync = resync_tx.clone();
-                let sid_for_resync = resync_sid.clone();
-                let resync_pending_for_patch = resync_flag.clone();
-
-                let _ = ui_weak.upgrade_in_event_loop(move |ui| {
-                    let Ok(mut state) = state_for_patch.lock() else {
-                        request_resync(
-                            &tx_for_resync,
-                            &sid_for_resync,
-                            "failed to lock UI model state for patch",
-                            &resync_pending_for_patch,
-                            outbound_queue_cap,
-                        );
-                        return;
-                    };
+            self.resync_resuming(
+                &mut state,
+                ResyncCause::new("window_apply_failed", format!("window apply failed: {err}"))
+                    .at_rev(rev)
+                    .at_path(Some(WINDOWS_PATH.to_string())),
+            );
+            return;
+        }
 
-                    if sid != sid_for_resync {
-                        reset_for_resync(&mut state);
-                        request_resync(
-                            &tx_for_resync,
-                            &sid_for_resync,
-                            "sid mismatch for patch envelope",
-                            &resync_pending_for_patch,
-                            outbound_queue_cap,
-                        );
-                        return;
-                    }
+        mark_applied_rev(&mut state, rev);
+        mark_applied_ack(&mut state, ack);
+        self.intents.acknowledge(ack);
+        report_applied(&self.applied_reporter, &self.intents.tx, &self.sid, rev);
 
-                    if let Err(err) = validate_patch_rev(&state, rev) {
-                        reset_for_resync(&mut state);
-                        request_resync(
-                            &tx_for_resync,
-                            &sid_for_resync,
-                            &format!("invalid patch revision: {err}"),
-                            &resync_pending_for_patch,
-                            outbound_queue_cap,
-                        );
-                        return;
-                    }
+        if resume_from.is_some() {
+            state.resume = None;
+            self.resync_pending.store(false, Ordering::Release);
+            resolve_lost_intents::<B>(&self.intents, ack);
+        }
+    }
+}
 
-                    if let Err(err) = apply_patch::<B>(&ui, &ops, &mut state) {
-                        reset_for_resync(&mut state);
-                        request_resync(
-                            &tx_for_resync,
-                            &sid_for_resync,
-                            &format!("patch apply failed: {err}"),
-                            &resync_pending_for_patch,
-                            outbound_queue_cap,
-                        );
-                        return;
-                    }
+/// Pings the server every interval and flags the connection stale once nothing
+/// has arrived for `stale_after`. Runs on the UI thread.
+fn start_heartbeat<B: HostBindings>(
+    ui_weaks: Vec<slint::Weak<B::Ui>>,
+    config: HeartbeatConfig,
+    liveness: Arc<Mutex<Liveness>>,
+    tx: OutboundSender,
+    sid: String,
+) -> slint::Timer {
+    let timer = slint::Timer::default();
 
-                    mark_applied_rev(&mut state, rev);
-                    mark_applied_ack(&mut state, ack);
-                });
-            }
-            ElixirEnvelope::Error {
-                sid,
-                rev,
-                code,
-                message,
-            } => {
-                eprintln!("server error sid={sid} rev={rev:?}: {code}: {message}");
-                if should_resync_for_error(&code) {
-                    request_resync(
-                        &resync_tx,
-                        &resync_sid,
-                        &format!("server requested resync via error code '{code}'"),
-                        &resync_flag,
-                        outbound_queue_cap,
-                    );
-                }
+    timer.start(slint::TimerMode::Repeated, config.interval, move || {
+        let Ok(mut liveness) = liveness.lock() else {
+            return;
+        };
+
+        if let Some(stale) = liveness.check(Instant::now(), config.stale_after) {
+            warn!(
+                "no message from server for {:?}; marking connection stale",
+                config.stale_after
+            );
+            for ui in ui_weaks.iter().filter_map(slint::Weak::upgrade) {
+                B::set_connection_stale(&ui, stale);
             }
-        });
+        }
 
-        if let Err(err) = &read_result {
-            eprintln!("reader loop terminated with error: {err}");
+        if liveness.is_enabled() {
+            // Replaces a ping still queued, so a stalled writer holds one at most.
+            let _ = tx.try_send(ping_envelope(sid.clone(), liveness.next_nonce()));
         }
+    });
 
-        let quit_result = slint::invoke_from_event_loop(|| {
-            let _ = slint::quit_event_loop();
-        });
+    timer
+}
 
-        if let Err(err) = quit_result {
-            eprintln!("failed to request UI event loop quit: {err}");
-        }
+fn set_connection_stale<B: HostBindings>(ui_weaks: &[slint::Weak<B::Ui>], stale: bool) {
+    for ui_weak in ui_weaks {
+        let _ = ui_weak.upgrade_in_event_loop(move |ui| B::set_connection_stale(&ui, stale));
+    }
+}
 
-        read_result
-    });
+/// Write half of the live connection, with the codec the server accepted.
+struct Connection<W: Write> {
+    writer: BufWriter<W>,
+    codec: Codec,
+}
 
-    ui.run()?;
+type ConnectionWriter<W> = Arc<Mutex<Option<Connection<W>>>>;
 
-    // Drop UI first so callback closures release their `tx` clones.
-    drop(ui);
-    drop(tx);
+fn connection_writer_loop<W: Write>(
+    slot: &ConnectionWriter<W>,
+    rx: OutboundReceiver,
+    written_off_intents: &HashMap<String, Arc<AtomicU64>>,
+) {
+    for envelope in rx {
+        let Ok(mut guard) = slot.lock() else {
+            error!(target: TRANSPORT_LOG_TARGET, "failed to lock transport writer");
+            return;
+        };
 
-    if reader_handle.is_finished() {
-        match reader_handle.join() {
-            Ok(Ok(())) => {}
-            Ok(Err(err)) => eprintln!("reader thread returned error: {err}"),
-            Err(err) => eprintln!("reader thread join failed: {err:?}"),
+        // Checked under the slot lock, so nothing written off reaches a
+        // connection after its `ready`. The UI thread replays these.
+        if let UiEnvelope::Intent { sid, id, .. } = &envelope
+            && written_off_intents
+                .get(sid)
+                .is_some_and(|written_off| *id <= written_off.load(Ordering::Acquire))
+        {
+            continue;
+        }
+
+        let Some(connection) = guard.as_mut() else {
+            warn!(target: TRANSPORT_LOG_TARGET, "transport disconnected; dropping outbound envelope");
+            continue;
+        };
+
+        if let Err(err) = write_envelope_with(&mut connection.writer, &envelope, connection.codec) {
+            warn!(target: TRANSPORT_LOG_TARGET, "failed to write outbound envelope: {err}");
+            *guard = None;
         }
-    } else {
-        // Avoid hanging process exit on a blocked stdio read during teardown.
-        eprintln!("reader thread still active during shutdown; skipping join");
     }
+}
 
-    if writer_handle.is_finished() {
-        match writer_handle.join() {
-            Ok(Ok(())) => {}
-            Ok(Err(err)) => eprintln!("writer thread returned error: {err}"),
-            Err(err) => eprintln!("writer thread join failed: {err:?}"),
+/// Async counterpart of `connection_writer_loop`, for one connection.
+#[cfg(feature = "async")]
+async fn connection_writer_task<W: tokio::io::AsyncWrite + Unpin>(
+    mut writer: W,
+    rx: Arc<OutboundReceiver>,
+    codec: Arc<Mutex<Codec>>,
+    written_off_intents: Arc<HashMap<String, Arc<AtomicU64>>>,
+) {
+    while let Some(envelope) = rx.recv().await {
+        // The connection was installed after the write-off, so the check can
+        // run here without the blocking writer's slot lock.
+        if let UiEnvelope::Intent { sid, id, .. } = &envelope
+            && written_off_intents
+                .get(sid)
+                .is_some_and(|written_off| *id <= written_off.load(Ordering::Acquire))
+        {
+            continue;
+        }
+
+        let codec = codec.lock().map(|codec| *codec).unwrap_or(Codec::Json);
+        if let Err(err) = write_envelope_async(&mut writer, &envelope, codec).await {
+            warn!(target: TRANSPORT_LOG_TARGET, "failed to write outbound envelope: {err}");
+            return;
         }
-    } else {
-        // Avoid hanging process exit on a blocked stdio write during teardown.
-        eprintln!("writer thread still active during shutdown; skipping join");
     }
+}
 
-    Ok(())
+fn reconnect_delay(attempt: u32) -> Duration {
+    let index = (attempt as usize).min(RECONNECT_BACKOFF_MS.len() - 1);
+    Duration::from_millis(RECONNECT_BACKOFF_MS[index])
 }
 
-fn install_callbacks<B: HostBindings>(
-    ui: &B::Ui,
-    tx: SyncSender<UiEnvelope>,
-    sid: String,
-    next_intent_id: Arc<AtomicU64>,
-    dropped_intent_count: Arc<AtomicU64>,
-    queue_capacity: usize,
-) {
-    let bridge_tx = tx.clone();
-    let bridge_sid = sid.clone();
-    let bridge_next_id = next_intent_id.clone();
-    let bridge_drop_count = dropped_intent_count.clone();
+fn install_callbacks<B: HostBindings>(ui: &B::Ui, intents: IntentSender) {
+    let bridge_intents = intents.clone();
     B::bind_bridge_intent(ui, move |intent_name, intent_arg| {
         if intent_name.is_empty() {
             return;
@@ -309,21 +1302,10 @@ fn install_callbacks<B: HostBindings>(
             json!({ "arg": intent_arg })
         };
 
-        send_intent(
-            &bridge_tx,
-            bridge_sid.clone(),
-            &bridge_next_id,
-            &intent_name,
-            payload,
-            &bridge_drop_count,
-            queue_capacity,
-        );
+        bridge_intents.submit(&intent_name, payload);
     });
 
-    let intent_tx = tx.clone();
-    let intent_sid = sid.clone();
-    let intent_next_id = next_intent_id.clone();
-    let intent_drop_count = dropped_intent_count.clone();
+    let ui_intents = intents.clone();
     B::bind_ui_intent(ui, move |intent_name, intent_arg| {
         if intent_name.is_empty() {
             return;
@@ -335,21 +1317,9 @@ fn install_callbacks<B: HostBindings>(
             json!({ "arg": intent_arg })
         };
 
-        send_intent(
-            &intent_tx,
-            intent_sid.clone(),
-            &intent_next_id,
-            &intent_name,
-            payload,
-            &intent_drop_count,
-            queue_capacity,
-        );
+        ui_intents.submit(&intent_name, payload);
     });
 
-    let navigate_tx = tx.clone();
-    let navigate_sid = sid.clone();
-    let navigate_intent_id = next_intent_id.clone();
-    let navigate_drop_count = dropped_intent_count.clone();
     B::bind_navigate(ui, move |route_name, params_json| {
         if route_name.is_empty() {
             return;
@@ -358,53 +1328,334 @@ fn install_callbacks<B: HostBindings>(
         let params = parse_params_json(&params_json);
         let payload = json!({ "to": route_name, "params": params });
 
-        send_intent(
-            &navigate_tx,
-            navigate_sid.clone(),
-            &navigate_intent_id,
-            "ui.route.navigate",
-            payload,
-            &navigate_drop_count,
-            queue_capacity,
-        );
+        intents.submit("ui.route.navigate", payload);
     });
 }
 
-fn send_intent(
-    tx: &SyncSender<UiEnvelope>,
+/// Outbound side of the intent callbacks. Runs on the UI thread; intents held
+/// by the coalescer are flushed by single-shot timers.
+#[derive(Clone)]
+struct IntentSender {
+    tx: OutboundSender,
     sid: String,
-    next_intent_id: &AtomicU64,
-    name: &str,
-    payload: serde_json::Value,
-    dropped_intent_count: &AtomicU64,
+    next_intent_id: Arc<AtomicU64>,
+    dropped_intent_count: Arc<AtomicU64>,
     queue_capacity: usize,
-) {
-    let id = next_intent_id.fetch_add(1, Ordering::Relaxed);
-    let envelope = intent_envelope(sid, id, name.to_string(), payload);
-
-    match tx.try_send(envelope) {
-        Ok(()) => {}
-        Err(TrySendError::Full(_envelope)) => {
-            let dropped = dropped_intent_count.fetch_add(1, Ordering::Relaxed) + 1;
-            if dropped == 1 || dropped.is_power_of_two() {
-                eprintln!(
-                    "ui intent queue full (cap={queue_capacity}); dropped {dropped} intent(s)"
-                );
+    coalescer: Arc<Mutex<IntentCoalescer>>,
+    log: Arc<Mutex<IntentLog>>,
+}
+
+impl IntentSender {
+    fn submit(&self, name: &str, payload: Value) {
+        let now = Instant::now();
+        let Ok(mut coalescer) = self.coalescer.lock() else {
+            self.send(name, payload);
+            return;
+        };
+
+        let ready = coalescer.submit(name, payload, now);
+        let wake_at = coalescer.schedule_wake();
+        drop(coalescer);
+
+        for (name, payload) in ready {
+            self.send(&name, payload);
+        }
+        self.schedule_flush(wake_at, now);
+    }
+
+    fn flush(&self) {
+        let now = Instant::now();
+        let Ok(mut coalescer) = self.coalescer.lock() else {
+            return;
+        };
+
+        coalescer.woke();
+        let due = coalescer.take_due(now);
+        let wake_at = coalescer.schedule_wake();
+        drop(coalescer);
+
+        for (name, payload) in due {
+            self.send(&name, payload);
+        }
+        self.schedule_flush(wake_at, now);
+    }
+
+    fn schedule_flush(&self, wake_at: Option<Instant>, now: Instant) {
+        if let Some(wake_at) = wake_at {
+            let intents = self.clone();
+            slint::Timer::single_shot(wake_at.saturating_duration_since(now), move || {
+                intents.flush();
+            });
+        }
+    }
+
+    fn send(&self, name: &str, payload: Value) {
+        // Queued and logged under one lock, so a write-off never falls between.
+        let mut log = self.log.lock().ok();
+        let id = self.next_intent_id.fetch_add(1, Ordering::Relaxed);
+        let envelope = intent_envelope(self.sid.clone(), id, name.to_string(), payload.clone());
+
+        match self.tx.try_send(envelope) {
+            Ok(()) => {
+                if let Some(log) = log.as_mut() {
+                    log.record(id, name, payload);
+                }
+            }
+            Err(TrySendError::Full(_envelope)) => {
+                let dropped = self.dropped_intent_count.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped == 1 || dropped.is_power_of_two() {
+                    warn!(
+                        sid = self.sid.as_str(), dropped;
+                        "ui intent queue full (cap={}); dropped {dropped} intent(s)",
+                        self.queue_capacity
+                    );
+                }
+            }
+            Err(TrySendError::Disconnected(_envelope)) => {
+                warn!(sid = self.sid.as_str(); "failed to queue UI intent: {name}");
+            }
+        }
+    }
+
+    fn acknowledge(&self, ack: Option<u64>) {
+        if let Some(ack) = ack
+            && let Ok(mut log) = self.log.lock()
+        {
+            log.ack(ack);
+        }
+    }
+}
+
+/// Replays or discards the intents a resync wrote off, once the fresh render
+/// shows which of them the server processed.
+fn resolve_lost_intents<B: HostBindings>(intents: &IntentSender, ack: Option<u64>) {
+    let Ok(mut log) = intents.log.lock() else {
+        return;
+    };
+    let replay_enabled = log.replay_enabled();
+    let lost = log.take_lost(ack);
+    drop(log);
+
+    let mut discarded = Vec::new();
+    for intent in lost {
+        if replay_enabled && B::intent_replay(&intent.name) == IntentReplay::Replay {
+            info!(
+                sid = intents.sid.as_str();
+                "replaying intent {} (id={}) after resync",
+                intent.name, intent.id
+            );
+            intents.send(&intent.name, intent.payload);
+        } else {
+            discarded.push(format!("{}#{}", intent.name, intent.id));
+        }
+    }
+
+    if !discarded.is_empty() {
+        let message = format!(
+            "discarded {} intent(s) the server did not confirm before resync: {}",
+            discarded.len(),
+            discarded.join(", ")
+        );
+        warn!(sid = intents.sid.as_str(); "{message}");
+        enqueue_control_envelope(
+            &intents.tx,
+            diagnostic_envelope(
+                intents.sid.clone(),
+                "intents_discarded",
+                message,
+                None,
+                None,
+            ),
+        );
+    }
+}
+
+/// Decides when to send `applied` acknowledgements.
+///
+/// At most one is sent per `min_interval`; a revision applied inside the
+/// interval is held back and sent by a trailing timer, so Elixir always learns
+/// about the latest painted revision.
+#[derive(Debug)]
+struct AppliedReporter {
+    enabled: bool,
+    min_interval: Duration,
+    last_sent_at: Option<Instant>,
+    held: Option<(u64, u64)>,
+    trailing_scheduled: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum AppliedDecision {
+    Send { rev: u64, applied_at_ms: u64 },
+    SendLater(Duration),
+    Hold,
+}
+
+impl AppliedReporter {
+    fn new(min_interval: Duration) -> Self {
+        Self {
+            enabled: false,
+            min_interval,
+            last_sent_at: None,
+            held: None,
+            trailing_scheduled: false,
+        }
+    }
+
+    fn record(&mut self, rev: u64, applied_at_ms: u64, now: Instant) -> AppliedDecision {
+        if !self.enabled {
+            return AppliedDecision::Hold;
+        }
+
+        let elapsed = self
+            .last_sent_at
+            .map(|sent_at| now.saturating_duration_since(sent_at));
+
+        match elapsed {
+            Some(elapsed) if elapsed < self.min_interval => {
+                self.held = Some((rev, applied_at_ms));
+                if self.trailing_scheduled {
+                    AppliedDecision::Hold
+                } else {
+                    self.trailing_scheduled = true;
+                    AppliedDecision::SendLater(self.min_interval - elapsed)
+                }
             }
+            _ => {
+                self.last_sent_at = Some(now);
+                self.held = None;
+                AppliedDecision::Send { rev, applied_at_ms }
+            }
+        }
+    }
+
+    fn take_held(&mut self, now: Instant) -> Option<(u64, u64)> {
+        self.trailing_scheduled = false;
+        let held = self.held.take().filter(|_| self.enabled)?;
+        self.last_sent_at = Some(now);
+        Some(held)
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.min_interval);
+    }
+}
+
+fn report_applied(
+    reporter: &Arc<Mutex<AppliedReporter>>,
+    tx: &OutboundSender,
+    sid: &str,
+    rev: u64,
+) {
+    let Ok(mut guard) = reporter.lock() else {
+        return;
+    };
+
+    match guard.record(rev, unix_time_ms(), Instant::now()) {
+        AppliedDecision::Send { rev, applied_at_ms } => {
+            send_applied(tx, sid, rev, applied_at_ms);
+        }
+        AppliedDecision::SendLater(delay) => {
+            let reporter = reporter.clone();
+            let tx = tx.clone();
+            let sid = sid.to_string();
+            slint::Timer::single_shot(delay, move || {
+                let held = reporter
+                    .lock()
+                    .ok()
+                    .and_then(|mut reporter| reporter.take_held(Instant::now()));
+                if let Some((rev, applied_at_ms)) = held {
+                    send_applied(&tx, &sid, rev, applied_at_ms);
+                }
+            });
+        }
+        AppliedDecision::Hold => {}
+    }
+}
+
+// The control lane keeps the latest `applied` of each session, replacing any
+// older one still queued, so only a closed connection loses it.
+fn send_applied(tx: &OutboundSender, sid: &str, rev: u64, applied_at_ms: u64) {
+    if let Err(TrySendError::Disconnected(_)) =
+        tx.try_send(applied_envelope(sid.to_string(), rev, applied_at_ms))
+    {
+        warn!(sid; "failed to queue applied acknowledgement for rev={rev}");
+    }
+}
+
+fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Why the host asks for a resync; reported to Elixir as a `diagnostic` envelope.
+#[derive(Debug, Clone)]
+struct ResyncCause {
+    code: &'static str,
+    message: String,
+    rev: Option<u64>,
+    path: Option<String>,
+}
+
+impl ResyncCause {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            rev: None,
+            path: None,
         }
-        Err(TrySendError::Disconnected(_envelope)) => {
-            eprintln!("failed to queue UI intent: {name}");
+    }
+
+    fn at_rev(self, rev: u64) -> Self {
+        self.with_rev(Some(rev))
+    }
+
+    fn with_rev(mut self, rev: Option<u64>) -> Self {
+        self.rev = rev;
+        self
+    }
+
+    fn at_path(mut self, path: Option<String>) -> Self {
+        self.path = path;
+        self
+    }
+}
+
+/// Patch failure, with the path of the op that failed when one is known.
+#[derive(Debug)]
+struct PatchFailure {
+    path: Option<String>,
+    message: String,
+}
+
+impl PatchFailure {
+    fn without_path(message: String) -> Self {
+        Self {
+            path: None,
+            message,
         }
     }
 }
 
 fn request_resync(
-    tx: &SyncSender<UiEnvelope>,
+    tx: &OutboundSender,
     sid: &str,
-    reason: &str,
+    ready: &UiEnvelope,
+    cause: ResyncCause,
     resync_pending: &AtomicBool,
-    queue_capacity: usize,
 ) {
+    let diagnostic = diagnostic_envelope(
+        sid.to_string(),
+        cause.code,
+        cause.message.clone(),
+        cause.rev,
+        cause.path,
+    );
+    enqueue_control_envelope(tx, diagnostic);
+
     if resync_pending
         .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
         .is_err()
@@ -412,9 +1663,11 @@ fn request_resync(
         return;
     }
 
-    eprintln!("{reason}; requesting resync");
+    warn!(sid; "{}: {}; requesting resync", cause.code, cause.message);
 
-    enqueue_control_envelope(tx.clone(), ready_envelope(sid.to_string()), queue_capacity);
+    // The connection is still up, so intents queued behind this `ready` are
+    // delivered after it; only a new connection writes any off.
+    enqueue_control_envelope(tx, ready.clone());
 }
 
 fn apply_render<B: HostBindings>(
@@ -423,27 +1676,66 @@ fn apply_render<B: HostBindings>(
     ui_model_state: &mut UiModelState<B::ScreenId>,
 ) -> Result<(), String> {
     ui_model_state.vm = vm.clone();
+    ui_model_state.resume = None;
     apply_global_props::<B>(ui, &ui_model_state.vm);
     let screen_id = B::apply_screen_render(ui, vm)?;
     ui_model_state.screen_id = screen_id;
     Ok(())
 }
 
+/// Applies `ops` all or nothing: they change the view-model in place, and are
+/// undone if an op or the properties reject them. On failure the state and
+/// the properties stay at the last good frame.
 fn apply_patch<B: HostBindings>(
     ui: &B::Ui,
     ops: &[PatchOp],
     ui_model_state: &mut UiModelState<B::ScreenId>,
-) -> Result<(), String> {
-    apply_vm_patch_ops(&mut ui_model_state.vm, ops)?;
-    apply_global_props::<B>(ui, &ui_model_state.vm);
+) -> Result<(), PatchFailure> {
+    let undo = patch_vm(&mut ui_model_state.vm, ops)?;
 
-    if patch_changes_screen::<B>(ops) {
-        let screen_id = B::apply_screen_render(ui, &ui_model_state.vm)?;
-        ui_model_state.screen_id = screen_id;
-        Ok(())
+    let vm = &ui_model_state.vm;
+    apply_global_props::<B>(ui, vm);
+    let applied = if patch_changes_screen::<B>(ops) {
+        B::apply_screen_render(ui, vm)
     } else {
-        B::apply_screen_patch(ui, ui_model_state.screen_id, ops, &ui_model_state.vm)
+        B::apply_screen_patch(ui, ui_model_state.screen_id, ops, vm)
+            .map(|()| ui_model_state.screen_id)
+    };
+
+    match applied {
+        Ok(screen_id) => {
+            ui_model_state.screen_id = screen_id;
+            Ok(())
+        }
+        Err(message) => {
+            undo.rollback(&mut ui_model_state.vm);
+            // Some properties may already hold the new values.
+            apply_global_props::<B>(ui, &ui_model_state.vm);
+            if let Err(err) = B::apply_screen_render(ui, &ui_model_state.vm) {
+                warn!("failed to restore the last good frame: {err}");
+            }
+            Err(PatchFailure::without_path(message))
+        }
+    }
+}
+
+/// Applies `ops` to `vm` in place. When one fails, the ops before it are
+/// undone, so `vm` is left as it was. Only the values the ops write are
+/// copied, never the view-model.
+fn patch_vm<'a>(vm: &mut Value, ops: &'a [PatchOp]) -> Result<VmUndo<'a>, PatchFailure> {
+    let mut undo = VmUndo(Vec::with_capacity(ops.len()));
+
+    for op in ops {
+        if let Err(message) = apply_vm_patch_op(vm, op, &mut undo) {
+            undo.rollback(vm);
+            return Err(PatchFailure {
+                path: Some(op.path().to_string()),
+                message,
+            });
+        }
     }
+
+    Ok(undo)
 }
 
 fn apply_global_props<B: HostBindings>(ui: &B::Ui, vm: &Value) {
@@ -485,14 +1777,28 @@ fn apply_global_props<B: HostBindings>(ui: &B::Ui, vm: &Value) {
     B::set_error_screen_module(ui, error_screen_module);
 }
 
-fn patch_changes_screen<B: HostBindings>(ops: &[PatchOp]) -> bool {
-    ops.iter().any(|op| match op {
-        PatchOp::Replace { path, .. } | PatchOp::Add { path, .. } | PatchOp::Remove { path } => {
-            B::patch_changes_screen(path)
-        }
+fn patch_touches_windows(ops: &[PatchOp]) -> bool {
+    changed_paths(ops).any(|path| {
+        path.is_empty()
+            || path == WINDOWS_PATH
+            || path
+                .strip_prefix(WINDOWS_PATH)
+                .is_some_and(|rest| rest.starts_with('/'))
     })
 }
 
+fn patch_changes_screen<B: HostBindings>(ops: &[PatchOp]) -> bool {
+    changed_paths(ops).any(B::patch_changes_screen)
+}
+
+/// Every path a patch writes or deletes; `test` ops change nothing.
+fn changed_paths(ops: &[PatchOp]) -> impl Iterator<Item = &str> {
+    ops.iter()
+        .filter(|op| !matches!(op, PatchOp::Test { .. }))
+        .flat_map(|op| std::iter::once(op.path()).chain(op.removed_path()))
+        .map(Pointer::as_str)
+}
+
 pub fn validate_render_rev<ScreenId: Copy + Default>(
     state: &UiModelState<ScreenId>,
     rev: u64,
@@ -542,6 +1848,25 @@ pub fn reset_for_resync<ScreenId: Copy + Default>(state: &mut UiModelState<Scree
     *state = UiModelState::default();
 }
 
+/// Like `reset_for_resync`, but keeps the view-model so the server can answer
+/// the next `ready` with a patch from `last_rev` instead of a full render.
+pub fn suspend_for_resync<ScreenId: Copy + Default>(state: &mut UiModelState<ScreenId>) {
+    state.resume = resume_point(state);
+    state.last_rev = None;
+    state.last_ack = None;
+}
+
+pub fn resume_point<ScreenId: Copy + Default>(
+    state: &UiModelState<ScreenId>,
+) -> Option<ResumePoint> {
+    state.resume.clone().or_else(|| {
+        state.last_rev.map(|rev| ResumePoint {
+            rev,
+            vm_hash: vm_hash(&state.vm),
+        })
+    })
+}
+
 fn parse_params_json(raw: &str) -> Value {
     if raw.is_empty() {
         return json!({});
@@ -554,39 +1879,41 @@ fn parse_params_json(raw: &str) -> Value {
     }
 }
 
-fn enqueue_control_envelope(
-    tx: SyncSender<UiEnvelope>,
-    envelope: UiEnvelope,
-    queue_capacity: usize,
-) {
-    match tx.try_send(envelope) {
-        Ok(()) => {}
-        Err(TrySendError::Full(envelope)) => {
-            eprintln!(
-                "ui outbound queue full (cap={queue_capacity}); waiting to enqueue control envelope"
-            );
-            thread::spawn(move || {
-                if tx.send(envelope).is_err() {
-                    eprintln!("failed to enqueue control envelope");
-                }
-            });
-        }
-        Err(TrySendError::Disconnected(_envelope)) => {
-            eprintln!("failed to enqueue control envelope");
-        }
+fn enqueue_control_envelope(tx: &OutboundSender, envelope: UiEnvelope) {
+    // The control lane only refuses envelopes once the writer is gone.
+    if tx.try_send(envelope).is_err() {
+        error!(target: TRANSPORT_LOG_TARGET, "failed to enqueue control envelope");
     }
 }
 
-fn should_resync_for_error(code: &str) -> bool {
-    matches!(
-        code,
-        "decode_error"
-            | "frame_too_large"
-            | "invalid_envelope"
-            | "resync_required"
-            | "rev_mismatch"
-            | "patch_apply_error"
-    )
+fn show_server_error<B: HostBindings>(ui: &B::Ui, code: &ErrorCode, message: &str) {
+    B::set_error_title(ui, "Server Error");
+    B::set_error_message(ui, &format!("{code}: {message}"));
+    B::set_error_screen_module(ui, "");
+    B::set_active_screen(ui, "error");
+}
+
+/// Sessions served over the transport, one window each: `PROJECTION_SIDS` as
+/// a comma-separated list, else `PROJECTION_SID`, else `S1`.
+fn parse_session_ids() -> Vec<String> {
+    let raw = std::env::var("PROJECTION_SIDS")
+        .or_else(|_| std::env::var("PROJECTION_SID"))
+        .unwrap_or_default();
+    session_ids(&raw)
+}
+
+fn session_ids(raw: &str) -> Vec<String> {
+    let mut sids: Vec<String> = Vec::new();
+    for sid in raw.split(',').map(str::trim) {
+        if !sid.is_empty() && !sids.iter().any(|known| known == sid) {
+            sids.push(sid.to_string());
+        }
+    }
+
+    if sids.is_empty() {
+        sids.push("S1".to_string());
+    }
+    sids
 }
 
 fn parse_outbound_queue_capacity() -> usize {
@@ -597,92 +1924,300 @@ fn parse_outbound_queue_capacity() -> usize {
         .unwrap_or(DEFAULT_UI_OUTBOUND_QUEUE_CAP)
 }
 
-fn apply_vm_patch_ops(vm: &mut Value, ops: &[PatchOp]) -> Result<(), String> {
-    for op in ops {
-        match op {
-            PatchOp::Replace { path, value } => set_path(vm, path, value.clone(), true)?,
-            PatchOp::Add { path, value } => set_path(vm, path, value.clone(), false)?,
-            PatchOp::Remove { path } => remove_path(vm, path)?,
+fn parse_control_queue_capacity() -> usize {
+    std::env::var("PROJECTION_UI_CONTROL_QUEUE_CAP")
+        .ok()
+        .and_then(|raw| raw.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_UI_CONTROL_QUEUE_CAP)
+}
+
+fn parse_applied_min_interval() -> Duration {
+    std::env::var("PROJECTION_APPLIED_MIN_INTERVAL_MS")
+        .ok()
+        .and_then(|raw| raw.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::ZERO)
+}
+
+fn apply_vm_patch_op<'a>(
+    vm: &mut Value,
+    op: &'a PatchOp,
+    undo: &mut VmUndo<'a>,
+) -> Result<(), String> {
+    match op {
+        PatchOp::Replace { path, value } => set_path(vm, path, value.clone(), true, undo),
+        PatchOp::Add { path, value } => set_path(vm, path, value.clone(), false, undo),
+        PatchOp::Remove { path } => {
+            let (slot, removed) = remove_path(vm, path)?;
+            undo.0.push(UndoStep::Removed(slot, removed));
+            Ok(())
+        }
+        PatchOp::Move { from, path } => {
+            if path.tokens().len() > from.tokens().len()
+                && path.tokens().zip(from.tokens()).all(|(a, b)| a == b)
+            {
+                return Err(format!("cannot move {from} into its own child {path}"));
+            }
+
+            let (slot, value) = remove_path(vm, from)?;
+            undo.0.push(UndoStep::Removed(slot, value.clone()));
+            set_path(vm, path, value, false, undo)
+        }
+        PatchOp::Copy { from, path } => {
+            let value = get_path(vm, from)?.clone();
+            set_path(vm, path, value, false, undo)
+        }
+        PatchOp::Test { path, value } => {
+            let found = get_path(vm, path)?;
+            if !json_equal(found, value) {
+                return Err(format!(
+                    "test failed at {path}: expected {value}, found {found}"
+                ));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// A place in the view-model: under the container at the first `depth`
+/// tokens of `path`.
+#[derive(Debug)]
+struct Slot<'a> {
+    path: &'a Pointer,
+    depth: usize,
+    at: SlotKey<'a>,
+}
+
+#[derive(Debug)]
+enum SlotKey<'a> {
+    Root,
+    Key(&'a str),
+    Index(usize),
+}
+
+#[derive(Debug)]
+enum UndoStep<'a> {
+    /// A new key or array item went in.
+    Filled(Slot<'a>),
+    /// The slot held this value before it was replaced.
+    Overwritten(Slot<'a>, Value),
+    /// This value was taken out of the slot.
+    Removed(Slot<'a>, Value),
+}
+
+/// What `patch_vm` changed, newest last.
+#[derive(Debug)]
+struct VmUndo<'a>(Vec<UndoStep<'a>>);
+
+impl Slot<'_> {
+    fn container<'v>(&self, vm: &'v mut Value) -> Option<&'v mut Value> {
+        self.path
+            .tokens()
+            .take(self.depth)
+            .try_fold(vm, descend_existing)
+    }
+}
+
+impl VmUndo<'_> {
+    /// Puts `vm` back as it was before the ops, undoing the newest first.
+    fn rollback(self, vm: &mut Value) {
+        for step in self.0.into_iter().rev() {
+            let undone = match step {
+                UndoStep::Filled(slot) => match (slot.container(vm), slot.at) {
+                    (Some(Value::Object(map)), SlotKey::Key(key)) => map.remove(key).is_some(),
+                    (Some(Value::Array(items)), SlotKey::Index(index)) if index < items.len() => {
+                        items.remove(index);
+                        true
+                    }
+                    _ => false,
+                },
+                UndoStep::Overwritten(slot, previous) => {
+                    let target = match (slot.container(vm), slot.at) {
+                        (Some(root), SlotKey::Root) => Some(root),
+                        (Some(Value::Object(map)), SlotKey::Key(key)) => map.get_mut(key),
+                        (Some(Value::Array(items)), SlotKey::Index(index)) => items.get_mut(index),
+                        _ => None,
+                    };
+                    target.map(|target| *target = previous).is_some()
+                }
+                UndoStep::Removed(slot, previous) => match (slot.container(vm), slot.at) {
+                    (Some(root), SlotKey::Root) => {
+                        *root = previous;
+                        true
+                    }
+                    (Some(Value::Object(map)), SlotKey::Key(key)) => {
+                        map.insert(key.to_string(), previous);
+                        true
+                    }
+                    (Some(Value::Array(items)), SlotKey::Index(index)) if index <= items.len() => {
+                        items.insert(index, previous);
+                        true
+                    }
+                    _ => false,
+                },
+            };
+
+            if !undone {
+                warn!("failed to undo a patch op; the view-model may be inconsistent");
+            }
         }
     }
+}
 
-    Ok(())
+/// Equality as RFC 6902 `test` defines it: numbers compare by value, so `1`
+/// equals `1.0`, at any depth.
+fn json_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => {
+            match (left.as_i64(), right.as_i64(), left.as_u64(), right.as_u64()) {
+                (Some(left), Some(right), _, _) => left == right,
+                (_, _, Some(left), Some(right)) => left == right,
+                _ => left.as_f64() == right.as_f64(),
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .zip(right)
+                    .all(|(left, right)| json_equal(left, right))
+        }
+        (Value::Object(left), Value::Object(right)) => {
+            left.len() == right.len()
+                && left
+                    .iter()
+                    .all(|(key, left)| right.get(key).is_some_and(|right| json_equal(left, right)))
+        }
+        _ => left == right,
+    }
 }
 
-fn set_path(root: &mut Value, path: &str, value: Value, replace_only: bool) -> Result<(), String> {
-    let tokens = parse_pointer(path)?;
+fn get_path<'a>(root: &'a Value, path: &Pointer) -> Result<&'a Value, String> {
+    path.get(root)
+        .ok_or_else(|| format!("path does not exist: {path}"))
+}
 
-    if tokens.is_empty() {
-        *root = value;
+fn set_path<'a>(
+    root: &mut Value,
+    path: &'a Pointer,
+    value: Value,
+    replace_only: bool,
+    undo: &mut VmUndo<'a>,
+) -> Result<(), String> {
+    let Some((parents, last)) = path.tokens().split_last() else {
+        let slot = Slot {
+            path,
+            depth: 0,
+            at: SlotKey::Root,
+        };
+        let previous = std::mem::replace(root, value);
+        undo.0.push(UndoStep::Overwritten(slot, previous));
         return Ok(());
-    }
+    };
 
+    let depth = parents.len();
     let mut current = root;
 
-    for token in &tokens[..tokens.len() - 1] {
-        current = descend_or_create(current, token)?;
+    for (index, token) in parents.enumerate() {
+        current = descend_or_create(current, path, index, token, undo)?;
     }
 
-    let last = tokens.last().expect("tokens not empty");
-
     match current {
         Value::Object(map) => {
-            if replace_only && !map.contains_key(last) {
-                return Err(format!("replace path does not exist: {path}"));
+            let slot = Slot {
+                path,
+                depth,
+                at: SlotKey::Key(last),
+            };
+
+            match map.get_mut(last) {
+                Some(existing) => {
+                    let previous = std::mem::replace(existing, value);
+                    undo.0.push(UndoStep::Overwritten(slot, previous));
+                }
+                None if replace_only => {
+                    return Err(format!("replace path does not exist: {path}"));
+                }
+                None => {
+                    map.insert(last.to_string(), value);
+                    undo.0.push(UndoStep::Filled(slot));
+                }
             }
-
-            map.insert(last.clone(), value);
             Ok(())
         }
-        Value::Array(items) => {
+        Value::Array(items) if replace_only => {
             let index = parse_index(last, items.len(), path)?;
-
-            if index == items.len() {
-                items.push(value);
+            let previous = std::mem::replace(&mut items[index], value);
+            let slot = Slot {
+                path,
+                depth,
+                at: SlotKey::Index(index),
+            };
+            undo.0.push(UndoStep::Overwritten(slot, previous));
+            Ok(())
+        }
+        Value::Array(items) => {
+            // RFC 6902 `add` inserts and shifts; `-` appends.
+            let index = if last == "-" {
+                items.len()
             } else {
-                items[index] = value;
-            }
-
+                parse_index(last, items.len() + 1, path)?
+            };
+
+            items.insert(index, value);
+            let slot = Slot {
+                path,
+                depth,
+                at: SlotKey::Index(index),
+            };
+            undo.0.push(UndoStep::Filled(slot));
             Ok(())
         }
         _ => Err(format!("cannot set path on non-container parent: {path}")),
     }
 }
 
-fn remove_path(root: &mut Value, path: &str) -> Result<(), String> {
-    let tokens = parse_pointer(path)?;
-
-    if tokens.is_empty() {
-        *root = Value::Object(serde_json::Map::new());
-        return Ok(());
-    }
+/// Removes the value at `path` and returns it with the slot it came from.
+fn remove_path<'a>(root: &mut Value, path: &'a Pointer) -> Result<(Slot<'a>, Value), String> {
+    let Some((parents, last)) = path.tokens().split_last() else {
+        let slot = Slot {
+            path,
+            depth: 0,
+            at: SlotKey::Root,
+        };
+        let removed = std::mem::replace(root, Value::Object(serde_json::Map::new()));
+        return Ok((slot, removed));
+    };
 
+    let depth = parents.len();
     let mut current = root;
 
-    for token in &tokens[..tokens.len() - 1] {
+    for token in parents {
         current = descend_existing(current, token)
             .ok_or_else(|| format!("remove path does not exist: {path}"))?;
     }
 
-    let last = tokens.last().expect("tokens not empty");
-
     match current {
         Value::Object(map) => {
-            if map.remove(last).is_some() {
-                Ok(())
-            } else {
-                Err(format!("remove path does not exist: {path}"))
-            }
+            let removed = map
+                .remove(last)
+                .ok_or_else(|| format!("remove path does not exist: {path}"))?;
+            let slot = Slot {
+                path,
+                depth,
+                at: SlotKey::Key(last),
+            };
+            Ok((slot, removed))
         }
         Value::Array(items) => {
-            let index = parse_index(last, items.len().saturating_sub(1), path)?;
-
-            if index < items.len() {
-                items.remove(index);
-                Ok(())
-            } else {
-                Err(format!("remove path index out of bounds: {path}"))
-            }
+            let index = parse_index(last, items.len(), path)?;
+            let slot = Slot {
+                path,
+                depth,
+                at: SlotKey::Index(index),
+            };
+            Ok((slot, items.remove(index)))
         }
         _ => Err(format!(
             "cannot remove path on non-container parent: {path}"
@@ -690,53 +2225,30 @@ fn remove_path(root: &mut Value, path: &str) -> Result<(), String> {
     }
 }
 
-fn parse_pointer(path: &str) -> Result<Vec<String>, String> {
-    if path.is_empty() {
-        return Ok(vec![]);
-    }
-
-    if !path.starts_with('/') {
-        return Err(format!("invalid json pointer path: {path}"));
-    }
-
-    path.split('/')
-        .skip(1)
-        .map(unescape_json_pointer_token)
-        .collect()
-}
-
-fn unescape_json_pointer_token(token: &str) -> Result<String, String> {
-    let mut out = String::with_capacity(token.len());
-    let mut chars = token.chars();
-
-    while let Some(ch) = chars.next() {
-        if ch == '~' {
-            match chars.next() {
-                Some('0') => out.push('~'),
-                Some('1') => out.push('/'),
-                Some(other) => {
-                    return Err(format!("invalid escape ~{other} in json pointer token"));
-                }
-                None => return Err("trailing ~ in json pointer token".to_string()),
+/// Steps into `token`, the one at `depth` in `path`, creating a missing
+/// object key on the way.
+fn descend_or_create<'v, 'a>(
+    value: &'v mut Value,
+    path: &'a Pointer,
+    depth: usize,
+    token: &'a str,
+    undo: &mut VmUndo<'a>,
+) -> Result<&'v mut Value, String> {
+    match value {
+        Value::Object(map) => {
+            if !map.contains_key(token) {
+                map.insert(token.to_string(), Value::Object(serde_json::Map::new()));
+                undo.0.push(UndoStep::Filled(Slot {
+                    path,
+                    depth,
+                    at: SlotKey::Key(token),
+                }));
             }
-        } else {
-            out.push(ch);
+            Ok(map.get_mut(token).expect("inserted above"))
         }
-    }
-
-    Ok(out)
-}
-
-fn descend_or_create<'a>(value: &'a mut Value, token: &str) -> Result<&'a mut Value, String> {
-    match value {
-        Value::Object(map) => Ok(map
-            .entry(token.to_string())
-            .or_insert_with(|| Value::Object(serde_json::Map::new()))),
         Value::Array(items) => {
             let index = parse_index(token, items.len(), token)?;
-            items
-                .get_mut(index)
-                .ok_or_else(|| format!("array index out of bounds at token {token}"))
+            Ok(&mut items[index])
         }
         _ => Err(format!(
             "cannot descend into non-container value at token {token}"
@@ -747,31 +2259,55 @@ fn descend_or_create<'a>(value: &'a mut Value, token: &str) -> Result<&'a mut Va
 fn descend_existing<'a>(value: &'a mut Value, token: &str) -> Option<&'a mut Value> {
     match value {
         Value::Object(map) => map.get_mut(token),
-        Value::Array(items) => token
-            .parse::<usize>()
-            .ok()
-            .and_then(|index| items.get_mut(index)),
+        Value::Array(items) => array_index(token).and_then(|index| items.get_mut(index)),
         _ => None,
     }
 }
 
-fn parse_index(token: &str, max_len: usize, path: &str) -> Result<usize, String> {
-    let index = token
-        .parse::<usize>()
-        .map_err(|_| format!("invalid array index '{token}' at path {path}"))?;
+/// Parses an array index that must be below `len`.
+fn parse_index(token: &str, len: usize, path: impl fmt::Display) -> Result<usize, String> {
+    let index = array_index(token)
+        .ok_or_else(|| format!("invalid array index '{token}' at path {path}"))?;
 
-    if index > max_len {
+    if index < len {
+        Ok(index)
+    } else {
         Err(format!(
             "array index out of bounds '{token}' at path {path}"
         ))
-    } else {
-        Ok(index)
     }
 }
 
+/// Defines `ProjectionRuntimeBindings` for the generated window and screens.
 #[macro_export]
-macro_rules! app_main {
-    ($window:ty, $ui_global:ty, $error_global:ty, $generated:ident) => {
+macro_rules! app_bindings {
+    (@binding error_policy = $error_policy:path) => {
+        fn error_policy(code: &$crate::ErrorCode) -> $crate::ErrorPolicy {
+            $error_policy(code)
+        }
+    };
+    (@binding intent_policy = $intent_policy:path) => {
+        fn intent_policy(name: &str) -> Option<$crate::IntentPolicy> {
+            $intent_policy(name)
+        }
+    };
+    (@binding intent_replay = $intent_replay:path) => {
+        fn intent_replay(name: &str) -> $crate::IntentReplay {
+            $intent_replay(name)
+        }
+    };
+    (@binding inbound_stats = $inbound_stats:path) => {
+        fn inbound_stats(ui: &Self::Ui, stats: $crate::InboundStats) {
+            $inbound_stats(ui, stats)
+        }
+    };
+    (
+        $window:ty,
+        $ui_global:ty,
+        $error_global:ty,
+        $generated:ident
+        $(, $binding:ident = $value:path)* $(,)?
+    ) => {
         struct ProjectionRuntimeBindings;
 
         impl $crate::HostBindings for ProjectionRuntimeBindings {
@@ -822,6 +2358,14 @@ macro_rules! app_main {
                 ui.set_nav_can_back(nav_can_back);
             }
 
+            fn set_connection_stale(ui: &Self::Ui, stale: bool) {
+                ui.set_connection_stale(stale);
+            }
+
+            fn set_modal_open(ui: &Self::Ui, open: bool) {
+                ui.set_modal_open(open);
+            }
+
             fn set_error_title(ui: &Self::Ui, title: &str) {
                 let error_state = ui.global::<$error_global>();
                 error_state.set_error_title(title.into());
@@ -852,7 +2396,21 @@ macro_rules! app_main {
             ) -> Result<(), String> {
                 $generated::apply_patch(ui, screen_id, ops, vm)
             }
+
+            fn new_window(kind: &str) -> Result<Box<dyn $crate::ProjectionWindow>, String> {
+                $generated::new_window(kind)
+            }
+
+            $($crate::app_bindings!(@binding $binding = $value);)*
         }
+    };
+}
+
+/// `app_bindings!` plus a `main` that runs the host on its blocking threads.
+#[macro_export]
+macro_rules! app_main {
+    ($($args:tt)*) => {
+        $crate::app_bindings!($($args)*);
 
         fn main() {
             if let Err(err) = $crate::run::<ProjectionRuntimeBindings>() {
@@ -866,43 +2424,169 @@ macro_rules! app_main {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::mpsc;
 
     #[test]
-    fn send_intent_drops_when_queue_is_full() {
-        let (tx, rx) = mpsc::sync_channel(1);
-        let next_intent_id = AtomicU64::new(1);
-        let dropped = AtomicU64::new(0);
+    fn session_ids_are_split_deduplicated_and_default_to_s1() {
+        assert_eq!(session_ids("left, right,,left"), vec!["left", "right"]);
+        assert_eq!(session_ids(" S2 "), vec!["S2"]);
+        assert_eq!(session_ids(""), vec!["S1"]);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_mode_rejects_a_current_thread_runtime() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("runtime");
+        let err = require_multi_thread(runtime.handle()).expect_err("current-thread runtime");
+        assert!(err.to_string().contains("multi-thread"));
+    }
+
+    #[test]
+    fn applied_reporter_rate_limits_and_holds_the_latest_rev() {
+        let mut reporter = AppliedReporter::new(Duration::from_millis(100));
+        let start = Instant::now();
+
+        assert_eq!(reporter.record(1, 10, start), AppliedDecision::Hold);
+
+        reporter.enabled = true;
+        assert_eq!(
+            reporter.record(1, 10, start),
+            AppliedDecision::Send {
+                rev: 1,
+                applied_at_ms: 10
+            }
+        );
+        assert_eq!(
+            reporter.record(2, 20, start + Duration::from_millis(40)),
+            AppliedDecision::SendLater(Duration::from_millis(60))
+        );
+        assert_eq!(
+            reporter.record(3, 30, start + Duration::from_millis(50)),
+            AppliedDecision::Hold
+        );
+        assert_eq!(
+            reporter.take_held(start + Duration::from_millis(100)),
+            Some((3, 30))
+        );
+        assert_eq!(reporter.take_held(start + Duration::from_millis(150)), None);
 
-        tx.send(ready_envelope("S1".to_string()))
-            .expect("seed queue with one envelope");
+        reporter.reset();
+        assert!(!reporter.enabled);
+    }
 
-        send_intent(
+    #[test]
+    fn request_resync_reports_every_cause_but_sends_ready_once() {
+        let (tx, rx) = outbound_queue(8, 8);
+        let ready = ready_envelope("S1".to_string(), "stdio-packet-4");
+        let pending = AtomicBool::new(false);
+        let log = Mutex::new(IntentLog::new(8));
+        let written_off = log.lock().unwrap().written_off();
+        log.lock().unwrap().record(1, "clock.pause", json!({}));
+
+        request_resync(
             &tx,
-            "S1".to_string(),
-            &next_intent_id,
-            "clock.pause",
-            json!({}),
-            &dropped,
-            1,
+            "S1",
+            &ready,
+            ResyncCause::new("patch_apply_failed", "remove path does not exist: /a")
+                .at_rev(3)
+                .at_path(Some("/a".to_string())),
+            &pending,
+        );
+        log.lock().unwrap().record(2, "clock.resume", json!({}));
+        request_resync(
+            &tx,
+            "S1",
+            &ready,
+            ResyncCause::new("rev_mismatch", "patch revision mismatch").at_rev(4),
+            &pending,
         );
 
-        assert_eq!(dropped.load(Ordering::Relaxed), 1);
+        // The connection is up, so the queued intents still go out.
+        assert_eq!(written_off.load(Ordering::Acquire), 0);
+
+        let queued: Vec<UiEnvelope> = std::iter::from_fn(|| rx.try_recv()).collect();
+        assert_eq!(queued.len(), 3);
 
-        let seeded = rx.try_recv().expect("seed envelope remains queued");
-        match seeded {
-            UiEnvelope::Ready { sid, .. } => assert_eq!(sid, "S1"),
-            other => panic!("expected ready envelope, got {other:?}"),
+        match &queued[0] {
+            UiEnvelope::Diagnostic {
+                code, rev, path, ..
+            } => {
+                assert_eq!(code, "patch_apply_failed");
+                assert_eq!(*rev, Some(3));
+                assert_eq!(path.as_deref(), Some("/a"));
+            }
+            other => panic!("expected diagnostic, got {other:?}"),
         }
+        assert!(matches!(queued[1], UiEnvelope::Ready { .. }));
+        assert!(
+            matches!(&queued[2], UiEnvelope::Diagnostic { code, path: None, .. } if code == "rev_mismatch")
+        );
+    }
+
+    #[test]
+    fn send_intent_drops_when_intent_lane_is_full() {
+        let (tx, rx) = outbound_queue(1, 1);
+        let intents = IntentSender {
+            tx: tx.clone(),
+            sid: "S1".to_string(),
+            next_intent_id: Arc::new(AtomicU64::new(1)),
+            dropped_intent_count: Arc::new(AtomicU64::new(0)),
+            queue_capacity: 1,
+            coalescer: Arc::new(Mutex::new(IntentCoalescer::new(|_| None))),
+            log: Arc::new(Mutex::new(IntentLog::new(8))),
+        };
+
+        tx.try_send(intent_envelope(
+            "S1".to_string(),
+            1,
+            "clock.resume",
+            json!({}),
+        ))
+        .expect("seed intent lane with one envelope");
+        tx.try_send(ready_envelope("S1".to_string(), "stdio-packet-4"))
+            .expect("control lane is unaffected");
+
+        intents.send("clock.pause", json!({}));
+
+        assert_eq!(intents.dropped_intent_count.load(Ordering::Relaxed), 1);
+
+        // A dropped intent is not waiting for an ack.
+        let mut log = intents.log.lock().unwrap();
+        log.write_off();
+        assert!(log.take_lost(None).is_empty());
+
+        assert!(matches!(rx.try_recv(), Some(UiEnvelope::Ready { .. })));
+        assert!(matches!(
+            rx.try_recv(),
+            Some(UiEnvelope::Intent { name, .. }) if name == "clock.resume"
+        ));
+        assert!(rx.try_recv().is_none());
+    }
+
+    #[test]
+    fn reconnect_delay_backs_off_and_caps() {
+        assert_eq!(reconnect_delay(0), Duration::from_millis(100));
+        assert_eq!(reconnect_delay(3), Duration::from_millis(1_000));
+        assert_eq!(reconnect_delay(50), Duration::from_millis(5_000));
     }
 
     #[test]
-    fn resync_error_codes_are_explicit() {
-        assert!(should_resync_for_error("decode_error"));
-        assert!(should_resync_for_error("frame_too_large"));
-        assert!(should_resync_for_error("invalid_envelope"));
-        assert!(should_resync_for_error("resync_required"));
-        assert!(!should_resync_for_error("validation_warning"));
+    fn default_error_policy_resyncs_known_codes_and_shows_unknown_ones() {
+        for code in [
+            "decode_error",
+            "frame_too_large",
+            "invalid_envelope",
+            "resync_required",
+            "rev_mismatch",
+            "patch_apply_error",
+        ] {
+            let code = ErrorCode::from(code.to_string());
+            assert_eq!(default_error_policy(&code), ErrorPolicy::Resync, "{code}");
+        }
+
+        let unknown = ErrorCode::from("validation_warning".to_string());
+        assert_eq!(default_error_policy(&unknown), ErrorPolicy::ShowError);
     }
 
     #[test]
@@ -913,6 +2597,293 @@ mod tests {
         assert!(validate_render_rev(&state, 2).is_ok());
     }
 
+    fn apply_json_patch(doc: Value, patch: Value) -> Result<Value, String> {
+        let ops: Vec<PatchOp> = serde_json::from_value(patch).map_err(|err| err.to_string())?;
+        let mut doc = doc;
+        patch_vm(&mut doc, &ops).map_err(|failure| failure.message)?;
+        Ok(doc)
+    }
+
+    #[test]
+    fn vm_patches_follow_the_rfc_6902_conformance_suite() {
+        // Cases from the json-patch-tests suite (RFC 6902 appendix A and tests.json).
+        let cases = [
+            (
+                json!({ "foo": "bar" }),
+                json!([{ "op": "add", "path": "/baz", "value": "qux" }]),
+                json!({ "baz": "qux", "foo": "bar" }),
+            ),
+            (
+                json!({ "foo": ["bar", "baz"] }),
+                json!([{ "op": "add", "path": "/foo/1", "value": "qux" }]),
+                json!({ "foo": ["bar", "qux", "baz"] }),
+            ),
+            (
+                json!({ "foo": ["bar"] }),
+                json!([{ "op": "add", "path": "/foo/0", "value": "baz" }]),
+                json!({ "foo": ["baz", "bar"] }),
+            ),
+            (
+                json!({ "foo": ["bar"] }),
+                json!([{ "op": "add", "path": "/foo/1", "value": "baz" }]),
+                json!({ "foo": ["bar", "baz"] }),
+            ),
+            (
+                json!({ "foo": ["bar"] }),
+                json!([{ "op": "add", "path": "/foo/-", "value": ["abc", "def"] }]),
+                json!({ "foo": ["bar", ["abc", "def"]] }),
+            ),
+            (
+                json!([1, 2]),
+                json!([{ "op": "add", "path": "/-", "value": { "foo": ["bar", "baz"] } }]),
+                json!([1, 2, { "foo": ["bar", "baz"] }]),
+            ),
+            (
+                json!([1, 2, [3, [4, 5]]]),
+                json!([{ "op": "add", "path": "/2/1/-", "value": { "foo": ["bar", "baz"] } }]),
+                json!([1, 2, [3, [4, 5, { "foo": ["bar", "baz"] }]]]),
+            ),
+            (
+                json!({ "foo": 1 }),
+                json!([{ "op": "add", "path": "", "value": [] }]),
+                json!([]),
+            ),
+            (
+                json!({ "foo": 1, "bar": [1, 2, 3, 4] }),
+                json!([{ "op": "remove", "path": "/bar" }]),
+                json!({ "foo": 1 }),
+            ),
+            (
+                json!({ "foo": ["bar", "qux", "baz"] }),
+                json!([{ "op": "remove", "path": "/foo/1" }]),
+                json!({ "foo": ["bar", "baz"] }),
+            ),
+            (
+                json!([1, 2, 3, 4]),
+                json!([{ "op": "remove", "path": "/0" }]),
+                json!([2, 3, 4]),
+            ),
+            (
+                json!({ "foo": "bar" }),
+                json!([{ "op": "replace", "path": "/foo", "value": "baz" }]),
+                json!({ "foo": "baz" }),
+            ),
+            (
+                json!([""]),
+                json!([{ "op": "replace", "path": "/0", "value": 0 }]),
+                json!([0]),
+            ),
+            (
+                json!({ "/": 9, "~1": 10 }),
+                json!([{ "op": "add", "path": "/~01", "value": 11 }]),
+                json!({ "/": 9, "~1": 11 }),
+            ),
+            (
+                json!([1, 2, 3, 4]),
+                json!([
+                    { "op": "remove", "path": "/1" },
+                    { "op": "add", "path": "/1", "value": "x" },
+                    { "op": "add", "path": "/-", "value": "y" },
+                ]),
+                json!([1, "x", 3, 4, "y"]),
+            ),
+            (
+                json!({ "foo": { "bar": "baz", "waldo": "fred" }, "qux": { "corge": "grault" } }),
+                json!([{ "op": "move", "from": "/foo/waldo", "path": "/qux/thud" }]),
+                json!({ "foo": { "bar": "baz" }, "qux": { "corge": "grault", "thud": "fred" } }),
+            ),
+            (
+                json!({ "foo": ["all", "grass", "cows", "eat"] }),
+                json!([{ "op": "move", "from": "/foo/1", "path": "/foo/3" }]),
+                json!({ "foo": ["all", "cows", "eat", "grass"] }),
+            ),
+            (
+                json!({ "baz": [{ "qux": "hello" }], "bar": 1 }),
+                json!([{ "op": "copy", "from": "/baz/0", "path": "/boo" }]),
+                json!({ "baz": [{ "qux": "hello" }], "bar": 1, "boo": { "qux": "hello" } }),
+            ),
+            (
+                json!({ "baz": "qux", "foo": ["a", 2, "c"] }),
+                json!([
+                    { "op": "test", "path": "/baz", "value": "qux" },
+                    { "op": "test", "path": "/foo/1", "value": 2 },
+                ]),
+                json!({ "baz": "qux", "foo": ["a", 2, "c"] }),
+            ),
+            (
+                json!({ "/": 9, "~1": 10 }),
+                json!([{ "op": "test", "path": "/~01", "value": 10 }]),
+                json!({ "/": 9, "~1": 10 }),
+            ),
+            // Numbers are equal when their values are (RFC 6902 section 4.6).
+            (
+                json!({ "foo": 1 }),
+                json!([{ "op": "test", "path": "/foo", "value": 1.0 }]),
+                json!({ "foo": 1 }),
+            ),
+            (
+                json!({ "foo": { "bar": [1, 2.5, { "baz": 3.0 }] } }),
+                json!([
+                    { "op": "test", "path": "/foo", "value": { "bar": [1.0, 2.5, { "baz": 3 }] } },
+                ]),
+                json!({ "foo": { "bar": [1, 2.5, { "baz": 3.0 }] } }),
+            ),
+        ];
+
+        for (doc, patch, expected) in cases {
+            assert_eq!(
+                apply_json_patch(doc, patch.clone()).as_ref(),
+                Ok(&expected),
+                "{patch}"
+            );
+        }
+
+        let errors = [
+            (
+                json!({ "foo": ["bar", "baz"] }),
+                json!([{ "op": "add", "path": "/foo/3", "value": "qux" }]),
+            ),
+            (
+                json!(["foo", "sil"]),
+                json!([{ "op": "add", "path": "/bar", "value": 42 }]),
+            ),
+            (
+                json!(["foo", "sil"]),
+                json!([{ "op": "add", "path": "/1e0", "value": "bar" }]),
+            ),
+            (
+                json!(["foo", "sil"]),
+                json!([{ "op": "add", "path": "/01", "value": "bar" }]),
+            ),
+            (
+                json!(["foo", "sil"]),
+                json!([{ "op": "add", "path": "/-1", "value": "bar" }]),
+            ),
+            (
+                json!({ "foo": ["bar"] }),
+                json!([{ "op": "replace", "path": "/foo/1", "value": "baz" }]),
+            ),
+            (
+                json!({ "foo": ["bar"] }),
+                json!([{ "op": "replace", "path": "/foo/-", "value": "baz" }]),
+            ),
+            (
+                json!({ "foo": ["bar"] }),
+                json!([{ "op": "remove", "path": "/foo/1" }]),
+            ),
+            (
+                json!({ "foo": ["bar"] }),
+                json!([{ "op": "remove", "path": "/foo/-" }]),
+            ),
+            (
+                json!({ "baz": "qux" }),
+                json!([{ "op": "replace", "path": "/foo", "value": "bar" }]),
+            ),
+            (
+                json!({ "baz": "qux" }),
+                json!([{ "op": "test", "path": "/baz", "value": "bar" }]),
+            ),
+            (
+                json!({ "/": 9, "~1": 10 }),
+                json!([{ "op": "test", "path": "/~01", "value": "10" }]),
+            ),
+            (
+                json!({ "foo": 1 }),
+                json!([{ "op": "test", "path": "/bar", "value": 1 }]),
+            ),
+            (
+                json!({ "foo": 1 }),
+                json!([{ "op": "test", "path": "/foo", "value": 1.5 }]),
+            ),
+            (
+                json!({ "foo": [1, 2] }),
+                json!([{ "op": "test", "path": "/foo", "value": [1.0] }]),
+            ),
+            (
+                json!({ "foo": { "bar": 1 } }),
+                json!([{ "op": "test", "path": "/foo", "value": { "bar": 1, "baz": 2 } }]),
+            ),
+            (
+                json!({ "foo": 1 }),
+                json!([{ "op": "move", "from": "/bar", "path": "/baz" }]),
+            ),
+            (
+                json!({ "foo": { "bar": 1 } }),
+                json!([{ "op": "move", "from": "/foo", "path": "/foo/bar/baz" }]),
+            ),
+            (
+                json!({ "foo": 1 }),
+                json!([{ "op": "copy", "from": "/bar", "path": "/baz" }]),
+            ),
+        ];
+
+        for (doc, patch) in errors {
+            assert!(apply_json_patch(doc, patch.clone()).is_err(), "{patch}");
+        }
+    }
+
+    #[test]
+    fn a_failing_op_leaves_the_view_model_untouched() {
+        let vm = json!({ "screen": { "vm": { "items": ["a", "b"], "count": 2 } } });
+        let ops: Vec<PatchOp> = serde_json::from_value(json!([
+            { "op": "add", "path": "/screen/vm/items/-", "value": "c" },
+            { "op": "replace", "path": "/screen/vm/count", "value": 3 },
+            { "op": "remove", "path": "/screen/vm/missing" },
+        ]))
+        .expect("ops");
+
+        let mut patched = vm.clone();
+        let err = patch_vm(&mut patched, &ops).expect_err("missing path");
+        assert_eq!(err.path.as_deref(), Some("/screen/vm/missing"));
+        assert_eq!(patched, vm);
+
+        patch_vm(&mut patched, &ops[..2]).expect("valid prefix");
+        assert_eq!(
+            patched,
+            json!({ "screen": { "vm": { "items": ["a", "b", "c"], "count": 3 } } })
+        );
+    }
+
+    #[test]
+    fn rollback_undoes_every_kind_of_op_in_place() {
+        let vm = json!({ "list": ["a", "b", "c"], "box": { "n": 1 }, "keep": true });
+        let ops: Vec<PatchOp> = serde_json::from_value(json!([
+            { "op": "move", "from": "/list/0", "path": "/list/-" },
+            { "op": "copy", "from": "/box", "path": "/list/1" },
+            { "op": "replace", "path": "/box/n", "value": 2 },
+            { "op": "add", "path": "/new/deep/key", "value": "x" },
+            { "op": "remove", "path": "/keep" },
+            { "op": "add", "path": "/list/0", "value": "z" },
+            { "op": "test", "path": "/box/n", "value": 2 },
+        ]))
+        .expect("ops");
+
+        let mut patched = vm.clone();
+        let undo = patch_vm(&mut patched, &ops).expect("valid ops");
+        assert_eq!(
+            patched,
+            json!({
+                "list": ["z", "b", { "n": 1 }, "c", "a"],
+                "box": { "n": 2 },
+                "new": { "deep": { "key": "x" } }
+            })
+        );
+
+        undo.rollback(&mut patched);
+        assert_eq!(patched, vm);
+    }
+
+    #[test]
+    fn failed_test_ops_name_the_mismatch() {
+        let err = apply_json_patch(
+            json!({ "screen": { "vm": { "count": 3 } } }),
+            json!([{ "op": "test", "path": "/screen/vm/count", "value": 4 }]),
+        )
+        .expect_err("mismatch");
+
+        assert_eq!(err, "test failed at /screen/vm/count: expected 4, found 3");
+    }
+
     #[test]
     fn render_rev_rejects_stale_or_skipped_revisions() {
         let mut state = UiModelState::<u8>::default();
@@ -946,4 +2917,346 @@ mod tests {
         mark_applied_ack(&mut state, Some(8));
         assert_eq!(state.last_ack, Some(8));
     }
+    #[test]
+    fn suspend_keeps_the_view_model_and_its_resume_point() {
+        let mut state = UiModelState::<u8> {
+            vm: json!({ "clock_text": "10:42:17" }),
+            ..UiModelState::default()
+        };
+        assert_eq!(resume_point(&state), None);
+
+        mark_applied_rev(&mut state, 4);
+        suspend_for_resync(&mut state);
+        let resume = ResumePoint {
+            rev: 4,
+            vm_hash: vm_hash(&json!({ "clock_text": "10:42:17" })),
+        };
+
+        assert_eq!(state.last_rev, None);
+        assert_eq!(state.vm, json!({ "clock_text": "10:42:17" }));
+        assert_eq!(state.resume, Some(resume.clone()));
+
+        // A second suspend before the answer keeps the original point.
+        suspend_for_resync(&mut state);
+        assert_eq!(resume_point(&state), Some(resume));
+        assert!(validate_patch_rev(&state, 5).is_err());
+
+        reset_for_resync(&mut state);
+        assert_eq!(resume_point(&state), None);
+    }
+
+    /// Bindings without a window, counting the screen patches they apply.
+    struct CountingBindings;
+
+    struct HeadlessUi;
+
+    thread_local! {
+        static SCREEN_PATCHES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+        static MODAL_OPEN: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+        static INBOUND_STATS: std::cell::Cell<Option<InboundStats>> =
+            const { std::cell::Cell::new(None) };
+        static WINDOW_INTENT: std::cell::RefCell<Option<WindowIntentHandler>> =
+            std::cell::RefCell::new(None);
+    }
+
+    /// The only window kind `CountingBindings` opens, `confirm`.
+    struct HeadlessWindow;
+
+    impl ProjectionWindow for HeadlessWindow {
+        fn apply(&self, _declaration: &WindowDeclaration) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn show(&self) -> Result<(), slint::PlatformError> {
+            Ok(())
+        }
+
+        fn hide(&self) -> Result<(), slint::PlatformError> {
+            Ok(())
+        }
+
+        fn on_close_requested(&self, _handler: Box<dyn Fn()>) {}
+
+        fn on_intent(&self, handler: WindowIntentHandler) {
+            WINDOW_INTENT.with(|intent| *intent.borrow_mut() = Some(handler));
+        }
+    }
+
+    impl ComponentHandle for HeadlessUi {
+        type WeakInner = ();
+
+        fn as_weak(&self) -> slint::Weak<Self> {
+            slint::Weak::new(())
+        }
+
+        fn clone_strong(&self) -> Self {
+            HeadlessUi
+        }
+
+        fn upgrade_from_weak_inner(_: &()) -> Option<Self> {
+            Some(HeadlessUi)
+        }
+
+        fn show(&self) -> Result<(), slint::PlatformError> {
+            Ok(())
+        }
+
+        fn hide(&self) -> Result<(), slint::PlatformError> {
+            Ok(())
+        }
+
+        fn window(&self) -> &slint::Window {
+            unreachable!("headless UI has no window")
+        }
+
+        fn run(&self) -> Result<(), slint::PlatformError> {
+            Ok(())
+        }
+
+        fn global<'a, T: slint::Global<'a, Self>>(&'a self) -> T {
+            unreachable!("headless UI has no globals")
+        }
+    }
+
+    impl HostBindings for CountingBindings {
+        type Ui = HeadlessUi;
+        type ScreenId = ();
+
+        fn new_ui() -> Result<HeadlessUi, slint::PlatformError> {
+            Ok(HeadlessUi)
+        }
+
+        fn bind_bridge_intent<F>(_ui: &HeadlessUi, _handler: F)
+        where
+            F: Fn(String, String) + Send + 'static,
+        {
+        }
+
+        fn bind_ui_intent<F>(_ui: &HeadlessUi, _handler: F)
+        where
+            F: Fn(String, String) + Send + 'static,
+        {
+        }
+
+        fn bind_navigate<F>(_ui: &HeadlessUi, _handler: F)
+        where
+            F: Fn(String, String) + Send + 'static,
+        {
+        }
+
+        fn set_app_title(_ui: &HeadlessUi, _title: &str) {}
+        fn set_active_screen(_ui: &HeadlessUi, _active_screen: &str) {}
+        fn set_nav_can_back(_ui: &HeadlessUi, _nav_can_back: bool) {}
+        fn set_error_title(_ui: &HeadlessUi, _title: &str) {}
+        fn set_error_message(_ui: &HeadlessUi, _message: &str) {}
+        fn set_error_screen_module(_ui: &HeadlessUi, _screen_module: &str) {}
+
+        fn apply_screen_render(_ui: &HeadlessUi, _vm: &Value) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn apply_screen_patch(
+            _ui: &HeadlessUi,
+            _screen_id: (),
+            _ops: &[PatchOp],
+            _vm: &Value,
+        ) -> Result<(), String> {
+            SCREEN_PATCHES.with(|count| count.set(count.get() + 1));
+            Ok(())
+        }
+
+        fn set_modal_open(_ui: &HeadlessUi, open: bool) {
+            MODAL_OPEN.with(|modal| modal.set(open));
+        }
+
+        fn inbound_stats(_ui: &HeadlessUi, stats: InboundStats) {
+            INBOUND_STATS.with(|last| last.set(Some(stats)));
+        }
+
+        fn new_window(kind: &str) -> Result<Box<dyn ProjectionWindow>, String> {
+            match kind {
+                "confirm" => Ok(Box::new(HeadlessWindow)),
+                other => Err(format!("no window component for kind '{other}'")),
+            }
+        }
+    }
+
+    fn intent_sender(sid: &str, tx: OutboundSender, capacity: usize) -> IntentSender {
+        IntentSender {
+            tx,
+            sid: sid.to_string(),
+            next_intent_id: Arc::new(AtomicU64::new(1)),
+            dropped_intent_count: Arc::new(AtomicU64::new(0)),
+            queue_capacity: capacity,
+            coalescer: Arc::new(Mutex::new(IntentCoalescer::new(|_| None))),
+            log: Arc::new(Mutex::new(IntentLog::new(capacity))),
+        }
+    }
+
+    fn headless_session(sid: &str) -> (Session<CountingBindings>, OutboundReceiver) {
+        let (tx, rx) = outbound_queue(8, 8);
+        let intents = intent_sender(sid, tx, 8);
+        let session = Session {
+            ready: ready_envelope(sid.to_string(), "stdio-packet-4"),
+            sid: sid.to_string(),
+            ui_weak: HeadlessUi.as_weak(),
+            state: Arc::new(Mutex::new(UiModelState::default())),
+            resync_pending: Arc::new(AtomicBool::new(false)),
+            applied_reporter: Arc::new(Mutex::new(AppliedReporter::new(Duration::ZERO))),
+            intents,
+            inbound: Arc::new(InboundQueue::default()),
+        };
+        (session, rx)
+    }
+
+    #[test]
+    fn a_merged_burst_of_patches_applies_without_a_resync() {
+        let (session, rx) = headless_session("merged-burst");
+        {
+            let mut state = session.state.lock().unwrap();
+            state.vm = json!({ "screen": { "name": "clock", "vm": { "count": 1 } } });
+            mark_applied_rev(&mut state, 1);
+        }
+
+        for rev in 2..=4 {
+            session.inbound.push(Frame::Patch {
+                base_rev: rev,
+                rev,
+                ack: None,
+                resume_from: None,
+                ops: serde_json::from_value(json!([
+                    { "op": "replace", "path": "/screen/vm/count", "value": rev }
+                ]))
+                .expect("ops"),
+            });
+        }
+        session.drain_inbound(&HeadlessUi);
+
+        let state = session.state.lock().unwrap();
+        assert_eq!(state.last_rev, Some(4));
+        assert_eq!(state.vm.pointer("/screen/vm/count"), Some(&json!(4)));
+        assert_eq!(SCREEN_PATCHES.with(std::cell::Cell::get), 1);
+        assert!(!session.resync_pending.load(Ordering::Acquire));
+        assert!(rx.try_recv().is_none(), "no ready was sent");
+        assert_eq!(
+            INBOUND_STATS.with(std::cell::Cell::get),
+            Some(InboundStats {
+                patches: 3,
+                coalesced: 2
+            })
+        );
+    }
+
+    #[test]
+    fn a_full_intent_lane_still_forwards_the_warning_about_it() {
+        let (tx, mut rx) = outbound_queue(8, 1);
+        let intents = intent_sender("queue-full", tx.clone(), 1);
+        host_log::install();
+        host_log::attach(tx);
+        host_log::set_forwarding(true);
+
+        intents.send("slider.moved", json!({ "value": 1 }));
+        intents.send("slider.moved", json!({ "value": 2 }));
+        host_log::detach();
+        drop(intents);
+
+        let warning = rx.find_map(|envelope| match envelope {
+            UiEnvelope::Log {
+                sid,
+                message,
+                fields,
+                ..
+            } if message.starts_with("ui intent queue full") => Some((sid, fields)),
+            _ => None,
+        });
+        let (sid, fields) = warning.expect("queue-full warning was forwarded");
+        assert_eq!(sid.as_deref(), Some("queue-full"));
+        assert_eq!(fields.get("dropped"), Some(&json!(1)));
+    }
+
+    #[test]
+    fn a_local_resync_still_sends_intents_queued_without_replay() {
+        let (session, rx) = headless_session("local-resync");
+        {
+            let mut state = session.state.lock().unwrap();
+            state.vm = json!({ "screen": { "name": "clock", "vm": { "count": 1 } } });
+            mark_applied_rev(&mut state, 1);
+        }
+        let written_off = session.intents.log.lock().unwrap().written_off();
+        let patch = |rev, resume_from, ops| Frame::Patch {
+            base_rev: rev,
+            rev,
+            ack: None,
+            resume_from,
+            ops: serde_json::from_value(ops).expect("ops"),
+        };
+
+        session.intents.send("clock.pause", json!({}));
+        session.inbound.push(patch(
+            2,
+            None,
+            json!([{ "op": "remove", "path": "/screen/vm/missing" }]),
+        ));
+        session.drain_inbound(&HeadlessUi);
+        assert!(session.resync_pending.load(Ordering::Acquire));
+
+        // Answered before the server got to the intent.
+        session.inbound.push(patch(
+            2,
+            Some(1),
+            json!([{ "op": "replace", "path": "/screen/vm/count", "value": 2 }]),
+        ));
+        session.drain_inbound(&HeadlessUi);
+        drop(session);
+
+        let slot: ConnectionWriter<Vec<u8>> = Arc::new(Mutex::new(Some(Connection {
+            writer: BufWriter::new(Vec::new()),
+            codec: Codec::Json,
+        })));
+        let written_off = HashMap::from([("local-resync".to_string(), written_off)]);
+        connection_writer_loop(&slot, rx, &written_off);
+
+        let connection = slot.lock().unwrap().take().expect("connection");
+        let written = connection.writer.into_inner().expect("flush");
+        let written = String::from_utf8_lossy(&written);
+        assert!(written.contains(r#""name":"clock.pause""#), "{written}");
+        assert!(!written.contains("intents_discarded"), "{written}");
+    }
+
+    #[test]
+    fn a_modal_window_blocks_the_main_window_until_it_closes() {
+        let (session, rx) = headless_session("modal-window");
+        let confirm = json!({ "kind": "confirm", "title": "Discard?", "modal": true });
+
+        session.render(
+            &HeadlessUi,
+            1,
+            None,
+            &json!({ "screen": { "name": "clock", "vm": {} }, "windows": { "confirm": confirm } }),
+        );
+        assert!(MODAL_OPEN.with(|modal| modal.get()));
+
+        WINDOW_INTENT.with(|intent| {
+            let handler = intent.borrow();
+            handler.as_ref().expect("window intent handler")("confirm.discard".into(), "".into());
+        });
+        let intent = std::iter::from_fn(|| rx.try_recv())
+            .find(|envelope| matches!(envelope, UiEnvelope::Intent { .. }))
+            .expect("window intent");
+        assert!(matches!(intent, UiEnvelope::Intent { name, .. } if name == "confirm.discard"));
+
+        session.patch(
+            &HeadlessUi,
+            2,
+            2,
+            None,
+            None,
+            &serde_json::from_value::<Vec<PatchOp>>(json!([
+                { "op": "remove", "path": "/windows/confirm" }
+            ]))
+            .expect("ops"),
+        );
+        assert_eq!(session.state.lock().unwrap().last_rev, Some(2));
+        assert!(!MODAL_OPEN.with(|modal| modal.get()));
+    }
 }
@@ -0,0 +1,404 @@
+use crate::host_log::TRANSPORT_LOG_TARGET;
+use crate::protocol::UiEnvelope;
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::mpsc::TrySendError;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// Forwarded logs are already rate-limited; this only bounds a stalled writer.
+const LOG_LANE_CAP: usize = 64;
+
+/// Which queue an outbound envelope waits in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    /// Handshakes, diagnostics, acknowledgements and heartbeats. Always
+    /// drained first, never refused and never evicted.
+    Control,
+    /// User intents.
+    Intent,
+    /// Forwarded logs, so a log burst cannot delay a resync and a full intent
+    /// lane cannot swallow the warning about it.
+    Log,
+}
+
+impl Lane {
+    pub fn of(envelope: &UiEnvelope) -> Self {
+        match envelope {
+            UiEnvelope::Intent { .. } => Lane::Intent,
+            UiEnvelope::Log { .. } => Lane::Log,
+            _ => Lane::Control,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Lanes {
+    control: VecDeque<UiEnvelope>,
+    intents: VecDeque<UiEnvelope>,
+    logs: VecDeque<UiEnvelope>,
+    control_cap: usize,
+    intent_cap: usize,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    lanes: Mutex<Lanes>,
+    available: Condvar,
+    #[cfg(feature = "async")]
+    notify: tokio::sync::Notify,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Lanes> {
+        self.lanes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn wake_one(&self) {
+        self.available.notify_one();
+        #[cfg(feature = "async")]
+        self.notify.notify_one();
+    }
+
+    fn wake_all(&self) {
+        self.available.notify_all();
+        #[cfg(feature = "async")]
+        self.notify.notify_one();
+    }
+}
+
+/// Creates the outbound queue drained by the writer thread.
+///
+/// Control envelopes jump ahead of queued intents. A `ready`, `applied`, `ping`
+/// or `pong` replaces the one of the same kind its session still has queued;
+/// nothing else in the control lane is ever dropped. It may outgrow
+/// `control_cap`, which only triggers a warning. When the intent or log lane
+/// is full new envelopes are refused. Logs go out before intents.
+pub fn outbound_queue(control_cap: usize, intent_cap: usize) -> (OutboundSender, OutboundReceiver) {
+    let shared = Arc::new(Shared {
+        lanes: Mutex::new(Lanes {
+            control: VecDeque::new(),
+            intents: VecDeque::new(),
+            logs: VecDeque::new(),
+            control_cap: control_cap.max(1),
+            intent_cap: intent_cap.max(1),
+            senders: 1,
+            receiver_alive: true,
+        }),
+        available: Condvar::new(),
+        #[cfg(feature = "async")]
+        notify: tokio::sync::Notify::new(),
+    });
+
+    (
+        OutboundSender {
+            shared: shared.clone(),
+        },
+        OutboundReceiver { shared },
+    )
+}
+
+#[derive(Debug)]
+pub struct OutboundSender {
+    shared: Arc<Shared>,
+}
+
+impl OutboundSender {
+    /// Queues `envelope` in its lane without blocking. Only intent and log
+    /// envelopes can come back as `Full`.
+    #[allow(clippy::result_large_err)] // hands the envelope back, like `mpsc`
+    pub fn try_send(&self, envelope: UiEnvelope) -> Result<(), TrySendError<UiEnvelope>> {
+        let mut lanes = self.shared.lock();
+        if !lanes.receiver_alive {
+            return Err(TrySendError::Disconnected(envelope));
+        }
+
+        match Lane::of(&envelope) {
+            Lane::Control => {
+                lanes
+                    .control
+                    .retain(|queued| !supersedes(&envelope, queued));
+                lanes.control.push_back(envelope);
+
+                // Warned once each time the lane crosses its cap.
+                if lanes.control.len() == lanes.control_cap + 1 {
+                    warn!(
+                        target: TRANSPORT_LOG_TARGET,
+                        "outbound control lane holds {} envelopes (cap={}); the writer is falling behind",
+                        lanes.control.len(),
+                        lanes.control_cap
+                    );
+                }
+            }
+            Lane::Intent => {
+                if lanes.intents.len() >= lanes.intent_cap {
+                    return Err(TrySendError::Full(envelope));
+                }
+                lanes.intents.push_back(envelope);
+            }
+            Lane::Log => {
+                if lanes.logs.len() >= LOG_LANE_CAP {
+                    return Err(TrySendError::Full(envelope));
+                }
+                lanes.logs.push_back(envelope);
+            }
+        }
+
+        drop(lanes);
+        self.shared.wake_one();
+        Ok(())
+    }
+}
+
+/// Whether `envelope` makes the already queued `queued` obsolete: only the
+/// latest handshake, acknowledgement and heartbeat of a session matter.
+fn supersedes(envelope: &UiEnvelope, queued: &UiEnvelope) -> bool {
+    match (envelope, queued) {
+        (
+            UiEnvelope::Ready { sid, .. },
+            UiEnvelope::Ready {
+                sid: queued_sid, ..
+            },
+        )
+        | (
+            UiEnvelope::Applied { sid, .. },
+            UiEnvelope::Applied {
+                sid: queued_sid, ..
+            },
+        )
+        | (
+            UiEnvelope::Ping { sid, .. },
+            UiEnvelope::Ping {
+                sid: queued_sid, ..
+            },
+        )
+        | (
+            UiEnvelope::Pong { sid, .. },
+            UiEnvelope::Pong {
+                sid: queued_sid, ..
+            },
+        ) => sid == queued_sid,
+        _ => false,
+    }
+}
+
+impl Clone for OutboundSender {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for OutboundSender {
+    fn drop(&mut self) {
+        let mut lanes = self.shared.lock();
+        lanes.senders -= 1;
+        if lanes.senders == 0 {
+            drop(lanes);
+            self.shared.wake_all();
+        }
+    }
+}
+
+/// Blocking iterator over queued envelopes, control lane first. Ends once
+/// every sender is gone and every lane is empty.
+#[derive(Debug)]
+pub struct OutboundReceiver {
+    shared: Arc<Shared>,
+}
+
+impl OutboundReceiver {
+    /// Takes the next queued envelope without waiting.
+    pub fn try_recv(&self) -> Option<UiEnvelope> {
+        let mut lanes = self.shared.lock();
+        lanes.pop()
+    }
+
+    /// Waits for the next envelope without blocking the thread. Returns `None`
+    /// once every sender is gone and every lane is empty. Meant for a single
+    /// consumer task.
+    #[cfg(feature = "async")]
+    pub async fn recv(&self) -> Option<UiEnvelope> {
+        loop {
+            {
+                let mut lanes = self.shared.lock();
+                if let Some(envelope) = lanes.pop() {
+                    return Some(envelope);
+                }
+                if lanes.senders == 0 {
+                    return None;
+                }
+            }
+
+            // `notify_one` stores a permit when nobody waits, so a send
+            // between the check above and this await is not lost.
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Lanes {
+    fn pop(&mut self) -> Option<UiEnvelope> {
+        self.control
+            .pop_front()
+            .or_else(|| self.logs.pop_front())
+            .or_else(|| self.intents.pop_front())
+    }
+}
+
+impl Iterator for OutboundReceiver {
+    type Item = UiEnvelope;
+
+    fn next(&mut self) -> Option<UiEnvelope> {
+        let mut lanes = self.shared.lock();
+        loop {
+            if let Some(envelope) = lanes.pop() {
+                return Some(envelope);
+            }
+            if lanes.senders == 0 {
+                return None;
+            }
+
+            lanes = self
+                .shared
+                .available
+                .wait(lanes)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+}
+
+impl Drop for OutboundReceiver {
+    fn drop(&mut self) {
+        self.shared.lock().receiver_alive = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{applied_envelope, intent_envelope, ready_envelope};
+    use serde_json::json;
+
+    fn intent(id: u64) -> UiEnvelope {
+        intent_envelope("S1".to_string(), id, "slider.moved", json!({}))
+    }
+
+    #[test]
+    fn control_envelopes_jump_ahead_of_queued_intents() {
+        let (tx, rx) = outbound_queue(4, 4);
+
+        tx.try_send(intent(1)).expect("intent");
+        tx.try_send(intent(2)).expect("intent");
+        tx.try_send(ready_envelope("S1".to_string(), "stdio-packet-4"))
+            .expect("ready");
+        drop(tx);
+
+        let order: Vec<&str> = rx
+            .map(|envelope| match envelope {
+                UiEnvelope::Ready { .. } => "ready",
+                UiEnvelope::Intent { .. } => "intent",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(order, vec!["ready", "intent", "intent"]);
+    }
+
+    #[test]
+    fn a_full_intent_lane_never_refuses_control_traffic() {
+        let (tx, mut rx) = outbound_queue(2, 1);
+
+        tx.try_send(intent(1)).expect("first intent fits");
+        assert!(matches!(tx.try_send(intent(2)), Err(TrySendError::Full(_))));
+
+        tx.try_send(ready_envelope("S1".to_string(), "stdio-packet-4"))
+            .expect("ready");
+        tx.try_send(applied_envelope("S1".to_string(), 1, 0))
+            .expect("applied");
+        tx.try_send(applied_envelope("S1".to_string(), 2, 0))
+            .expect("applied replaces the queued one");
+
+        assert!(matches!(rx.next(), Some(UiEnvelope::Ready { .. })));
+        assert!(matches!(
+            rx.next(),
+            Some(UiEnvelope::Applied { rev: 2, .. })
+        ));
+        assert!(matches!(rx.next(), Some(UiEnvelope::Intent { id: 1, .. })));
+    }
+
+    #[test]
+    fn a_full_control_lane_coalesces_by_kind_and_keeps_every_diagnostic() {
+        let (tx, rx) = outbound_queue(2, 1);
+        let pong = |nonce| crate::protocol::pong_envelope("S1".to_string(), nonce);
+        let diagnostic = |code: &str| {
+            crate::protocol::diagnostic_envelope("S1".to_string(), code, "", None, None)
+        };
+
+        tx.try_send(applied_envelope("S1".to_string(), 1, 0))
+            .expect("applied");
+        tx.try_send(pong(1)).expect("pong");
+        tx.try_send(diagnostic("resync_requested"))
+            .expect("diagnostic past the cap");
+        tx.try_send(applied_envelope("S2".to_string(), 4, 0))
+            .expect("applied");
+        tx.try_send(applied_envelope("S1".to_string(), 2, 0))
+            .expect("applied replaces the queued one");
+        tx.try_send(diagnostic("intents_discarded"))
+            .expect("diagnostic");
+        tx.try_send(pong(2)).expect("pong replaces the queued one");
+        drop(tx);
+
+        let sent: Vec<String> = rx
+            .map(|envelope| match envelope {
+                UiEnvelope::Applied { sid, rev, .. } => format!("applied {sid} {rev}"),
+                UiEnvelope::Pong { nonce, .. } => format!("pong {nonce}"),
+                UiEnvelope::Diagnostic { code, .. } => code,
+                _ => "other".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            sent,
+            vec![
+                "resync_requested",
+                "applied S2 4",
+                "applied S1 2",
+                "intents_discarded",
+                "pong 2"
+            ]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn recv_waits_for_senders_without_blocking() {
+        let (tx, rx) = outbound_queue(4, 4);
+
+        let sender = tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            tx.try_send(intent(1)).expect("intent");
+        });
+
+        assert!(matches!(
+            rx.recv().await,
+            Some(UiEnvelope::Intent { id: 1, .. })
+        ));
+        sender.await.expect("sender task");
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[test]
+    fn senders_see_a_dropped_receiver_as_disconnected() {
+        let (tx, rx) = outbound_queue(1, 1);
+        drop(rx);
+
+        assert!(matches!(
+            tx.try_send(intent(1)),
+            Err(TrySendError::Disconnected(_))
+        ));
+    }
+}
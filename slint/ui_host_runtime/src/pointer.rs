@@ -0,0 +1,278 @@
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::fmt;
+use std::ops::Range;
+
+/// An RFC 6901 JSON pointer, tokenized once when the patch is decoded.
+///
+/// Tokens without `~` escapes are ranges into the pointer text, so parsing
+/// only allocates the token list.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Pointer {
+    raw: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Raw(Range<usize>),
+    Unescaped(Box<str>),
+}
+
+impl Pointer {
+    pub fn parse(raw: impl Into<String>) -> Result<Self, String> {
+        let raw = raw.into();
+
+        if raw.is_empty() {
+            return Ok(Self {
+                raw,
+                tokens: Vec::new(),
+            });
+        }
+
+        if !raw.starts_with('/') {
+            return Err(format!("invalid json pointer path: {raw}"));
+        }
+
+        let mut tokens = Vec::new();
+        let mut start = 1;
+
+        for token in raw[1..].split('/') {
+            let end = start + token.len();
+
+            tokens.push(if token.contains('~') {
+                Token::Unescaped(unescape_json_pointer_token(token)?.into_boxed_str())
+            } else {
+                Token::Raw(start..end)
+            });
+
+            start = end + 1;
+        }
+
+        Ok(Self { raw, tokens })
+    }
+
+    /// The pointer as it was sent, with its escapes.
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    pub fn tokens(&self) -> Tokens<'_> {
+        Tokens {
+            pointer: self,
+            start: 0,
+            end: self.tokens.len(),
+        }
+    }
+
+    /// Looks the pointer up in `value`, like `Value::pointer` without
+    /// parsing it again.
+    pub fn get<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        lookup(self.tokens(), value)
+    }
+
+    /// Like `get`, but a final `-` under an array names its last element,
+    /// where an `add`, `move` or `copy` to `-` appended.
+    pub fn get_written<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        match self.tokens().split_last() {
+            Some((parents, "-")) => match lookup(parents, value)? {
+                Value::Array(items) => items.last(),
+                Value::Object(map) => map.get("-"),
+                _ => None,
+            },
+            _ => self.get(value),
+        }
+    }
+
+    fn token(&self, index: usize) -> &str {
+        match &self.tokens[index] {
+            Token::Raw(range) => &self.raw[range.clone()],
+            Token::Unescaped(token) => token,
+        }
+    }
+}
+
+impl fmt::Debug for Pointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Pointer").field(&self.raw).finish()
+    }
+}
+
+impl fmt::Display for Pointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pointer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        Pointer::parse(raw).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A run of unescaped reference tokens of a `Pointer`.
+#[derive(Clone)]
+pub struct Tokens<'a> {
+    pointer: &'a Pointer,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn first(&self) -> Option<&'a str> {
+        (self.start < self.end).then(|| self.pointer.token(self.start))
+    }
+
+    pub fn split_last(&self) -> Option<(Tokens<'a>, &'a str)> {
+        (self.start < self.end).then(|| {
+            let parents = Tokens {
+                end: self.end - 1,
+                ..self.clone()
+            };
+            (parents, self.pointer.token(self.end - 1))
+        })
+    }
+
+    pub fn starts_with(&self, prefix: &[&str]) -> bool {
+        self.len() >= prefix.len() && self.clone().zip(prefix).all(|(token, p)| token == *p)
+    }
+
+    /// The tokens after `prefix`, as for a path relative to `/screen/vm`.
+    pub fn strip_prefix(&self, prefix: &[&str]) -> Option<Tokens<'a>> {
+        self.starts_with(prefix).then(|| Tokens {
+            start: self.start + prefix.len(),
+            ..self.clone()
+        })
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.first()?;
+        self.start += 1;
+        Some(token)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.end - self.start;
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for Tokens<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (parents, last) = self.split_last()?;
+        self.end = parents.end;
+        Some(last)
+    }
+}
+
+impl ExactSizeIterator for Tokens<'_> {}
+
+impl<const N: usize> PartialEq<[&str; N]> for Tokens<'_> {
+    fn eq(&self, other: &[&str; N]) -> bool {
+        self.len() == N && self.starts_with(other)
+    }
+}
+
+fn lookup<'v>(mut tokens: Tokens<'_>, value: &'v Value) -> Option<&'v Value> {
+    tokens.try_fold(value, |current, token| match current {
+        Value::Object(map) => map.get(token),
+        Value::Array(items) => array_index(token).and_then(|index| items.get(index)),
+        _ => None,
+    })
+}
+
+/// RFC 6901 array index: decimal digits without leading zeros.
+pub(crate) fn array_index(token: &str) -> Option<usize> {
+    let canonical = token == "0" || (!token.starts_with('0') && !token.is_empty());
+
+    if canonical && token.bytes().all(|byte| byte.is_ascii_digit()) {
+        token.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn unescape_json_pointer_token(token: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(token.len());
+    let mut chars = token.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch == '~' {
+            match chars.next() {
+                Some('0') => out.push('~'),
+                Some('1') => out.push('/'),
+                Some(other) => {
+                    return Err(format!("invalid escape ~{other} in json pointer token"));
+                }
+                None => return Err("trailing ~ in json pointer token".to_string()),
+            }
+        } else {
+            out.push(ch);
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn tokens_are_unescaped_and_relative_to_a_prefix() {
+        let pointer = Pointer::parse("/screen/vm/a~1b/m~0n/0").expect("pointer");
+
+        assert_eq!(pointer.as_str(), "/screen/vm/a~1b/m~0n/0");
+        assert_eq!(
+            pointer.tokens().collect::<Vec<_>>(),
+            vec!["screen", "vm", "a/b", "m~n", "0"]
+        );
+
+        let field = pointer
+            .tokens()
+            .strip_prefix(&["screen", "vm"])
+            .expect("screen vm");
+        assert!(field == ["a/b", "m~n", "0"]);
+        assert!(field.starts_with(&["a/b"]));
+        assert!(pointer.tokens().strip_prefix(&["app"]).is_none());
+
+        assert!(Pointer::parse("").expect("root").tokens().first().is_none());
+        assert_eq!(
+            Pointer::parse("/")
+                .expect("empty key")
+                .tokens()
+                .collect::<Vec<_>>(),
+            vec![""]
+        );
+        assert!(Pointer::parse("screen").is_err());
+        assert!(Pointer::parse("/a~2").is_err());
+    }
+
+    #[test]
+    fn get_follows_objects_and_canonical_array_indexes() {
+        let vm = json!({ "items": ["a", "b"], "m~n": { "a/b": 1 } });
+
+        let get = |raw: &str| Pointer::parse(raw).expect("pointer").get(&vm).cloned();
+        assert_eq!(get("/items/1"), Some(json!("b")));
+        assert_eq!(get("/m~0n/a~1b"), Some(json!(1)));
+        assert_eq!(get("/items/01"), None);
+        assert_eq!(get("/items/-"), None);
+        assert_eq!(get(""), Some(vm.clone()));
+
+        let get_written = |raw: &str| {
+            Pointer::parse(raw)
+                .expect("pointer")
+                .get_written(&vm)
+                .cloned()
+        };
+        assert_eq!(get_written("/items/-"), Some(json!("b")));
+        assert_eq!(get_written("/items/0"), Some(json!("a")));
+        assert_eq!(get_written("/m~0n/-"), None);
+    }
+}
@@ -1,16 +1,41 @@
+use crate::chunk::{self, MAX_REASSEMBLED_PAYLOAD, Reassembler};
+use crate::coalesce::IntentPolicy;
+use crate::pointer::Pointer;
+use flate2::Compression as ZlibLevel;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::io::{self, Read, Write};
-use std::sync::mpsc::Receiver;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufWriter, Read, Write};
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 pub const UI_TO_ELIXIR_CAP: usize = 65_536;
 pub const ELIXIR_TO_UI_CAP: usize = 1_048_576;
+pub const COMPRESSION_THRESHOLD: usize = 16_384;
+/// First byte of a compressed frame, followed by the zlib stream. `0xC0` is
+/// MessagePack `nil`, which never starts an envelope, and is not valid JSON.
+pub const ZLIB_MARKER: u8 = 0xC0;
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const FEATURE_APPLIED: &str = "applied";
+pub const FEATURE_HEARTBEAT: &str = "heartbeat";
+pub const FEATURE_LOG: &str = "log";
+pub const FEATURE_INTENT_REPLAY: &str = "intent_replay";
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "t")]
 pub enum UiEnvelope {
     #[serde(rename = "ready")]
-    Ready { sid: String, capabilities: Value },
+    Ready {
+        sid: String,
+        /// Names this host process; intent ids restart when it changes.
+        instance: String,
+        capabilities: Capabilities,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        resume: Option<ResumePoint>,
+    },
     #[serde(rename = "intent")]
     Intent {
         sid: String,
@@ -18,13 +43,55 @@ pub enum UiEnvelope {
         name: String,
         payload: Value,
     },
+    /// Sent after a revision was applied on the UI thread, with the wall-clock
+    /// time (ms since the unix epoch) it was applied at.
+    #[serde(rename = "applied")]
+    Applied {
+        sid: String,
+        rev: u64,
+        applied_at_ms: u64,
+    },
+    /// Host-side failure report, sent alongside every resync request.
+    #[serde(rename = "diagnostic")]
+    Diagnostic {
+        sid: String,
+        code: String,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rev: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    /// Host log record forwarded into the server's logs. `sid` names the
+    /// session the record is about, when it is about one.
+    #[serde(rename = "log")]
+    Log {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sid: Option<String>,
+        level: String,
+        target: String,
+        message: String,
+        #[serde(skip_serializing_if = "Map::is_empty")]
+        fields: Map<String, Value>,
+    },
+    /// Liveness probe; the peer answers with a `pong` carrying the same nonce.
+    #[serde(rename = "ping")]
+    Ping { sid: String, nonce: u64 },
+    #[serde(rename = "pong")]
+    Pong { sid: String, nonce: u64 },
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "t")]
 pub enum ElixirEnvelope {
     #[serde(rename = "render")]
-    Render { sid: String, rev: u64, vm: Value },
+    Render {
+        sid: String,
+        rev: u64,
+        #[serde(default)]
+        ack: Option<u64>,
+        vm: Value,
+    },
 
     #[serde(rename = "patch")]
     Patch {
@@ -32,6 +99,10 @@ pub enum ElixirEnvelope {
         rev: u64,
         #[serde(default)]
         ack: Option<u64>,
+        /// Set when the patch answers a resuming `ready`: the ops apply to the
+        /// view-model the host kept at this revision.
+        #[serde(default)]
+        resume_from: Option<u64>,
         ops: Vec<PatchOp>,
     },
 
@@ -40,29 +111,375 @@ pub enum ElixirEnvelope {
         sid: String,
         #[serde(default)]
         rev: Option<u64>,
-        code: String,
+        code: ErrorCode,
         message: String,
     },
+
+    /// Server answer to `ready`: the protocol version it speaks and the
+    /// optional features it turned on for this connection.
+    #[serde(rename = "accept")]
+    Accept {
+        sid: String,
+        protocol_version: u32,
+        #[serde(default = "default_codec_name")]
+        codec: String,
+        #[serde(default)]
+        compression: Option<String>,
+        #[serde(default)]
+        features: Vec<String>,
+        /// Coalescing the server asks for, by intent name.
+        #[serde(default)]
+        intent_policies: HashMap<String, IntentPolicy>,
+    },
+
+    #[serde(rename = "ping")]
+    Ping { sid: String, nonce: u64 },
+
+    #[serde(rename = "pong")]
+    Pong { sid: String, nonce: u64 },
+}
+
+impl ElixirEnvelope {
+    /// The session this envelope is addressed to.
+    pub fn sid(&self) -> &str {
+        match self {
+            ElixirEnvelope::Render { sid, .. }
+            | ElixirEnvelope::Patch { sid, .. }
+            | ElixirEnvelope::Error { sid, .. }
+            | ElixirEnvelope::Accept { sid, .. }
+            | ElixirEnvelope::Ping { sid, .. }
+            | ElixirEnvelope::Pong { sid, .. } => sid,
+        }
+    }
+}
+
+/// The revision a resyncing host still holds, with a hash of its view-model.
+/// Lets the server answer `ready` with a patch instead of a full render.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumePoint {
+    pub rev: u64,
+    pub vm_hash: String,
+}
+
+/// What the host supports, sent in the `ready` envelope.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub protocol_version: u32,
+    pub transport: String,
+    pub codecs: Vec<String>,
+    pub compression: Vec<String>,
+    pub patch_ops: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunking: Option<ChunkingCapability>,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkingCapability {
+    pub max_payload: usize,
+}
+
+impl Capabilities {
+    pub fn for_transport(transport: &str) -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            transport: transport.to_string(),
+            codecs: Codec::SUPPORTED
+                .iter()
+                .map(|codec| codec.name().to_string())
+                .collect(),
+            compression: Compression::SUPPORTED
+                .iter()
+                .map(|compression| compression.name().to_string())
+                .collect(),
+            patch_ops: ["replace", "add", "remove", "move", "copy", "test"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            chunking: Some(ChunkingCapability {
+                max_payload: MAX_REASSEMBLED_PAYLOAD,
+            }),
+            features: vec![
+                FEATURE_APPLIED.to_string(),
+                FEATURE_HEARTBEAT.to_string(),
+                FEATURE_LOG.to_string(),
+                FEATURE_INTENT_REPLAY.to_string(),
+            ],
+        }
+    }
+}
+
+/// The host speaks exactly one protocol version; anything else would only
+/// surface later as confusing decode failures, so it is rejected up front.
+pub fn check_protocol_version(server_version: u32) -> Result<(), String> {
+    if server_version == PROTOCOL_VERSION {
+        Ok(())
+    } else {
+        Err(format!(
+            "incompatible protocol version: server speaks {server_version}, host speaks {PROTOCOL_VERSION}"
+        ))
+    }
+}
+
+fn default_codec_name() -> String {
+    Codec::Json.name().to_string()
+}
+
+/// Code carried by an `error` envelope. Codes this host does not know yet
+/// decode as `Unknown` instead of failing the whole envelope.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ErrorCode {
+    DecodeError,
+    FrameTooLarge,
+    InvalidEnvelope,
+    ResyncRequired,
+    RevMismatch,
+    PatchApplyError,
+    Unknown(String),
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ErrorCode::DecodeError => "decode_error",
+            ErrorCode::FrameTooLarge => "frame_too_large",
+            ErrorCode::InvalidEnvelope => "invalid_envelope",
+            ErrorCode::ResyncRequired => "resync_required",
+            ErrorCode::RevMismatch => "rev_mismatch",
+            ErrorCode::PatchApplyError => "patch_apply_error",
+            ErrorCode::Unknown(code) => code,
+        }
+    }
+}
+
+impl From<String> for ErrorCode {
+    fn from(code: String) -> Self {
+        match code.as_str() {
+            "decode_error" => ErrorCode::DecodeError,
+            "frame_too_large" => ErrorCode::FrameTooLarge,
+            "invalid_envelope" => ErrorCode::InvalidEnvelope,
+            "resync_required" => ErrorCode::ResyncRequired,
+            "rev_mismatch" => ErrorCode::RevMismatch,
+            "patch_apply_error" => ErrorCode::PatchApplyError,
+            _ => ErrorCode::Unknown(code),
+        }
+    }
+}
+
+impl From<ErrorCode> for String {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::Unknown(code) => code,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "op")]
 pub enum PatchOp {
     #[serde(rename = "replace")]
-    Replace { path: String, value: Value },
+    Replace { path: Pointer, value: Value },
     #[serde(rename = "add")]
-    Add { path: String, value: Value },
+    Add { path: Pointer, value: Value },
     #[serde(rename = "remove")]
-    Remove { path: String },
+    Remove { path: Pointer },
+    #[serde(rename = "move")]
+    Move { from: Pointer, path: Pointer },
+    #[serde(rename = "copy")]
+    Copy { from: Pointer, path: Pointer },
+    #[serde(rename = "test")]
+    Test { path: Pointer, value: Value },
+}
+
+impl PatchOp {
+    pub fn path(&self) -> &Pointer {
+        match self {
+            PatchOp::Replace { path, .. }
+            | PatchOp::Add { path, .. }
+            | PatchOp::Remove { path }
+            | PatchOp::Move { path, .. }
+            | PatchOp::Copy { path, .. }
+            | PatchOp::Test { path, .. } => path,
+        }
+    }
+
+    /// The path this op deletes: `remove`'s path, or `move`'s `from`.
+    pub fn removed_path(&self) -> Option<&Pointer> {
+        match self {
+            PatchOp::Remove { path } => Some(path),
+            PatchOp::Move { from, .. } => Some(from),
+            _ => None,
+        }
+    }
+
+    /// The path this op writes and its new value. `move` and `copy` carry no
+    /// value, so theirs is read from `vm`, the view model after the patch,
+    /// with `-` standing for the element they appended.
+    pub fn written_value<'a>(&'a self, vm: &'a Value) -> Option<(&'a Pointer, &'a Value)> {
+        match self {
+            PatchOp::Replace { path, value } | PatchOp::Add { path, value } => Some((path, value)),
+            PatchOp::Move { path, .. } | PatchOp::Copy { path, .. } => {
+                path.get_written(vm).map(|value| (path, value))
+            }
+            PatchOp::Remove { .. } | PatchOp::Test { .. } => None,
+        }
+    }
+}
+
+/// Payload encoding of a frame. JSON is the fallback every peer speaks;
+/// MessagePack carries the same serde types in a compact binary form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    Json,
+    MsgPack,
+}
+
+impl Codec {
+    /// Advertised as `capabilities.codecs` in the `ready` envelope, preferred first.
+    pub const SUPPORTED: [Codec; 2] = [Codec::MsgPack, Codec::Json];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Codec::Json => "json",
+            Codec::MsgPack => "msgpack",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::SUPPORTED
+            .into_iter()
+            .find(|codec| codec.name() == name)
+    }
+
+    /// Envelopes are always maps, so the first byte tells the codecs apart:
+    /// `{` (after optional whitespace) for JSON, a map marker for MessagePack.
+    pub fn detect(payload: &[u8]) -> Self {
+        match payload.iter().find(|byte| !byte.is_ascii_whitespace()) {
+            Some(0x80..=0x8f | 0xde | 0xdf) => Codec::MsgPack,
+            _ => Codec::Json,
+        }
+    }
+}
+
+/// Per-frame compression. A compressed frame is `ZLIB_MARKER` followed by a
+/// zlib stream; sniffing the zlib header alone would misread MessagePack maps
+/// such as `0x88 0xb7`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Zlib,
+}
+
+impl Compression {
+    /// Advertised as `capabilities.compression` in the `ready` envelope.
+    pub const SUPPORTED: [Compression; 1] = [Compression::Zlib];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zlib => "zlib",
+        }
+    }
+
+    pub fn detect(payload: &[u8]) -> Self {
+        match payload.first() {
+            Some(&ZLIB_MARKER) => Compression::Zlib,
+            _ => Compression::None,
+        }
+    }
 }
 
-pub fn ready_envelope(sid: String) -> UiEnvelope {
+/// Process id and start time, fixed for the life of the process.
+pub fn host_instance() -> &'static str {
+    static INSTANCE: OnceLock<String> = OnceLock::new();
+    INSTANCE.get_or_init(|| {
+        let started_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default();
+        format!("{:x}-{started_ms:x}", std::process::id())
+    })
+}
+
+pub fn ready_envelope(sid: String, transport: &str) -> UiEnvelope {
     UiEnvelope::Ready {
         sid,
-        capabilities: serde_json::json!({
-            "m1": true,
-            "transport": "stdio-packet-4"
-        }),
+        instance: host_instance().to_string(),
+        capabilities: Capabilities::for_transport(transport),
+        resume: None,
+    }
+}
+
+impl UiEnvelope {
+    /// Sets the resume point of a `ready` envelope; other envelopes are unchanged.
+    pub fn resuming_from(mut self, resume_point: Option<ResumePoint>) -> Self {
+        if let UiEnvelope::Ready { resume, .. } = &mut self {
+            *resume = resume_point;
+        }
+        self
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// FNV-1a (64-bit) over the canonical JSON of `vm`, as 16 lowercase hex
+/// digits. Elixir's `Projection.Protocol.vm_hash/1` matches it.
+pub fn vm_hash(vm: &Value) -> String {
+    let mut json = Vec::new();
+    write_canonical_json(vm, &mut json);
+    let hash = json.iter().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    });
+    format!("{hash:016x}")
+}
+
+/// Compact JSON with object keys sorted, as `serde_json` writes it, except
+/// that floats use the shortest digits that round-trip in `1.5e-7` form.
+/// Encoders disagree on `1e20` against `1.0e20`; both sides agree on this.
+fn write_canonical_json(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Number(number) if number.is_f64() => {
+            let float = number.as_f64().unwrap_or_default();
+            out.extend_from_slice(format!("{float:e}").as_bytes());
+        }
+        Value::Array(items) => {
+            out.push(b'[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(b',');
+                }
+                write_canonical_json(item, out);
+            }
+            out.push(b']');
+        }
+        // `Map` keeps its keys sorted.
+        Value::Object(map) => {
+            out.push(b'{');
+            for (index, (key, item)) in map.iter().enumerate() {
+                if index > 0 {
+                    out.push(b',');
+                }
+                let _ = serde_json::to_writer(&mut *out, key);
+                out.push(b':');
+                write_canonical_json(item, out);
+            }
+            out.push(b'}');
+        }
+        other => {
+            let _ = serde_json::to_writer(&mut *out, other);
+        }
     }
 }
 
@@ -80,50 +497,201 @@ pub fn intent_envelope(
     }
 }
 
-pub fn writer_loop(rx: Receiver<UiEnvelope>) -> io::Result<()> {
-    let stdout = io::stdout();
-    let mut writer = stdout.lock();
+pub fn applied_envelope(sid: String, rev: u64, applied_at_ms: u64) -> UiEnvelope {
+    UiEnvelope::Applied {
+        sid,
+        rev,
+        applied_at_ms,
+    }
+}
+
+pub fn log_envelope(
+    sid: Option<String>,
+    level: impl Into<String>,
+    target: impl Into<String>,
+    message: impl Into<String>,
+    fields: Map<String, Value>,
+) -> UiEnvelope {
+    UiEnvelope::Log {
+        sid,
+        level: level.into(),
+        target: target.into(),
+        message: message.into(),
+        fields,
+    }
+}
+
+pub fn ping_envelope(sid: String, nonce: u64) -> UiEnvelope {
+    UiEnvelope::Ping { sid, nonce }
+}
+
+pub fn pong_envelope(sid: String, nonce: u64) -> UiEnvelope {
+    UiEnvelope::Pong { sid, nonce }
+}
+
+pub fn diagnostic_envelope(
+    sid: String,
+    code: impl Into<String>,
+    message: impl Into<String>,
+    rev: Option<u64>,
+    path: Option<String>,
+) -> UiEnvelope {
+    UiEnvelope::Diagnostic {
+        sid,
+        code: code.into(),
+        message: message.into(),
+        rev,
+        path,
+    }
+}
+
+pub fn writer_loop(writer: impl Write, rx: impl IntoIterator<Item = UiEnvelope>) -> io::Result<()> {
+    let mut writer = BufWriter::new(writer);
 
     for envelope in rx {
-        let payload = encode_ui_envelope(&envelope)?;
-        write_frame(&mut writer, &payload, UI_TO_ELIXIR_CAP)?;
-        writer.flush()?;
+        write_envelope(&mut writer, &envelope)?;
     }
 
     Ok(())
 }
 
-pub fn reader_loop<F>(mut on_envelope: F) -> io::Result<()>
+pub fn write_envelope(writer: &mut impl Write, envelope: &UiEnvelope) -> io::Result<()> {
+    write_envelope_with(writer, envelope, Codec::Json)
+}
+
+pub fn write_envelope_with(
+    writer: &mut impl Write,
+    envelope: &UiEnvelope,
+    codec: Codec,
+) -> io::Result<()> {
+    writer.write_all(&encode_envelope_frame(envelope, codec)?)?;
+    writer.flush()
+}
+
+/// One outbound envelope as a complete frame, length prefix included.
+pub fn encode_envelope_frame(envelope: &UiEnvelope, codec: Codec) -> io::Result<Vec<u8>> {
+    let payload = encode_ui_envelope(envelope, codec)?;
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    write_frame(&mut frame, &payload, UI_TO_ELIXIR_CAP, Compression::None)?;
+    Ok(frame)
+}
+
+/// Reads frames until EOF, reassembling chunked messages (which may exceed
+/// `ELIXIR_TO_UI_CAP` up to `MAX_REASSEMBLED_PAYLOAD`) before decoding.
+/// An error returned by `on_envelope` stops the loop. A message that is
+/// oversized or cannot be decoded is skipped and handed to `on_discard`.
+pub fn reader_loop<F, D>(
+    mut reader: impl Read,
+    mut on_envelope: F,
+    mut on_discard: D,
+) -> io::Result<()>
 where
-    F: FnMut(ElixirEnvelope),
+    F: FnMut(ElixirEnvelope) -> io::Result<()>,
+    D: FnMut(io::Error),
 {
-    let stdin = io::stdin();
-    let mut reader = stdin.lock();
+    let mut frames = InboundFrames::new();
 
     loop {
-        match read_frame(&mut reader, ELIXIR_TO_UI_CAP) {
-            Ok(payload) => {
-                let envelope = decode_elixir_envelope(&payload)?;
-                on_envelope(envelope);
-            }
+        let frame = match read_frame(&mut reader, ELIXIR_TO_UI_CAP) {
+            Ok(frame) => frame,
             Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            // The oversized payload was skipped, so the stream is still framed.
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                on_discard(err);
+                continue;
+            }
             Err(err) => return Err(err),
+        };
+
+        if let Some(envelope) = frames.next_envelope(frame, &mut on_discard) {
+            on_envelope(envelope)?;
         }
     }
 }
 
-fn encode_ui_envelope(envelope: &UiEnvelope) -> io::Result<Vec<u8>> {
-    serde_json::to_vec(envelope).map_err(json_error)
+/// Decodes inbound frames, reassembling chunked messages first.
+#[derive(Debug, Default)]
+pub struct InboundFrames {
+    reassembler: Reassembler,
+}
+
+impl InboundFrames {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `None` while a chunked message is still incomplete.
+    pub fn decode(&mut self, frame: Vec<u8>) -> io::Result<Option<ElixirEnvelope>> {
+        let payload = if chunk::is_chunk(&frame) {
+            let Some(payload) = self.reassembler.push(&frame, Instant::now())? else {
+                return Ok(None);
+            };
+            decompress(payload, MAX_REASSEMBLED_PAYLOAD)?
+        } else {
+            self.reassembler.abandon_expired(Instant::now());
+            decompress(frame, ELIXIR_TO_UI_CAP)?
+        };
+
+        decode_elixir_envelope(&payload).map(Some)
+    }
+
+    /// Like `decode`, but hands messages that cannot be decoded, and chunked
+    /// messages the reassembler abandoned, to `on_discard` instead of failing.
+    pub fn next_envelope(
+        &mut self,
+        frame: Vec<u8>,
+        on_discard: &mut impl FnMut(io::Error),
+    ) -> Option<ElixirEnvelope> {
+        let decoded = self.decode(frame);
+
+        let abandoned = self.reassembler.take_abandoned();
+        if abandoned > 0 {
+            on_discard(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("abandoned {abandoned} incomplete chunked message(s)"),
+            ));
+        }
+
+        decoded.unwrap_or_else(|err| {
+            on_discard(err);
+            None
+        })
+    }
+}
+
+fn encode_ui_envelope(envelope: &UiEnvelope, codec: Codec) -> io::Result<Vec<u8>> {
+    match codec {
+        Codec::Json => serde_json::to_vec(envelope).map_err(json_error),
+        Codec::MsgPack => rmp_serde::to_vec_named(envelope).map_err(msgpack_error),
+    }
 }
 
 fn decode_elixir_envelope(payload: &[u8]) -> io::Result<ElixirEnvelope> {
-    serde_json::from_slice(payload).map_err(json_error)
+    match Codec::detect(payload) {
+        Codec::Json => serde_json::from_slice(payload).map_err(json_error),
+        Codec::MsgPack => rmp_serde::from_slice(payload).map_err(msgpack_error),
+    }
 }
 
 fn read_frame(reader: &mut impl Read, max_payload: usize) -> io::Result<Vec<u8>> {
     let mut len_buf = [0_u8; 4];
     reader.read_exact(&mut len_buf)?;
 
+    let len = match frame_len(len_buf, max_payload) {
+        Ok(len) => len,
+        Err(err) => {
+            let skip = u64::from(u32::from_be_bytes(len_buf));
+            io::copy(&mut reader.by_ref().take(skip), &mut io::sink())?;
+            return Err(err);
+        }
+    };
+    let mut payload = vec![0_u8; len];
+    reader.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Validates a frame's length prefix against `max_payload`.
+pub fn frame_len(len_buf: [u8; 4], max_payload: usize) -> io::Result<usize> {
     let len = u32::from_be_bytes(len_buf) as usize;
     if len > max_payload {
         return Err(io::Error::new(
@@ -132,12 +700,45 @@ fn read_frame(reader: &mut impl Read, max_payload: usize) -> io::Result<Vec<u8>>
         ));
     }
 
-    let mut payload = vec![0_u8; len];
-    reader.read_exact(&mut payload)?;
-    Ok(payload)
+    Ok(len)
+}
+
+/// Inflates zlib payloads; `max_payload` bounds the decompressed size, so
+/// compressed frames are held to the same cap as plain ones.
+fn decompress(payload: Vec<u8>, max_payload: usize) -> io::Result<Vec<u8>> {
+    match Compression::detect(&payload) {
+        Compression::None => Ok(payload),
+        Compression::Zlib => inflate_bounded(&payload[1..], max_payload),
+    }
+}
+
+/// Decompression bomb guard: inflates at most `max_payload + 1` bytes, whatever
+/// the stream claims, and rejects the frame once that bound is crossed.
+fn inflate_bounded(compressed: &[u8], max_payload: usize) -> io::Result<Vec<u8>> {
+    let mut inflated = Vec::with_capacity(compressed.len().saturating_mul(4).min(max_payload));
+    ZlibDecoder::new(compressed)
+        .take(max_payload as u64 + 1)
+        .read_to_end(&mut inflated)?;
+
+    if inflated.len() > max_payload {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("decompressed frame too large: exceeds {max_payload}"),
+        ));
+    }
+
+    Ok(inflated)
 }
 
-fn write_frame(writer: &mut impl Write, payload: &[u8], max_payload: usize) -> io::Result<()> {
+/// Writes one frame. `max_payload` bounds the uncompressed payload; with
+/// `Compression::Zlib` payloads of at least `COMPRESSION_THRESHOLD` bytes go
+/// out compressed, unless that would not make them smaller.
+fn write_frame(
+    writer: &mut impl Write,
+    payload: &[u8],
+    max_payload: usize,
+    compression: Compression,
+) -> io::Result<()> {
     if payload.len() > max_payload {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
@@ -145,6 +746,21 @@ fn write_frame(writer: &mut impl Write, payload: &[u8], max_payload: usize) -> i
         ));
     }
 
+    let compressed;
+    let payload = match compression {
+        Compression::Zlib if payload.len() >= COMPRESSION_THRESHOLD => {
+            let mut encoder = ZlibEncoder::new(vec![ZLIB_MARKER], ZlibLevel::default());
+            encoder.write_all(payload)?;
+            compressed = encoder.finish()?;
+            if compressed.len() < payload.len() {
+                compressed.as_slice()
+            } else {
+                payload
+            }
+        }
+        _ => payload,
+    };
+
     let len = u32::try_from(payload.len())
         .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "payload exceeds u32"))?;
 
@@ -157,6 +773,10 @@ fn json_error(err: serde_json::Error) -> io::Error {
     io::Error::new(io::ErrorKind::InvalidData, err)
 }
 
+fn msgpack_error(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -186,7 +806,7 @@ mod tests {
         let payload = br#"{"t":"ready","sid":"S1"}"#;
         let mut out = Vec::new();
 
-        write_frame(&mut out, payload, UI_TO_ELIXIR_CAP).expect("frame write");
+        write_frame(&mut out, payload, UI_TO_ELIXIR_CAP, Compression::None).expect("frame write");
 
         let mut cursor = Cursor::new(out);
         let decoded = read_frame(&mut cursor, UI_TO_ELIXIR_CAP).expect("frame read");
@@ -214,10 +834,197 @@ mod tests {
     fn endian_is_big_endian() {
         let payload = b"abc";
         let mut out = Vec::new();
-        write_frame(&mut out, payload, UI_TO_ELIXIR_CAP).expect("frame write");
+        write_frame(&mut out, payload, UI_TO_ELIXIR_CAP, Compression::None).expect("frame write");
         assert_eq!(&out[0..4], &[0, 0, 0, 3]);
     }
 
+    #[test]
+    fn compressed_frame_round_trips_and_cap_applies_after_inflating() {
+        let payload = format!(
+            r#"{{"t":"render","sid":"S1","rev":1,"vm":{{"rows":"{}"}}}}"#,
+            "device-row ".repeat(4_000)
+        );
+        let mut out = Vec::new();
+        write_frame(
+            &mut out,
+            payload.as_bytes(),
+            ELIXIR_TO_UI_CAP,
+            Compression::Zlib,
+        )
+        .expect("compressed frame write");
+
+        let wire_len = u32::from_be_bytes([out[0], out[1], out[2], out[3]]) as usize;
+        assert!(wire_len < payload.len());
+        assert_eq!(Compression::detect(&out[4..]), Compression::Zlib);
+
+        let frame = read_frame(&mut Cursor::new(out), ELIXIR_TO_UI_CAP).expect("frame read");
+        let decoded = decompress(frame.clone(), ELIXIR_TO_UI_CAP).expect("inflate frame");
+        assert_eq!(decoded, payload.as_bytes());
+
+        let err = decompress(frame, payload.len() - 1).expect_err("inflated payload over cap");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decompression_bomb_is_rejected_without_inflating_it_whole() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), ZlibLevel::best());
+        encoder
+            .write_all(&vec![b' '; 8 * ELIXIR_TO_UI_CAP])
+            .expect("compress bomb");
+        let bomb = encoder.finish().expect("finish bomb");
+        assert!(bomb.len() < ELIXIR_TO_UI_CAP);
+
+        let err = inflate_bounded(&bomb, ELIXIR_TO_UI_CAP).expect_err("bomb rejected");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("decompressed frame too large"));
+    }
+
+    #[test]
+    fn small_payloads_are_not_compressed() {
+        let mut out = Vec::new();
+        write_frame(&mut out, b"abc", UI_TO_ELIXIR_CAP, Compression::Zlib).expect("frame write");
+        assert_eq!(&out[4..], b"abc");
+        assert_eq!(Compression::detect(br#"{"t":"ready"}"#), Compression::None);
+        assert_eq!(Compression::detect(&[0x84, 0xa1]), Compression::None);
+        // Also a valid zlib header (CMF 0x88, FLG 0xb7).
+        assert_eq!(Compression::detect(&[0x88, 0xb7]), Compression::None);
+    }
+
+    #[test]
+    fn reader_loop_decodes_frames_until_eof() {
+        let mut input = Vec::new();
+        write_frame(
+            &mut input,
+            br#"{"t":"render","sid":"S1","rev":1,"vm":{}}"#,
+            ELIXIR_TO_UI_CAP,
+            Compression::None,
+        )
+        .expect("render frame");
+        write_frame(
+            &mut input,
+            br#"{"t":"patch","sid":"S1","rev":2,"ops":[]}"#,
+            ELIXIR_TO_UI_CAP,
+            Compression::None,
+        )
+        .expect("patch frame");
+
+        let mut revs = Vec::new();
+        reader_loop(
+            Cursor::new(input),
+            |envelope| {
+                match envelope {
+                    ElixirEnvelope::Render { rev, .. } | ElixirEnvelope::Patch { rev, .. } => {
+                        revs.push(rev)
+                    }
+                    other => panic!("unexpected envelope {other:?}"),
+                }
+                Ok(())
+            },
+            |err| panic!("unexpected discard: {err}"),
+        )
+        .expect("reader loop ends cleanly on eof");
+
+        assert_eq!(revs, vec![1, 2]);
+    }
+
+    #[test]
+    fn reader_loop_reassembles_envelopes_larger_than_the_frame_cap() {
+        let rows = "device-row ".repeat(ELIXIR_TO_UI_CAP / 4);
+        let payload = serde_json::to_vec(&serde_json::json!({
+            "t": "render", "sid": "S1", "rev": 1, "vm": {"rows": rows}
+        }))
+        .expect("encode large render");
+        assert!(payload.len() > ELIXIR_TO_UI_CAP);
+
+        let mut input = Vec::new();
+        for frame in chunk::split_into_chunks(&payload, 1, ELIXIR_TO_UI_CAP) {
+            write_frame(&mut input, &frame, ELIXIR_TO_UI_CAP, Compression::None)
+                .expect("chunk frame");
+        }
+        write_frame(
+            &mut input,
+            br#"{"t":"patch","sid":"S1","rev":2,"ops":[]}"#,
+            ELIXIR_TO_UI_CAP,
+            Compression::None,
+        )
+        .expect("patch frame");
+
+        let mut seen = Vec::new();
+        reader_loop(
+            Cursor::new(input),
+            |envelope| {
+                match envelope {
+                    ElixirEnvelope::Render { rev, vm, .. } => {
+                        assert_eq!(vm["rows"].as_str().map(str::len), Some(rows.len()));
+                        seen.push(rev);
+                    }
+                    ElixirEnvelope::Patch { rev, .. } => seen.push(rev),
+                    other => panic!("unexpected envelope {other:?}"),
+                }
+                Ok(())
+            },
+            |err| panic!("unexpected discard: {err}"),
+        )
+        .expect("reader loop ends cleanly on eof");
+
+        assert_eq!(seen, vec![1, 2]);
+    }
+
+    #[test]
+    fn reader_loop_discards_bad_messages_and_keeps_reading() {
+        let mut input = Vec::new();
+        write_frame(&mut input, b"{", ELIXIR_TO_UI_CAP, Compression::None).expect("malformed");
+        input.extend_from_slice(&(ELIXIR_TO_UI_CAP as u32 + 1).to_be_bytes());
+        input.extend(std::iter::repeat_n(b' ', ELIXIR_TO_UI_CAP + 1));
+        write_frame(
+            &mut input,
+            &[chunk::CHUNK_MARKER, 0, 0],
+            ELIXIR_TO_UI_CAP,
+            Compression::None,
+        )
+        .expect("truncated chunk header");
+        write_frame(
+            &mut input,
+            br#"{"t":"patch","sid":"S1","rev":2,"ops":[]}"#,
+            ELIXIR_TO_UI_CAP,
+            Compression::None,
+        )
+        .expect("patch frame");
+
+        let mut discarded = Vec::new();
+        let mut revs = Vec::new();
+        reader_loop(
+            Cursor::new(input),
+            |envelope| {
+                if let ElixirEnvelope::Patch { rev, .. } = envelope {
+                    revs.push(rev);
+                }
+                Ok(())
+            },
+            |err| discarded.push(err.to_string()),
+        )
+        .expect("bad messages do not end the loop");
+
+        assert_eq!(discarded.len(), 3, "{discarded:?}");
+        assert!(discarded[1].contains("frame too large"));
+        assert_eq!(revs, vec![2]);
+    }
+
+    #[test]
+    fn writer_loop_frames_each_envelope() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        tx.send(ready_envelope("S1".to_string(), "stdio-packet-4"))
+            .expect("queue ready");
+        drop(tx);
+
+        let mut out = Vec::new();
+        writer_loop(&mut out, rx).expect("writer loop drains queue");
+
+        let payload = read_frame(&mut Cursor::new(out), UI_TO_ELIXIR_CAP).expect("frame read");
+        let value: Value = serde_json::from_slice(&payload).expect("parse ready json");
+        assert_eq!(value["t"], "ready");
+    }
+
     #[test]
     fn decodes_patch_envelope() {
         let payload = br#"{"t":"patch","sid":"S1","rev":2,"ops":[{"op":"replace","path":"/any_field","value":"value-1"}]}"#;
@@ -228,9 +1035,70 @@ mod tests {
                 assert_eq!(sid, "S1");
                 assert_eq!(rev, 2);
                 assert_eq!(ops.len(), 1);
+                assert!(ops[0].path().tokens() == ["any_field"]);
             }
             other => panic!("expected patch, got {other:?}"),
         }
+
+        let bad_path =
+            br#"{"t":"patch","sid":"S1","rev":2,"ops":[{"op":"remove","path":"any_field"}]}"#;
+        assert!(decode_elixir_envelope(bad_path).is_err());
+    }
+
+    #[test]
+    fn written_value_of_an_append_is_the_last_element() {
+        let ops: Vec<PatchOp> = serde_json::from_value(serde_json::json!([
+            { "op": "copy", "from": "/items/0", "path": "/items/-" },
+            { "op": "move", "from": "/items/0", "path": "/items/1" }
+        ]))
+        .expect("ops");
+        let vm = serde_json::json!({ "items": ["b", "c", "a"] });
+
+        let (path, value) = ops[0].written_value(&vm).expect("copied value");
+        assert_eq!(path.as_str(), "/items/-");
+        assert_eq!(value, "a");
+        assert_eq!(
+            ops[1].written_value(&vm).map(|(_, value)| value),
+            Some(&serde_json::json!("c"))
+        );
+    }
+
+    #[test]
+    fn accept_envelope_carries_intent_policies() {
+        let payload = br#"{"t":"accept","sid":"S1","protocol_version":1,"intent_policies":{"volume.changed":{"mode":"throttle","interval_ms":50}}}"#;
+
+        match decode_elixir_envelope(payload).expect("decode accept") {
+            ElixirEnvelope::Accept {
+                intent_policies, ..
+            } => assert_eq!(
+                intent_policies.get("volume.changed"),
+                Some(&IntentPolicy::Throttle { interval_ms: 50 })
+            ),
+            other => panic!("expected accept, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn error_codes_decode_known_and_unknown_values() {
+        let payload = br#"{"t":"error","sid":"S1","code":"resync_required","message":"stale"}"#;
+        match decode_elixir_envelope(payload).expect("decode error") {
+            ElixirEnvelope::Error { code, .. } => assert_eq!(code, ErrorCode::ResyncRequired),
+            other => panic!("expected error, got {other:?}"),
+        }
+
+        let payload = br#"{"t":"error","sid":"S1","code":"quota_exceeded","message":"slow down"}"#;
+        match decode_elixir_envelope(payload).expect("decode unknown error") {
+            ElixirEnvelope::Error { code, .. } => {
+                assert_eq!(code, ErrorCode::Unknown("quota_exceeded".to_string()));
+                assert_eq!(code.to_string(), "quota_exceeded");
+            }
+            other => panic!("expected error, got {other:?}"),
+        }
+
+        assert_eq!(
+            serde_json::to_value(ErrorCode::PatchApplyError).expect("encode code"),
+            "patch_apply_error"
+        );
     }
 
     #[test]
@@ -239,7 +1107,7 @@ mod tests {
         let decoded = decode_elixir_envelope(payload).expect("decode render");
 
         match decoded {
-            ElixirEnvelope::Render { sid, rev, vm } => {
+            ElixirEnvelope::Render { sid, rev, vm, .. } => {
                 assert_eq!(sid, "S1");
                 assert_eq!(rev, 1);
                 assert_eq!(vm["hello"], "world");
@@ -252,12 +1120,15 @@ mod tests {
 
     #[test]
     fn encodes_intent_envelope() {
-        let encoded = encode_ui_envelope(&intent_envelope(
-            "S1".to_string(),
-            7,
-            "ui.route.navigate",
-            serde_json::json!({"to":"devices","params":{}}),
-        ))
+        let encoded = encode_ui_envelope(
+            &intent_envelope(
+                "S1".to_string(),
+                7,
+                "ui.route.navigate",
+                serde_json::json!({"to":"devices","params":{}}),
+            ),
+            Codec::Json,
+        )
         .expect("encode intent");
 
         let value: Value = serde_json::from_slice(&encoded).expect("parse encoded json");
@@ -267,18 +1138,72 @@ mod tests {
         assert_eq!(value["name"], "ui.route.navigate");
     }
 
+    #[test]
+    fn accept_envelope_negotiates_version_and_features() {
+        let payload = br#"{"t":"accept","sid":"S1","protocol_version":1,"compression":"zlib","features":["chunking"]}"#;
+
+        match decode_elixir_envelope(payload).expect("decode accept") {
+            ElixirEnvelope::Accept {
+                protocol_version,
+                codec,
+                compression,
+                features,
+                ..
+            } => {
+                assert_eq!(check_protocol_version(protocol_version), Ok(()));
+                assert_eq!(Codec::from_name(&codec), Some(Codec::Json));
+                assert_eq!(compression.as_deref(), Some("zlib"));
+                assert_eq!(features, vec!["chunking".to_string()]);
+            }
+            other => panic!("expected accept, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn incompatible_protocol_version_is_rejected() {
+        let err = check_protocol_version(PROTOCOL_VERSION + 1).expect_err("newer server");
+        assert!(err.contains("incompatible protocol version"));
+    }
+
+    #[test]
+    fn diagnostic_envelope_omits_unknown_rev_and_path() {
+        let encoded = encode_ui_envelope(
+            &diagnostic_envelope("S1".to_string(), "sid_mismatch", "sid mismatch", None, None),
+            Codec::Json,
+        )
+        .expect("encode diagnostic");
+
+        let value: Value = serde_json::from_slice(&encoded).expect("parse diagnostic json");
+        assert_eq!(
+            value,
+            serde_json::json!({"t":"diagnostic","sid":"S1","code":"sid_mismatch","message":"sid mismatch"})
+        );
+    }
+
     #[test]
     fn contract_fixture_caps_match_runtime_caps() {
         let fixture = contract_fixture();
         assert_eq!(fixture["caps"]["ui_to_elixir"], UI_TO_ELIXIR_CAP as u64);
         assert_eq!(fixture["caps"]["elixir_to_ui"], ELIXIR_TO_UI_CAP as u64);
+        assert_eq!(fixture["protocol_version"], PROTOCOL_VERSION);
+        assert_eq!(fixture["compression"]["zlib_marker"], ZLIB_MARKER);
+        assert_eq!(
+            fixture["compression"]["threshold"],
+            COMPRESSION_THRESHOLD as u64
+        );
     }
 
     #[test]
     fn contract_fixture_envelopes_decode_on_rust_side() {
         let fixture = contract_fixture();
 
-        for key in ["elixir_render", "elixir_patch", "elixir_error"] {
+        for key in [
+            "elixir_render",
+            "elixir_patch",
+            "elixir_error",
+            "elixir_accept",
+            "elixir_ping",
+        ] {
             let payload =
                 serde_json::to_vec(&fixture[key]).expect("encode contract envelope payload");
             decode_elixir_envelope(&payload)
@@ -286,26 +1211,212 @@ mod tests {
         }
     }
 
+    #[test]
+    fn contract_fixture_render_ack_decodes() {
+        let fixture = contract_fixture();
+        let payload = serde_json::to_vec(&fixture["elixir_render_ack"]).expect("encode fixture");
+
+        match decode_elixir_envelope(&payload).expect("decode render with ack") {
+            ElixirEnvelope::Render { rev, ack, .. } => {
+                assert_eq!(rev, 3);
+                assert_eq!(ack, Some(8));
+            }
+            other => panic!("expected render, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn contract_fixture_resume_handshake_matches() {
+        let fixture = contract_fixture();
+        let sample = &fixture["vm_hash_sample"];
+        assert_eq!(vm_hash(&sample["vm"]), sample["hash"]);
+        let floats = &fixture["vm_hash_float_sample"];
+        assert_eq!(vm_hash(&floats["vm"]), floats["hash"]);
+
+        let resume = ResumePoint {
+            rev: 3,
+            vm_hash: vm_hash(&sample["vm"]),
+        };
+        let ready = ready_envelope("S1".to_string(), "stdio-packet-4").resuming_from(Some(resume));
+        let ready_value: Value =
+            serde_json::from_slice(&encode_ui_envelope(&ready, Codec::Json).expect("encode ready"))
+                .expect("parse ready json");
+        assert_eq!(ready_value["resume"], fixture["ui_resume_point"]);
+
+        let payload = serde_json::to_vec(&fixture["elixir_patch_resume"]).expect("encode fixture");
+        match decode_elixir_envelope(&payload).expect("decode resuming patch") {
+            ElixirEnvelope::Patch {
+                rev,
+                ack,
+                resume_from,
+                ..
+            } => {
+                assert_eq!(rev, 5);
+                assert_eq!(ack, Some(8));
+                assert_eq!(resume_from, Some(3));
+            }
+            other => panic!("expected patch, got {other:?}"),
+        }
+    }
+
     #[test]
     fn contract_fixture_ui_envelopes_match_rust_encoding_semantics() {
         let fixture = contract_fixture();
 
-        let ready_encoded =
-            encode_ui_envelope(&ready_envelope("S1".to_string())).expect("encode ready");
-        let ready_value: Value = serde_json::from_slice(&ready_encoded).expect("parse ready json");
+        let ready_encoded = encode_ui_envelope(
+            &ready_envelope("S1".to_string(), "stdio-packet-4"),
+            Codec::Json,
+        )
+        .expect("encode ready");
+        let mut ready_value: Value =
+            serde_json::from_slice(&ready_encoded).expect("parse ready json");
+        assert_eq!(ready_value["instance"], host_instance());
+        ready_value["instance"] = fixture["ui_ready"]["instance"].clone();
         assert_eq!(ready_value, fixture["ui_ready"]);
 
-        let intent_encoded = encode_ui_envelope(&intent_envelope(
-            "S1".to_string(),
-            7,
-            "ui.route.navigate",
-            serde_json::json!({"to":"devices","params":{}}),
-        ))
+        let intent_encoded = encode_ui_envelope(
+            &intent_envelope(
+                "S1".to_string(),
+                7,
+                "ui.route.navigate",
+                serde_json::json!({"to":"devices","params":{}}),
+            ),
+            Codec::Json,
+        )
         .expect("encode intent");
 
         let intent_value: Value =
             serde_json::from_slice(&intent_encoded).expect("parse intent json");
         assert_eq!(intent_value, fixture["ui_intent"]);
+
+        let diagnostic_encoded = encode_ui_envelope(
+            &diagnostic_envelope(
+                "S1".to_string(),
+                "patch_apply_failed",
+                "replace path does not exist: /clock_text",
+                Some(2),
+                Some("/clock_text".to_string()),
+            ),
+            Codec::Json,
+        )
+        .expect("encode diagnostic");
+
+        let diagnostic_value: Value =
+            serde_json::from_slice(&diagnostic_encoded).expect("parse diagnostic json");
+        assert_eq!(diagnostic_value, fixture["ui_diagnostic"]);
+
+        let applied_encoded = encode_ui_envelope(
+            &applied_envelope("S1".to_string(), 2, 1_767_225_600_000),
+            Codec::Json,
+        )
+        .expect("encode applied");
+        let applied_value: Value =
+            serde_json::from_slice(&applied_encoded).expect("parse applied json");
+        assert_eq!(applied_value, fixture["ui_applied"]);
+
+        let mut fields = Map::new();
+        fields.insert("dropped".to_string(), Value::from(3));
+        let log_encoded = encode_ui_envelope(
+            &log_envelope(
+                Some("S1".to_string()),
+                "warn",
+                "projection_ui_host_runtime",
+                "ui intent queue full (cap=256); dropped 3 intent(s)",
+                fields,
+            ),
+            Codec::Json,
+        )
+        .expect("encode log");
+        let log_value: Value = serde_json::from_slice(&log_encoded).expect("parse log json");
+        assert_eq!(log_value, fixture["ui_log"]);
+
+        let pong_encoded = encode_ui_envelope(&pong_envelope("S1".to_string(), 3), Codec::Json)
+            .expect("encode pong");
+        let pong_value: Value = serde_json::from_slice(&pong_encoded).expect("parse pong json");
+        assert_eq!(pong_value, fixture["ui_pong"]);
+    }
+
+    #[test]
+    fn contract_fixture_msgpack_samples_decode_to_json_counterparts() {
+        let fixture = contract_fixture();
+
+        for key in ["elixir_render", "elixir_patch"] {
+            let hex = fixture["msgpack_samples"][key]
+                .as_str()
+                .unwrap_or_else(|| panic!("msgpack sample '{key}' is a hex string"));
+            let payload = decode_hex(hex);
+            assert_eq!(Codec::detect(&payload), Codec::MsgPack);
+
+            decode_elixir_envelope(&payload)
+                .unwrap_or_else(|err| panic!("msgpack sample '{key}' failed decode: {err}"));
+            let value: Value = rmp_serde::from_slice(&payload).expect("msgpack sample as value");
+            assert_eq!(value, fixture[key]);
+        }
+    }
+
+    #[test]
+    fn contract_fixture_msgpack_intent_matches_rust_encoding_semantics() {
+        let fixture = contract_fixture();
+        let encoded = encode_ui_envelope(
+            &intent_envelope(
+                "S1".to_string(),
+                7,
+                "ui.route.navigate",
+                serde_json::json!({"to":"devices","params":{}}),
+            ),
+            Codec::MsgPack,
+        )
+        .expect("encode msgpack intent");
+
+        let sample = decode_hex(
+            fixture["msgpack_samples"]["ui_intent"]
+                .as_str()
+                .expect("msgpack intent sample is a hex string"),
+        );
+        let encoded_value: Value = rmp_serde::from_slice(&encoded).expect("decode encoded intent");
+        let sample_value: Value = rmp_serde::from_slice(&sample).expect("decode sample intent");
+        assert_eq!(encoded_value, sample_value);
+        assert_eq!(encoded_value, fixture["ui_intent"]);
+    }
+
+    #[test]
+    fn codec_detection_falls_back_to_json() {
+        assert_eq!(Codec::detect(br#"{"t":"render"}"#), Codec::Json);
+        assert_eq!(Codec::detect(b"  \n{}"), Codec::Json);
+        assert_eq!(Codec::detect(b""), Codec::Json);
+        assert_eq!(Codec::detect(&[0x84, 0xa1, b't']), Codec::MsgPack);
+        assert_eq!(Codec::from_name("msgpack"), Some(Codec::MsgPack));
+        assert_eq!(Codec::from_name("cbor"), None);
+    }
+
+    #[test]
+    fn contract_fixture_chunk_sample_matches_rust_chunking() {
+        let fixture = contract_fixture();
+        let sample = &fixture["chunk_sample"];
+        let payload = sample["payload_ascii"]
+            .as_str()
+            .expect("payload_ascii is string");
+        let message_id = sample["message_id"].as_u64().expect("message_id") as u32;
+        let max_frame = sample["max_frame"].as_u64().expect("max_frame") as usize;
+        let expected: Vec<Vec<u8>> = sample["frames_hex"]
+            .as_array()
+            .expect("frames_hex is array")
+            .iter()
+            .map(|hex| decode_hex(hex.as_str().expect("frame hex is string")))
+            .collect();
+
+        assert_eq!(
+            chunk::split_into_chunks(payload.as_bytes(), message_id, max_frame),
+            expected
+        );
+
+        let mut reassembler = Reassembler::new();
+        let now = Instant::now();
+        let reassembled = expected
+            .iter()
+            .filter_map(|frame| reassembler.push(frame, now).expect("fixture chunk"))
+            .next();
+        assert_eq!(reassembled.as_deref(), Some(payload.as_bytes()));
     }
 
     #[test]
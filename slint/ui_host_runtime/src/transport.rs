@@ -0,0 +1,255 @@
+#[cfg(unix)]
+use std::fs::File;
+use std::io::{self, Read, Write};
+#[cfg(unix)]
+use std::os::fd::{FromRawFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+/// Byte channel carrying the framed protocol between the host and Elixir.
+///
+/// `connect` hands out the read and write halves that `reader_loop` and
+/// `writer_loop` run on. Stdio is the default used by `run`.
+pub trait Transport: Send + 'static {
+    type Reader: Read + Send + 'static;
+    type Writer: Write + Send + 'static;
+
+    fn connect(&mut self) -> io::Result<(Self::Reader, Self::Writer)>;
+
+    /// Advertised as `capabilities.transport` in the `ready` envelope.
+    fn name(&self) -> &'static str;
+
+    /// Whether the runtime should call `connect` again after the connection drops.
+    fn reconnects(&self) -> bool {
+        false
+    }
+}
+
+/// Frames over the process stdin/stdout, as spawned by an OTP port.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdioTransport;
+
+impl Transport for StdioTransport {
+    type Reader = io::Stdin;
+    type Writer = io::Stdout;
+
+    fn connect(&mut self) -> io::Result<(Self::Reader, Self::Writer)> {
+        Ok((io::stdin(), io::stdout()))
+    }
+
+    fn name(&self) -> &'static str {
+        "stdio-packet-4"
+    }
+}
+
+/// Frames over an arbitrary reader/writer pair, e.g. in-memory pipes in tests.
+///
+/// The pair can only be handed out once; a second `connect` fails.
+#[derive(Debug)]
+pub struct StreamTransport<R, W> {
+    streams: Option<(R, W)>,
+}
+
+impl<R, W> StreamTransport<R, W>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            streams: Some((reader, writer)),
+        }
+    }
+}
+
+impl<R, W> Transport for StreamTransport<R, W>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+{
+    type Reader = R;
+    type Writer = W;
+
+    fn connect(&mut self) -> io::Result<(Self::Reader, Self::Writer)> {
+        self.streams.take().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotConnected,
+                "stream transport was already connected",
+            )
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "stream-packet-4"
+    }
+}
+
+/// Frames over a unix domain socket served by an Elixir-side listener.
+///
+/// Reconnects when the socket drops, so the host outlives BEAM restarts.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct UnixSocketTransport {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocketTransport {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixSocketTransport {
+    type Reader = UnixStream;
+    type Writer = UnixStream;
+
+    fn connect(&mut self) -> io::Result<(Self::Reader, Self::Writer)> {
+        let stream = UnixStream::connect(&self.path)?;
+        let reader = stream.try_clone()?;
+        Ok((reader, stream))
+    }
+
+    fn name(&self) -> &'static str {
+        "unix-packet-4"
+    }
+
+    fn reconnects(&self) -> bool {
+        true
+    }
+}
+
+/// Frames over inherited file descriptors, matching OTP's `:nouse_stdio`
+/// (the host reads fd 3 and writes fd 4), so stdout/stderr stay free for logs.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct FdTransport {
+    read_fd: RawFd,
+    write_fd: RawFd,
+    connected: bool,
+}
+
+#[cfg(unix)]
+impl FdTransport {
+    pub const OTP_READ_FD: RawFd = 3;
+    pub const OTP_WRITE_FD: RawFd = 4;
+
+    pub fn new(read_fd: RawFd, write_fd: RawFd) -> Self {
+        Self {
+            read_fd,
+            write_fd,
+            connected: false,
+        }
+    }
+
+    pub fn otp() -> Self {
+        Self::new(Self::OTP_READ_FD, Self::OTP_WRITE_FD)
+    }
+}
+
+#[cfg(unix)]
+impl Transport for FdTransport {
+    type Reader = File;
+    type Writer = File;
+
+    fn connect(&mut self) -> io::Result<(Self::Reader, Self::Writer)> {
+        if self.connected {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "fd transport was already connected",
+            ));
+        }
+
+        ensure_fd_open(self.read_fd)?;
+        ensure_fd_open(self.write_fd)?;
+        self.connected = true;
+
+        // SAFETY: both descriptors were checked to be open and are inherited from
+        // the parent solely for the protocol channel; nothing else owns them.
+        let (reader, writer) = unsafe {
+            (
+                File::from_raw_fd(self.read_fd),
+                File::from_raw_fd(self.write_fd),
+            )
+        };
+
+        Ok((reader, writer))
+    }
+
+    fn name(&self) -> &'static str {
+        "fd-packet-4"
+    }
+}
+
+#[cfg(unix)]
+fn ensure_fd_open(fd: RawFd) -> io::Result<()> {
+    std::fs::metadata(format!("/dev/fd/{fd}"))
+        .map(|_| ())
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("fd {fd} is not open for the protocol channel: {err}"),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn stream_transport_hands_out_streams_once() {
+        let mut transport = StreamTransport::new(Cursor::new(vec![1_u8, 2, 3]), Vec::<u8>::new());
+
+        let (mut reader, _writer) = transport.connect().expect("first connect");
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).expect("read in-memory stream");
+        assert_eq!(buf, vec![1, 2, 3]);
+
+        let err = transport.connect().expect_err("second connect fails");
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fd_transport_rejects_descriptors_that_are_not_open() {
+        let mut transport = FdTransport::new(9_999, 9_998);
+        let err = transport.connect().expect_err("unopened fds are rejected");
+        assert!(err.to_string().contains("fd 9999"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_transport_reconnects_to_listener() {
+        use std::os::unix::net::UnixListener;
+
+        let path =
+            std::env::temp_dir().join(format!("projection-transport-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).expect("bind listener");
+        let mut transport = UnixSocketTransport::new(&path);
+        assert!(transport.reconnects());
+
+        for round in 0_u8..2 {
+            let (_reader, mut writer) = transport.connect().expect("connect to listener");
+            let (mut accepted, _addr) = listener.accept().expect("accept host connection");
+
+            writer.write_all(&[round]).expect("write through socket");
+            let mut buf = [0_u8; 1];
+            accepted
+                .read_exact(&mut buf)
+                .expect("read on listener side");
+            assert_eq!(buf, [round]);
+        }
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
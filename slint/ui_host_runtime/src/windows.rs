@@ -0,0 +1,347 @@
+use log::warn;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use slint::ComponentHandle;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+/// View-model subtree declaring secondary windows, keyed by window id.
+pub const WINDOWS_PATH: &str = "/windows";
+
+/// Intent sent when the user asks to close a secondary window, with its `id`.
+pub const WINDOW_CLOSE_INTENT: &str = "ui.window.close";
+
+/// One entry under `/windows/<id>`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WindowDeclaration {
+    /// Selects the component, see `HostBindings::new_window`.
+    pub kind: String,
+    #[serde(default)]
+    pub title: String,
+    /// Blocks input to the session's main window while this one is open.
+    #[serde(default)]
+    pub modal: bool,
+    #[serde(default)]
+    pub vm: Value,
+}
+
+/// Receives the `(name, arg)` of an intent raised inside a window.
+pub type WindowIntentHandler = Box<dyn Fn(String, String)>;
+
+/// A top-level window or dialog opened for a declaration.
+///
+/// The server owns its lifetime: a close request only sends
+/// `ui.window.close`, and the window goes once its declaration is removed.
+pub trait ProjectionWindow {
+    fn apply(&self, declaration: &WindowDeclaration) -> Result<(), String>;
+    fn show(&self) -> Result<(), slint::PlatformError>;
+    fn hide(&self) -> Result<(), slint::PlatformError>;
+    fn on_close_requested(&self, handler: Box<dyn Fn()>);
+
+    /// Routes the window's own `UI.intent(name, arg)` calls to `handler`.
+    fn on_intent(&self, _handler: WindowIntentHandler) {}
+}
+
+/// Wraps a generated component, with the function that pushes a declaration
+/// into its properties.
+pub struct ComponentWindow<C: ComponentHandle> {
+    component: C,
+    apply: fn(&C, &WindowDeclaration) -> Result<(), String>,
+    bind_intents: Option<fn(&C, WindowIntentHandler)>,
+}
+
+impl<C: ComponentHandle> ComponentWindow<C> {
+    pub fn new(component: C, apply: fn(&C, &WindowDeclaration) -> Result<(), String>) -> Self {
+        Self {
+            component,
+            apply,
+            bind_intents: None,
+        }
+    }
+
+    /// Sets the function that binds the component's intent callback.
+    pub fn with_intents(mut self, bind_intents: fn(&C, WindowIntentHandler)) -> Self {
+        self.bind_intents = Some(bind_intents);
+        self
+    }
+}
+
+impl<C: ComponentHandle> ProjectionWindow for ComponentWindow<C> {
+    fn apply(&self, declaration: &WindowDeclaration) -> Result<(), String> {
+        (self.apply)(&self.component, declaration)
+    }
+
+    fn show(&self) -> Result<(), slint::PlatformError> {
+        self.component.show()
+    }
+
+    fn hide(&self) -> Result<(), slint::PlatformError> {
+        self.component.hide()
+    }
+
+    fn on_close_requested(&self, handler: Box<dyn Fn()>) {
+        self.component.window().on_close_requested(move || {
+            handler();
+            slint::CloseRequestResponse::KeepWindowShown
+        });
+    }
+
+    fn on_intent(&self, handler: WindowIntentHandler) {
+        if let Some(bind_intents) = self.bind_intents {
+            bind_intents(&self.component, handler);
+        }
+    }
+}
+
+struct OpenWindow {
+    source: Value,
+    kind: String,
+    modal: bool,
+    window: Box<dyn ProjectionWindow>,
+}
+
+/// The secondary windows of one session.
+#[derive(Default)]
+pub struct WindowSet {
+    open: BTreeMap<String, OpenWindow>,
+}
+
+impl WindowSet {
+    /// Opens, updates and closes windows to match the `/windows` subtree of
+    /// `vm`. Windows whose declaration did not change are left alone; a
+    /// changed `kind` replaces the window.
+    pub fn sync(
+        &mut self,
+        vm: &Value,
+        new_window: impl Fn(&str) -> Result<Box<dyn ProjectionWindow>, String>,
+        on_close: impl Fn(&str) -> Box<dyn Fn()>,
+        on_intent: impl Fn() -> WindowIntentHandler,
+    ) -> Result<(), String> {
+        let empty = Map::new();
+        let declared = match vm.pointer(WINDOWS_PATH) {
+            None | Some(Value::Null) => &empty,
+            Some(Value::Object(declared)) => declared,
+            Some(other) => return Err(format!("{WINDOWS_PATH} must be an object, got {other}")),
+        };
+
+        self.open.retain(|id, open| {
+            let keep = declared.contains_key(id);
+            if !keep {
+                hide(id, open);
+            }
+            keep
+        });
+
+        for (id, source) in declared {
+            if self.open.get(id).is_some_and(|open| open.source == *source) {
+                continue;
+            }
+
+            let declaration: WindowDeclaration = serde_json::from_value(source.clone())
+                .map_err(|err| format!("invalid declaration for window '{id}': {err}"))?;
+
+            match self.open.get_mut(id) {
+                Some(open) if open.kind == declaration.kind => {
+                    open.window
+                        .apply(&declaration)
+                        .map_err(|err| format!("window '{id}': {err}"))?;
+                    open.source = source.clone();
+                    open.modal = declaration.modal;
+                }
+                _ => {
+                    if let Some(open) = self.open.remove(id) {
+                        hide(id, &open);
+                    }
+
+                    let window = new_window(&declaration.kind)
+                        .map_err(|err| format!("cannot open window '{id}': {err}"))?;
+                    window.on_close_requested(on_close(id));
+                    window.on_intent(on_intent());
+                    window
+                        .apply(&declaration)
+                        .map_err(|err| format!("window '{id}': {err}"))?;
+                    window
+                        .show()
+                        .map_err(|err| format!("cannot show window '{id}': {err}"))?;
+
+                    self.open.insert(
+                        id.clone(),
+                        OpenWindow {
+                            source: source.clone(),
+                            kind: declaration.kind,
+                            modal: declaration.modal,
+                            window,
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether an open window was declared `modal`.
+    pub fn modal_open(&self) -> bool {
+        self.open.values().any(|open| open.modal)
+    }
+
+    pub fn close_all(&mut self) {
+        for (id, open) in std::mem::take(&mut self.open) {
+            hide(&id, &open);
+        }
+    }
+}
+
+fn hide(id: &str, open: &OpenWindow) {
+    if let Err(err) = open.window.hide() {
+        warn!("failed to hide window '{id}': {err}");
+    }
+}
+
+thread_local! {
+    // Components are not `Send`, so the UI thread keeps them, by session.
+    static SESSION_WINDOWS: RefCell<HashMap<String, WindowSet>> = RefCell::new(HashMap::new());
+}
+
+/// Runs `f` on the windows of `sid`. UI thread only.
+pub fn with_session_windows<R>(sid: &str, f: impl FnOnce(&mut WindowSet) -> R) -> R {
+    SESSION_WINDOWS.with(|sessions| f(sessions.borrow_mut().entry(sid.to_string()).or_default()))
+}
+
+pub fn close_all_windows() {
+    SESSION_WINDOWS.with(|sessions| {
+        for windows in sessions.borrow_mut().values_mut() {
+            windows.close_all();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::rc::Rc;
+
+    #[derive(Default)]
+    struct Events(RefCell<Vec<String>>);
+
+    impl Events {
+        fn push(&self, event: String) {
+            self.0.borrow_mut().push(event);
+        }
+
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut self.0.borrow_mut())
+        }
+    }
+
+    struct FakeWindow {
+        kind: String,
+        events: Rc<Events>,
+    }
+
+    impl ProjectionWindow for FakeWindow {
+        fn apply(&self, declaration: &WindowDeclaration) -> Result<(), String> {
+            self.events
+                .push(format!("apply {} {}", self.kind, declaration.title));
+            Ok(())
+        }
+
+        fn show(&self) -> Result<(), slint::PlatformError> {
+            self.events.push(format!("show {}", self.kind));
+            Ok(())
+        }
+
+        fn hide(&self) -> Result<(), slint::PlatformError> {
+            self.events.push(format!("hide {}", self.kind));
+            Ok(())
+        }
+
+        fn on_close_requested(&self, _handler: Box<dyn Fn()>) {}
+    }
+
+    fn sync(windows: &mut WindowSet, events: &Rc<Events>, vm: Value) -> Result<(), String> {
+        windows.sync(
+            &vm,
+            |kind| match kind {
+                "settings" | "confirm" => Ok(Box::new(FakeWindow {
+                    kind: kind.to_string(),
+                    events: events.clone(),
+                }) as Box<dyn ProjectionWindow>),
+                other => Err(format!("unknown kind '{other}'")),
+            },
+            |_id| Box::new(|| {}),
+            || Box::new(|_name, _arg| {}),
+        )
+    }
+
+    #[test]
+    fn windows_follow_their_declarations() {
+        let events = Rc::new(Events::default());
+        let mut windows = WindowSet::default();
+
+        let settings = json!({ "kind": "settings", "title": "Settings" });
+        sync(
+            &mut windows,
+            &events,
+            json!({ "windows": { "prefs": settings } }),
+        )
+        .expect("open");
+        assert_eq!(
+            events.take(),
+            vec!["apply settings Settings", "show settings"]
+        );
+
+        sync(
+            &mut windows,
+            &events,
+            json!({ "windows": { "prefs": settings } }),
+        )
+        .expect("same");
+        assert!(events.take().is_empty());
+
+        let renamed = json!({ "kind": "settings", "title": "Preferences" });
+        sync(
+            &mut windows,
+            &events,
+            json!({ "windows": { "prefs": renamed } }),
+        )
+        .expect("update");
+        assert_eq!(events.take(), vec!["apply settings Preferences"]);
+        assert!(!windows.modal_open());
+
+        let dialog = json!({ "kind": "confirm", "title": "Sure?", "modal": true });
+        sync(
+            &mut windows,
+            &events,
+            json!({ "windows": { "prefs": dialog } }),
+        )
+        .expect("replace");
+        assert_eq!(
+            events.take(),
+            vec!["hide settings", "apply confirm Sure?", "show confirm"]
+        );
+        assert!(windows.modal_open());
+
+        sync(&mut windows, &events, json!({})).expect("close");
+        assert_eq!(events.take(), vec!["hide confirm"]);
+        assert!(!windows.modal_open());
+    }
+
+    #[test]
+    fn bad_declarations_are_reported() {
+        let events = Rc::new(Events::default());
+        let mut windows = WindowSet::default();
+
+        let err = sync(&mut windows, &events, json!({ "windows": [] })).expect_err("array");
+        assert!(err.contains("must be an object"));
+
+        let err = sync(
+            &mut windows,
+            &events,
+            json!({ "windows": { "about": { "kind": "about" } } }),
+        )
+        .expect_err("unknown kind");
+        assert!(err.contains("cannot open window 'about'"));
+    }
+}
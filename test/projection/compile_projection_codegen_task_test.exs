@@ -94,7 +94,7 @@ defmodule Projection.CompileProjectionCodegenTaskTest do
     end
   end
 
-  test "projection.codegen emits typed list bindings from schema items option" do
+  test "projection.codegen emits typed list bindings that follow element-level patch ops" do
     module_name = :"TypedListScreen#{System.unique_integer([:positive])}"
     module = Module.concat([Projection, module_name])
 
@@ -107,6 +107,7 @@ defmodule Projection.CompileProjectionCodegenTaskTest do
         field :ratios, :list, items: :float, default: [1.0, 0.5]
         field :flags, :list, items: :bool, default: [true, false]
         field :labels, :list, default: ["a", "b"]
+        field :title, :string, default: "Tiles"
       end
 
       @impl true
@@ -183,6 +184,13 @@ defmodule Projection.CompileProjectionCodegenTaskTest do
 
     assert screen_rs =~ "collect::<Result<Vec<i32>, String>>()?"
     assert screen_rs =~ "let model = slint::VecModel::from(parsed);"
+
+    # `add /tiles/1`, `move`, `copy` and `remove /tiles/0` re-set the whole list.
+    assert screen_rs =~ ~s(if field_path.starts_with(&["tiles"]\) {)
+    assert screen_rs =~ "set_tiles_from_vm(&g, screen_vm)?;"
+    refute screen_rs =~ ~s(field_path == ["tiles"])
+    assert screen_rs =~ ~s(if field_path == ["title"] {)
+    assert screen_rs =~ ~s(let screen_vm = vm.pointer("/screen/vm"\))
   end
 
   test "projection.codegen maps aliased route names to the referenced screen id" do
@@ -234,6 +242,106 @@ defmodule Projection.CompileProjectionCodegenTaskTest do
     refute mod_rs =~ "Some(\"devices\") => ScreenId::Devices"
   end
 
+  test "projection.codegen generates a window host and apply function per window kind" do
+    namespace = Module.concat([Projection, :"Windows#{System.unique_integer([:positive])}"])
+    module = Module.concat([namespace, Confirm])
+
+    source = """
+    defmodule #{inspect(module)} do
+      use ProjectionUI, :window
+
+      schema do
+        field :message, :string, default: "Discard?"
+        field :destructive, :bool
+      end
+    end
+    """
+
+    Code.compile_string(source)
+
+    original_router_module = Application.get_env(:projection, :router_module)
+    original_screen_modules = Application.get_env(:projection, :screen_modules)
+    original_window_modules = Application.get_env(:projection, :window_modules)
+    Application.delete_env(:projection, :router_module)
+    Application.put_env(:projection, :screen_modules, [Projection.TestScreens.Clock])
+    Application.put_env(:projection, :window_modules, [module])
+
+    on_exit(fn ->
+      if original_router_module do
+        Application.put_env(:projection, :router_module, original_router_module)
+      else
+        Application.delete_env(:projection, :router_module)
+      end
+
+      if is_nil(original_screen_modules) do
+        Application.delete_env(:projection, :screen_modules)
+      else
+        Application.put_env(:projection, :screen_modules, original_screen_modules)
+      end
+
+      if is_nil(original_window_modules) do
+        Application.delete_env(:projection, :window_modules)
+      else
+        Application.put_env(:projection, :window_modules, original_window_modules)
+      end
+
+      Mix.Task.reenable("projection.codegen")
+
+      capture_io(fn ->
+        previous_allow_empty = System.get_env("PROJECTION_ALLOW_EMPTY")
+        System.put_env("PROJECTION_ALLOW_EMPTY", "1")
+
+        try do
+          Mix.Tasks.Projection.Codegen.run([])
+        after
+          if is_nil(previous_allow_empty) do
+            System.delete_env("PROJECTION_ALLOW_EMPTY")
+          else
+            System.put_env("PROJECTION_ALLOW_EMPTY", previous_allow_empty)
+          end
+        end
+      end)
+    end)
+
+    Mix.Task.reenable("projection.codegen")
+
+    capture_io(fn ->
+      Mix.Tasks.Projection.Codegen.run([])
+    end)
+
+    window_rs = File.read!("slint/ui_host/src/generated/confirm_window.rs")
+
+    assert window_rs =~
+             "pub fn apply(window: &ConfirmWindowHost, declaration: &WindowDeclaration)"
+
+    assert window_rs =~ "window.set_window_title(declaration.title.as_str().into());"
+    assert window_rs =~ "set_message_from_vm(&g, window_vm)?;"
+    assert window_rs =~ "set_destructive_from_vm(&g, window_vm)?;"
+    assert window_rs =~ ~s(set_message_from_value(g, "vm/message", value\))
+    assert window_rs =~
+             "pub fn bind_intents(window: &ConfirmWindowHost, handler: WindowIntentHandler)"
+
+    host_slint = File.read!("slint/ui_host/src/generated/confirm_window.slint")
+    assert host_slint =~ "export component ConfirmWindowHost inherits Window {"
+    assert host_slint =~ ~r{import \{ ConfirmWindow \} from ".*/windows/confirm\.slint";}
+    assert host_slint =~ "message: ConfirmWindowState.message;"
+
+    state_slint = File.read!("slint/ui_host/src/generated/confirm_window_state.slint")
+    assert state_slint =~ "export global ConfirmWindowState {"
+    assert state_slint =~ "in property <string> message: \"Discard?\";"
+
+    mod_rs = File.read!("slint/ui_host/src/generated/mod.rs")
+    assert mod_rs =~ "pub mod confirm_window;"
+    assert mod_rs =~ ~s("confirm" => {)
+    assert mod_rs =~ "ComponentWindow::new(window, confirm_window::apply)"
+
+    app_slint = File.read!("slint/ui_host/src/generated/app.slint")
+    assert app_slint =~ ~s(export { ConfirmWindowHost } from "confirm_window.slint";)
+    assert app_slint =~ ~s(export { ConfirmWindowState } from "confirm_window_state.slint";)
+    assert app_slint =~ "in property <bool> modal_open: false;"
+    assert app_slint =~ "if root.modal_open: Rectangle {"
+  end
+
   defp ensure_required_ui_shell_files! do
     ui_root = Path.join(File.cwd!(), configured_ui_root())
     File.mkdir_p!(ui_root)
@@ -8,14 +8,19 @@ defmodule Projection.HostBridgeTest do
   defmodule SessionStub do
     use GenServer
 
-    def start_link(test_pid) do
-      GenServer.start_link(__MODULE__, test_pid)
+    def start_link(target) do
+      GenServer.start_link(__MODULE__, target)
     end
 
     @impl true
-    def init(test_pid), do: {:ok, test_pid}
+    def init(target), do: {:ok, target}
 
     @impl true
+    def handle_cast({:ui_envelope, envelope}, {test_pid, label} = target) when is_map(envelope) do
+      send(test_pid, {:session_envelope, label, envelope})
+      {:noreply, target}
+    end
+
     def handle_cast({:ui_envelope, envelope}, test_pid) when is_map(envelope) do
       send(test_pid, {:session_envelope, envelope})
       {:noreply, test_pid}
@@ -53,6 +58,42 @@ defmodule Projection.HostBridgeTest do
                    1_000
   end
 
+  test "routes envelopes by sid when bridging several sessions" do
+    {:ok, left} = start_supervised({SessionStub, {self(), :left}}, id: :left)
+    {:ok, right} = start_supervised({SessionStub, {self(), :right}}, id: :right)
+
+    {:ok, owner} =
+      start_supervised(
+        {HostBridge,
+         [
+           sessions: %{"left" => left, "right" => right},
+           sid: "left",
+           command: "/bin/cat"
+         ]}
+      )
+
+    port = wait_for_port!(owner)
+
+    intent = %{"t" => "intent", "sid" => "right", "id" => 1, "name" => "noop"}
+    assert true == Port.command(port, Jason.encode!(intent))
+    assert_receive {:session_envelope, :right, %{"t" => "intent", "sid" => "right"}}, 1_000
+    refute_receive {:session_envelope, :left, _}, 100
+
+    log =
+      capture_log(fn ->
+        stray = %{"t" => "intent", "sid" => "other", "id" => 2, "name" => "noop"}
+        assert true == Port.command(port, Jason.encode!(stray))
+        refute_receive {:session_envelope, _, _}, 200
+      end)
+
+    assert log =~ "unknown sid \"other\""
+
+    # A decode failure resyncs every session under its own sid.
+    assert true == Port.command(port, "{")
+    assert_receive {:session_envelope, :left, %{"t" => "ready", "sid" => "left"}}, 1_000
+    assert_receive {:session_envelope, :right, %{"t" => "ready", "sid" => "right"}}, 1_000
+  end
+
   test "oversized inbound frame emits frame_too_large error envelope and resync" do
     {:ok, session} = start_supervised({SessionStub, self()})
 
@@ -110,6 +151,278 @@ defmodule Projection.HostBridgeTest do
     refute_receive {:session_envelope, _}, 100
   end
 
+  test "ready with a transport other than the one the bridge opened is reported" do
+    {:ok, session} = start_supervised({SessionStub, self()})
+
+    {:ok, owner} =
+      start_supervised(
+        {HostBridge,
+         [
+           session: session,
+           sid: "S5",
+           command: "/bin/cat"
+         ]}
+      )
+
+    port = wait_for_port!(owner)
+
+    ready =
+      Jason.encode!(%{
+        "t" => "ready",
+        "sid" => "S5",
+        "capabilities" => %{"transport" => "fd-packet-4"}
+      })
+
+    log =
+      capture_log(fn ->
+        assert true == Port.command(port, ready)
+        assert_receive {:session_envelope, %{"t" => "ready", "sid" => "S5"}}, 1_000
+      end)
+
+    assert log =~ "ui_host transport mismatch: expected stdio-packet-4, host reported fd-packet-4"
+  end
+
+  test "socket mode forwards frames from a connected host and replies on the socket" do
+    {:ok, session} = start_supervised({SessionStub, self()})
+
+    socket_path =
+      Path.join(
+        System.tmp_dir!(),
+        "projection_host_bridge_#{System.unique_integer([:positive])}.sock"
+      )
+
+    {:ok, owner} =
+      start_supervised(
+        {HostBridge,
+         [
+           session: session,
+           sid: "S4",
+           socket_path: socket_path
+         ]}
+      )
+
+    {:ok, socket} =
+      :gen_tcp.connect({:local, String.to_charlist(socket_path)}, 0, [
+        :binary,
+        packet: 4,
+        active: false
+      ])
+
+    ready = Jason.encode!(%{"t" => "ready", "sid" => "S4", "capabilities" => %{}})
+    assert :ok == :gen_tcp.send(socket, ready)
+    assert_receive {:session_envelope, %{"t" => "ready", "sid" => "S4"}}, 1_000
+
+    HostBridge.send_envelope(owner, Projection.Protocol.render_envelope("S4", 1, %{}))
+    assert {:ok, payload} = :gen_tcp.recv(socket, 0, 1_000)
+    assert %{"t" => "render", "sid" => "S4", "rev" => 1} = Jason.decode!(payload)
+
+    # A reconnecting host is accepted on the same listener.
+    :ok = :gen_tcp.close(socket)
+
+    {:ok, socket} =
+      :gen_tcp.connect({:local, String.to_charlist(socket_path)}, 0, [
+        :binary,
+        packet: 4,
+        active: false
+      ])
+
+    assert :ok == :gen_tcp.send(socket, ready)
+    assert_receive {:session_envelope, %{"t" => "ready", "sid" => "S4"}}, 1_000
+  end
+
+  test "answers a versioned ready with an accept envelope" do
+    {:ok, session} = start_supervised({SessionStub, self()})
+
+    socket_path =
+      Path.join(
+        System.tmp_dir!(),
+        "projection_host_bridge_#{System.unique_integer([:positive])}.sock"
+      )
+
+    {:ok, _owner} =
+      start_supervised(
+        {HostBridge,
+         [
+           session: session,
+           sid: "S6",
+           socket_path: socket_path
+         ]}
+      )
+
+    {:ok, socket} =
+      :gen_tcp.connect({:local, String.to_charlist(socket_path)}, 0, [
+        :binary,
+        packet: 4,
+        active: false
+      ])
+
+    ready =
+      Jason.encode!(%{
+        "t" => "ready",
+        "sid" => "S6",
+        "capabilities" => %{
+          "protocol_version" => Projection.Protocol.protocol_version(),
+          "transport" => "unix-packet-4",
+          "compression" => ["zlib"]
+        }
+      })
+
+    assert :ok == :gen_tcp.send(socket, ready)
+    assert {:ok, payload} = :gen_tcp.recv(socket, 0, 1_000)
+
+    assert %{"t" => "accept", "sid" => "S6", "compression" => "zlib"} = Jason.decode!(payload)
+    assert_receive {:session_envelope, %{"t" => "ready", "sid" => "S6"}}, 1_000
+
+    # Each ready is accepted under its own sid, whatever sid the bridge started with.
+    second_ready =
+      Jason.encode!(%{
+        "t" => "ready",
+        "sid" => "S6b",
+        "capabilities" => %{"protocol_version" => Projection.Protocol.protocol_version()}
+      })
+
+    assert :ok == :gen_tcp.send(socket, second_ready)
+    assert {:ok, payload} = :gen_tcp.recv(socket, 0, 1_000)
+    assert %{"t" => "accept", "sid" => "S6b"} = Jason.decode!(payload)
+    assert_receive {:session_envelope, %{"t" => "ready", "sid" => "S6b"}}, 1_000
+  end
+
+  test "answers host pings with a pong without involving the session" do
+    {:ok, session} = start_supervised({SessionStub, self()})
+
+    socket_path =
+      Path.join(
+        System.tmp_dir!(),
+        "projection_host_bridge_#{System.unique_integer([:positive])}.sock"
+      )
+
+    {:ok, _owner} =
+      start_supervised(
+        {HostBridge,
+         [
+           session: session,
+           sid: "S8",
+           socket_path: socket_path
+         ]}
+      )
+
+    {:ok, socket} =
+      :gen_tcp.connect({:local, String.to_charlist(socket_path)}, 0, [
+        :binary,
+        packet: 4,
+        active: false
+      ])
+
+    ping = Jason.encode!(%{"t" => "ping", "sid" => "S8", "nonce" => 42})
+
+    assert :ok == :gen_tcp.send(socket, ping)
+    assert {:ok, payload} = :gen_tcp.recv(socket, 0, 1_000)
+
+    assert %{"t" => "pong", "sid" => "S8", "nonce" => 42} == Jason.decode!(payload)
+    refute_receive {:session_envelope, _}, 100
+  end
+
+  test "host diagnostics are logged and emitted as telemetry" do
+    test_pid = self()
+    handler_id = "projection-host-bridge-diagnostic-#{System.unique_integer([:positive])}"
+
+    :ok =
+      :telemetry.attach(
+        handler_id,
+        [:projection, :host_bridge, :diagnostic],
+        fn event, measurements, metadata, _config ->
+          send(test_pid, {:telemetry, event, measurements, metadata})
+        end,
+        nil
+      )
+
+    on_exit(fn -> :telemetry.detach(handler_id) end)
+
+    {:ok, session} = start_supervised({SessionStub, self()})
+
+    {:ok, owner} =
+      start_supervised(
+        {HostBridge,
+         [
+           session: session,
+           sid: "S7",
+           command: "/bin/cat"
+         ]}
+      )
+
+    port = wait_for_port!(owner)
+
+    diagnostic =
+      Jason.encode!(%{
+        "t" => "diagnostic",
+        "sid" => "S7",
+        "code" => "patch_apply_failed",
+        "message" => "replace path does not exist: /clock_text",
+        "rev" => 2,
+        "path" => "/clock_text"
+      })
+
+    log =
+      capture_log(fn ->
+        assert true == Port.command(port, diagnostic)
+
+        assert_receive {:telemetry, [:projection, :host_bridge, :diagnostic], %{count: 1},
+                        metadata},
+                       1_000
+
+        assert metadata.code == "patch_apply_failed"
+        assert metadata.rev == 2
+        assert metadata.path == "/clock_text"
+      end)
+
+    assert log =~ "ui_host diagnostic patch_apply_failed"
+  end
+
+  test "host log records are written to Logger without reaching the session" do
+    {:ok, session} = start_supervised({SessionStub, self()})
+
+    {:ok, owner} =
+      start_supervised(
+        {HostBridge,
+         [
+           session: session,
+           sid: "S9",
+           command: "/bin/cat"
+         ]}
+      )
+
+    port = wait_for_port!(owner)
+
+    record =
+      Jason.encode!(%{
+        "t" => "log",
+        "sid" => "S9",
+        "level" => "warn",
+        "target" => "projection_ui_host_runtime",
+        "message" => "ui intent queue full (cap=256); dropped 1 intent(s)",
+        "fields" => %{"dropped" => 1}
+      })
+
+    connection_record =
+      Jason.encode!(%{
+        "t" => "log",
+        "level" => "warn",
+        "target" => "projection_ui_host_runtime",
+        "message" => "transport closed; reconnecting"
+      })
+
+    log =
+      capture_log([metadata: [:sid]], fn ->
+        assert true == Port.command(port, record)
+        assert true == Port.command(port, connection_record)
+        refute_receive {:session_envelope, _}, 200
+      end)
+
+    assert log =~ ~r/sid=S9 \[warning\] ui_host [^:]+: ui intent queue full/
+    assert log =~ ~r/\[warning\] ui_host [^:]+: transport closed/
+    refute log =~ ~r/sid=S9 \[warning\] ui_host [^:]+: transport closed/
+  end
+
   defp wait_for_port!(owner, attempts \\ 40)
 
   defp wait_for_port!(owner, attempts) when attempts > 0 do
@@ -19,4 +19,16 @@ defmodule Projection.PatchTest do
       Patch.replace("/screen/~2", "bad")
     end
   end
+
+  test "move, copy and test builders carry their RFC6902 fields" do
+    assert Patch.move("/items/0", "/items/-") ==
+             %{"op" => "move", "from" => "/items/0", "path" => "/items/-"}
+
+    assert Patch.copy("/a", "/b") == %{"op" => "copy", "from" => "/a", "path" => "/b"}
+    assert Patch.test("/count", 3) == %{"op" => "test", "path" => "/count", "value" => 3}
+
+    assert_raise ArgumentError, ~r/invalid JSON pointer path/, fn ->
+      Patch.move("items/0", "/items/1")
+    end
+  end
 end
@@ -3,6 +3,7 @@ defmodule Projection.ProtocolTest do
 
   import ExUnit.CaptureLog
 
+  alias Projection.MsgPack
   alias Projection.Protocol
 
   @contract_path Path.expand("../../priv/protocol_contract/contract.json", __DIR__)
@@ -40,6 +41,130 @@ defmodule Projection.ProtocolTest do
     assert is_binary(encoded)
   end
 
+  test "compresses large outbound payloads only when asked" do
+    envelope =
+      Protocol.render_envelope("S1", 1, %{rows: String.duplicate("device-row ", 4_000)})
+
+    assert {:ok, plain} = Protocol.encode_outbound(envelope)
+    assert {:ok, compressed} = Protocol.encode_outbound(envelope, compress: true)
+    assert byte_size(compressed) < byte_size(plain)
+
+    marker = load_contract_fixture()["compression"]["zlib_marker"]
+    assert <<^marker, deflated::binary>> = compressed
+    assert :zlib.uncompress(deflated) == plain
+
+    small = Protocol.render_envelope("S1", 1, %{clock_text: "10:42:17"})
+    assert {:ok, small_payload} = Protocol.encode_outbound(small, compress: true)
+    assert {:ok, _decoded} = Jason.decode(small_payload)
+  end
+
+  test "splits payloads over the outbound cap when the host reassembles chunks" do
+    rows = String.duplicate("device-row ", div(Protocol.elixir_to_ui_cap(), 8))
+    envelope = Protocol.render_envelope("S1", 1, %{rows: rows})
+
+    assert {:error, :frame_too_large} = Protocol.encode_outbound_frames(envelope)
+
+    capture_log(fn ->
+      assert {:ok, frames} =
+               Protocol.encode_outbound_frames(envelope, chunk_limit: 16 * 1_048_576)
+
+      assert length(frames) == 2
+      assert Enum.all?(frames, &(byte_size(&1) <= Protocol.elixir_to_ui_cap()))
+
+      payload =
+        frames
+        |> Enum.map(fn <<0xC1, _id::32, _index::32, 2::32, data::binary>> -> data end)
+        |> IO.iodata_to_binary()
+
+      assert {:ok, %{"vm" => %{"rows" => ^rows}}} = Jason.decode(payload)
+    end)
+  end
+
+  test "chunk framing matches the contract fixture" do
+    sample = load_contract_fixture()["chunk_sample"]
+
+    frames =
+      Protocol.split_chunks(sample["payload_ascii"], sample["message_id"], sample["max_frame"])
+
+    assert Enum.map(frames, &Base.encode16(&1, case: :lower)) == sample["frames_hex"]
+    assert Protocol.chunk_limit(load_contract_fixture()["ui_ready"]) == 16_777_216
+  end
+
+  test "accept envelope matches the contract fixture" do
+    fixture = load_contract_fixture()
+
+    assert Protocol.protocol_version() == fixture["protocol_version"]
+    assert Protocol.host_protocol_version(fixture["ui_ready"]) == fixture["protocol_version"]
+    assert Protocol.accept_envelope("S1", fixture["ui_ready"]) == fixture["elixir_accept"]
+    assert Protocol.host_protocol_version(%{"t" => "ready", "sid" => "S1"}) == nil
+  end
+
+  test "accept envelope declares intent coalescing policies" do
+    accept =
+      Protocol.accept_envelope("S1", %{"t" => "ready", "sid" => "S1"},
+        intent_policies: %{
+          "volume.changed" => {:throttle, 50},
+          "search.changed" => {:debounce, 300},
+          "list.scrolled" => :latest_wins
+        }
+      )
+
+    assert accept["intent_policies"] == %{
+             "volume.changed" => %{"mode" => "throttle", "interval_ms" => 50},
+             "search.changed" => %{"mode" => "debounce", "interval_ms" => 300},
+             "list.scrolled" => %{"mode" => "latest_wins"}
+           }
+
+    refute Map.has_key?(Protocol.accept_envelope("S1", %{"t" => "ready"}), "intent_policies")
+
+    assert_raise ArgumentError, fn ->
+      Protocol.accept_envelope("S1", %{"t" => "ready"}, intent_policies: %{"a" => :sometimes})
+    end
+  end
+
+  test "picks msgpack only when the host lists it" do
+    assert Protocol.codec(load_contract_fixture()["ui_ready"]) == "msgpack"
+    assert Protocol.codec(%{"t" => "ready", "capabilities" => %{"codecs" => ["json"]}}) == "json"
+    assert Protocol.codec(%{"t" => "ready", "sid" => "S1"}) == "json"
+  end
+
+  test "msgpack samples in the contract fixture decode to their json counterparts" do
+    fixture = load_contract_fixture()
+
+    for {key, hex} <- fixture["msgpack_samples"] do
+      payload = Base.decode16!(hex, case: :lower)
+      assert MsgPack.decode(payload) == {:ok, fixture[key]}
+    end
+
+    intent = Base.decode16!(fixture["msgpack_samples"]["ui_intent"], case: :lower)
+    assert Protocol.decode_inbound(intent) == {:ok, fixture["ui_intent"]}
+  end
+
+  test "encodes outbound envelopes as msgpack when negotiated" do
+    vm = %{
+      rows: [0, 127, 128, 65_536, 4_294_967_296, -1, -33, -129, -70_000],
+      label: String.duplicate("device-row ", 40),
+      ratio: 1.5,
+      enabled: true,
+      note: nil
+    }
+
+    envelope = Protocol.render_envelope("S1", 1, vm)
+
+    assert {:ok, payload} = Protocol.encode_outbound(envelope, codec: "msgpack")
+    assert <<0x84, _rest::binary>> = payload
+    assert {:ok, decoded} = MsgPack.decode(payload)
+    assert decoded == Jason.decode!(Jason.encode!(envelope))
+
+    assert {:error, :decode_error} = MsgPack.decode(binary_part(payload, 0, 10))
+    assert {:error, :encode_error} = MsgPack.encode(%{"pid" => self()})
+  end
+
+  test "reads compression support from ready capabilities" do
+    assert Protocol.compression_supported?(load_contract_fixture()["ui_ready"])
+    refute Protocol.compression_supported?(%{"t" => "ready", "sid" => "S1"})
+  end
+
   test "rejects malformed inbound payload" do
     assert {:error, :decode_error} = Protocol.decode_inbound("{")
   end
@@ -87,6 +212,10 @@ defmodule Projection.ProtocolTest do
     assert {:ok, render_json} = Protocol.encode_outbound(render)
     assert Jason.decode!(render_json) == fixture["elixir_render"]
 
+    render_ack = Protocol.render_envelope("S1", 3, %{"clock_text" => "10:42:19"}, ack: 8)
+    assert {:ok, render_ack_json} = Protocol.encode_outbound(render_ack)
+    assert Jason.decode!(render_ack_json) == fixture["elixir_render_ack"]
+
     patch =
       Protocol.patch_envelope(
         "S1",
@@ -104,6 +233,20 @@ defmodule Projection.ProtocolTest do
     assert {:ok, error_json} = Protocol.encode_outbound(error)
     assert Jason.decode!(error_json) == fixture["elixir_error"]
 
+    patch_resume =
+      Protocol.patch_envelope(
+        "S1",
+        5,
+        [
+          %{"op" => "replace", "path" => "/screen/vm/clock_text", "value" => "10:42:19"}
+        ],
+        resume_from: 3,
+        ack: 8
+      )
+
+    assert {:ok, patch_resume_json} = Protocol.encode_outbound(patch_resume)
+    assert Jason.decode!(patch_resume_json) == fixture["elixir_patch_resume"]
+
     frame_hex = fixture["frame_sample"]["frame_hex"]
     payload_ascii = fixture["frame_sample"]["payload_ascii"]
     frame = Base.decode16!(String.upcase(frame_hex))
@@ -111,4 +254,34 @@ defmodule Projection.ProtocolTest do
     assert len == byte_size(payload_ascii)
     assert payload == payload_ascii
   end
+
+  test "vm hash matches the contract fixture whatever the key types" do
+    sample = load_contract_fixture()["vm_hash_sample"]
+    assert Protocol.vm_hash(sample["vm"]) == sample["hash"]
+
+    atom_keyed = %{
+      screen: %{name: "clock", vm: %{clock_text: "10:42:17", clock_running: true, label: nil}},
+      app: %{title: "Projection"},
+      items: [1, 2.5, ~s(a"b)]
+    }
+
+    assert Protocol.vm_hash(atom_keyed) == sample["hash"]
+  end
+
+  test "vm hash writes floats the way the host does" do
+    sample = load_contract_fixture()["vm_hash_float_sample"]
+    assert Protocol.vm_hash(sample["vm"]) == sample["hash"]
+
+    readings = %{
+      large: 1.0e20,
+      small: 1.5e-7,
+      whole: 1.0,
+      negative: -0.25,
+      decimal: 100_000.0,
+      tenth: 0.1,
+      count: 3
+    }
+
+    assert Protocol.vm_hash(%{readings: readings}) == sample["hash"]
+  end
 end
@@ -1,6 +1,7 @@
 defmodule Projection.SessionBatchingTest do
   use ExUnit.Case, async: true
 
+  alias Projection.Protocol
   alias Projection.Session
 
   test "coalesces duplicate paths into one patch and keeps latest ack" do
@@ -132,4 +133,183 @@ defmodule Projection.SessionBatchingTest do
 
     refute_receive {:"$gen_cast", {:send_envelope, _}}, 250
   end
+
+  test "render after a resync acknowledges intents whose patch was still batched" do
+    {:ok, session} =
+      start_supervised(
+        {Session,
+         [
+           sid: "S1",
+           screen_module: Projection.TestScreens.Clock,
+           host_bridge: self(),
+           batch_window_ms: 250,
+           max_pending_ops: 64
+         ]}
+      )
+
+    assert {:ok, [render_1]} =
+             Session.handle_ui_envelope_sync(session, %{"t" => "ready", "sid" => "S1"})
+
+    refute Map.has_key?(render_1, "ack")
+
+    assert {:ok, []} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "intent",
+               "sid" => "S1",
+               "id" => 41,
+               "name" => "clock.pause",
+               "payload" => %{}
+             })
+
+    assert {:ok, [render_2]} =
+             Session.handle_ui_envelope_sync(session, %{"t" => "ready", "sid" => "S1"})
+
+    assert render_2["ack"] == 41
+    assert render_2["vm"][:clock_running] == false
+    refute_receive {:"$gen_cast", {:send_envelope, _}}, 300
+  end
+
+  test "ready from a new host instance drops the ack of the previous one" do
+    {:ok, session} =
+      start_supervised(
+        {Session,
+         [sid: "S1", screen_module: Projection.TestScreens.Clock, host_bridge: self()]}
+      )
+
+    ready = %{"t" => "ready", "sid" => "S1", "instance" => "a-1"}
+    assert {:ok, [_render]} = Session.handle_ui_envelope_sync(session, ready)
+
+    assert {:ok, []} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "intent",
+               "sid" => "S1",
+               "id" => 12,
+               "name" => "clock.pause",
+               "payload" => %{}
+             })
+
+    assert {:ok, [same_host]} = Session.handle_ui_envelope_sync(session, ready)
+    assert same_host["ack"] == 12
+
+    assert {:ok, [without_instance]} =
+             Session.handle_ui_envelope_sync(session, %{"t" => "ready", "sid" => "S1"})
+
+    assert without_instance["ack"] == 12
+
+    assert {:ok, [restarted]} =
+             Session.handle_ui_envelope_sync(session, %{ready | "instance" => "b-2"})
+
+    refute Map.has_key?(restarted, "ack")
+  end
+
+  test "ready naming a recent revision is answered with a patch from it" do
+    {:ok, session} =
+      start_supervised(
+        {Session,
+         [
+           sid: "S1",
+           screen_module: Projection.TestScreens.Clock,
+           host_bridge: self(),
+           batch_window_ms: 0
+         ]}
+      )
+
+    assert {:ok, [render]} =
+             Session.handle_ui_envelope_sync(session, %{"t" => "ready", "sid" => "S1"})
+
+    assert {:ok, []} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "intent",
+               "sid" => "S1",
+               "id" => 51,
+               "name" => "clock.pause",
+               "payload" => %{}
+             })
+
+    assert_receive {:"$gen_cast", {:send_envelope, %{"t" => "patch", "rev" => 2}}}, 300
+
+    resume = %{"rev" => 1, "vm_hash" => Protocol.vm_hash(render["vm"])}
+
+    assert {:ok, [patch]} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "ready",
+               "sid" => "S1",
+               "resume" => resume
+             })
+
+    assert patch["t"] == "patch"
+    assert patch["rev"] == 3
+    assert patch["resume_from"] == 1
+    assert patch["ack"] == 51
+    assert %{"op" => "replace", "path" => "/clock_running", "value" => false} in patch["ops"]
+
+    stale = %{"rev" => 1, "vm_hash" => "0000000000000000"}
+
+    assert {:ok, [%{"t" => "render", "rev" => 4}]} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "ready",
+               "sid" => "S1",
+               "resume" => stale
+             })
+  end
+
+  test "holds patches while the host lags behind on applied revisions" do
+    {:ok, session} =
+      start_supervised(
+        {Session,
+         [
+           sid: "S1",
+           screen_module: Projection.TestScreens.Clock,
+           host_bridge: self(),
+           batch_window_ms: 0,
+           max_unapplied_revs: 1
+         ]}
+      )
+
+    ready = %{"t" => "ready", "sid" => "S1", "capabilities" => %{"features" => ["applied"]}}
+    assert {:ok, [_render]} = Session.handle_ui_envelope_sync(session, ready)
+
+    assert {:ok, []} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "applied",
+               "sid" => "S1",
+               "rev" => 1,
+               "applied_at_ms" => System.system_time(:millisecond)
+             })
+
+    assert {:ok, []} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "intent",
+               "sid" => "S1",
+               "id" => 301,
+               "name" => "clock.pause",
+               "payload" => %{}
+             })
+
+    assert_receive {:"$gen_cast", {:send_envelope, %{"t" => "patch", "rev" => 2}}}, 300
+
+    assert {:ok, []} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "intent",
+               "sid" => "S1",
+               "id" => 302,
+               "name" => "clock.resume",
+               "payload" => %{}
+             })
+
+    refute_receive {:"$gen_cast", {:send_envelope, _}}, 100
+    assert %{applied_rev: 1, rev: 2} = Session.snapshot(session)
+
+    assert {:ok, []} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "applied",
+               "sid" => "S1",
+               "rev" => 2,
+               "applied_at_ms" => System.system_time(:millisecond)
+             })
+
+    assert_receive {:"$gen_cast", {:send_envelope, patch}}, 300
+    assert patch["rev"] == 3
+    assert patch["ack"] == 302
+  end
 end
@@ -25,6 +25,45 @@ defmodule Projection.SessionRouterTest do
     end
   end
 
+  defmodule Confirm do
+    use ProjectionUI, :window
+
+    schema do
+      field(:message, :string, default: "Unsaved changes will be lost.")
+    end
+  end
+
+  defmodule DialogScreen do
+    use ProjectionUI, :screen
+
+    schema do
+      field(:confirm_open, :bool, default: true)
+    end
+
+    @impl true
+    def handle_event("ui.window.close", %{"id" => "confirm"}, state) do
+      {:noreply, assign(state, :confirm_open, false)}
+    end
+
+    @impl true
+    def render(assigns) do
+      windows =
+        if assigns.confirm_open,
+          do: %{confirm: Confirm.declare(%{}, title: "Discard changes?", modal: true)},
+          else: %{}
+
+      %{confirm_open: assigns.confirm_open, windows: windows}
+    end
+  end
+
+  defmodule DialogRouter do
+    use Projection.Router.DSL
+
+    screen_session :main do
+      screen("/dialog", DialogScreen, :show, as: :dialog)
+    end
+  end
+
   test "routed mode renders nav and screen VM and supports navigate/back intents" do
     {:ok, session} =
       start_supervised(
@@ -308,4 +347,32 @@ defmodule Projection.SessionRouterTest do
     snapshot = Session.snapshot(session)
     assert snapshot.vm.screen.name == "clock"
   end
+
+  test "routed screens declare windows at the vm root and close them on request" do
+    {:ok, session} =
+      start_supervised(
+        {Session, [sid: "S1", router: DialogRouter, route: "dialog", host_bridge: self()]}
+      )
+
+    assert {:ok, [render]} =
+             Session.handle_ui_envelope_sync(session, %{"t" => "ready", "sid" => "S1"})
+
+    assert %{kind: "confirm", title: "Discard changes?", modal: true, vm: window_vm} =
+             render["vm"][:windows][:confirm]
+
+    assert window_vm == %{message: "Unsaved changes will be lost."}
+    refute Map.has_key?(render["vm"][:screen][:vm], :windows)
+
+    assert {:ok, []} =
+             Session.handle_ui_envelope_sync(session, %{
+               "t" => "intent",
+               "sid" => "S1",
+               "id" => 1,
+               "name" => "ui.window.close",
+               "payload" => %{"id" => "confirm"}
+             })
+
+    assert_receive {:"$gen_cast", {:send_envelope, patch}}, 200
+    assert %{"op" => "remove", "path" => "/windows/confirm"} in patch["ops"]
+  end
 end